@@ -21,4 +21,18 @@ pub trait Bus: Readable + Writable {
     fn toggle_interrupts(&mut self, value: bool);
     fn dma_transfer(&mut self, from: u16, to: u16, size: u16);
     fn master_interrupt_enable(&self) -> bool;
+
+    /// Starts timing the memory accesses of the CPU: from there, every read and write
+    /// takes an M-cycle, and those made while the PPU reads OAM can corrupt it
+    fn start_cpu_cycles(&mut self);
+    /// M-cycle where the CPU increments or decrements a 16-bit register holding `address`
+    /// without accessing memory, like `INC rr` or before pushing to the stack
+    fn increment_cycle(&self, address: u16);
+    /// M-cycle where the CPU doesn't access memory
+    fn idle_cycle(&self);
+    /// Reads `address` while incrementing or decrementing the register holding it,
+    /// like `LD A,(HL+)` or `POP`
+    fn read_increment(&self, address: u16) -> u8;
+    /// Stops timing the accesses of the CPU and applies the OAM corruptions they triggered
+    fn end_cpu_cycles(&mut self);
 }
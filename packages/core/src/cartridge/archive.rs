@@ -0,0 +1,396 @@
+use crate::cartridge::cartridge_error::CartridgeError;
+use crate::util::crc32::crc32;
+use crate::util::inflate::inflate;
+use std::error::Error;
+use std::ffi::OsStr;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const ZIP_END_SIGNATURE: u32 = 0x0605_4B50;
+const ZIP_ENTRY_SIGNATURE: u32 = 0x0201_4B50;
+const ZIP_LOCAL_SIGNATURE: u32 = 0x0403_4B50;
+const ZIP_END_SIZE: usize = 22;
+const ZIP_ENTRY_SIZE: usize = 46;
+const ZIP_LOCAL_SIZE: usize = 30;
+const ZIP_STORED: u16 = 0;
+const ZIP_DEFLATED: u16 = 8;
+const ZIP_ENCRYPTED_FLAG: u16 = 1;
+
+const GZIP_MAGIC: [u8; 3] = [0x1F, 0x8B, 0x08];
+const GZIP_HEADER_SIZE: usize = 10;
+const GZIP_FOOTER_SIZE: usize = 8;
+const GZIP_HEADER_CRC_FLAG: u8 = 0x02;
+const GZIP_EXTRA_FLAG: u8 = 0x04;
+const GZIP_NAME_FLAG: u8 = 0x08;
+const GZIP_COMMENT_FLAG: u8 = 0x10;
+
+/// Extensions of the files loaded from zip archives when no entry is given
+const ROM_EXTENSIONS: [&str; 2] = ["gb", "gbc"];
+
+#[derive(Debug, PartialEq)]
+pub enum ArchiveError {
+    /// The zip archive contains no Game Boy ROM
+    NoRom,
+    /// The zip archive has no entry with the given name
+    MissingEntry(String),
+    /// The entry is compressed with another method than deflate
+    UnsupportedCompression(u16),
+    Encrypted,
+    /// The archive isn't a valid zip or gzip file
+    Corrupted,
+    /// The extracted ROM doesn't have the CRC32 stored in the archive
+    WrongChecksum,
+}
+
+impl fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArchiveError::NoRom => write!(f, "no Game Boy ROM in the archive"),
+            ArchiveError::MissingEntry(name) => write!(f, "no {} in the archive", name),
+            ArchiveError::UnsupportedCompression(method) => {
+                write!(f, "unsupported compression method {}", method)
+            }
+            ArchiveError::Encrypted => write!(f, "encrypted archives aren't supported"),
+            ArchiveError::Corrupted => write!(f, "the archive is corrupted"),
+            ArchiveError::WrongChecksum => write!(f, "the ROM in the archive is corrupted"),
+        }
+    }
+}
+
+impl Error for ArchiveError {}
+
+/// A ROM read from disk, either directly or from a zip or gzip archive
+pub struct RomFile {
+    /// Path of the ROM, or the path it would have next to its archive.
+    /// Battery saves and savestates are named after it.
+    pub path: PathBuf,
+    pub data: Vec<u8>,
+}
+
+impl RomFile {
+    /// Reads a ROM, extracting it from `.zip` and `.gz` files.
+    /// `entry` is the name of the file to load from a zip archive,
+    /// its first Game Boy ROM is loaded otherwise.
+    pub fn read(path: &Path, entry: Option<&str>) -> Result<RomFile, CartridgeError> {
+        let data = fs::read(path)?;
+        let extension = path
+            .extension()
+            .and_then(OsStr::to_str)
+            .map(str::to_ascii_lowercase);
+        let (name, data) = match extension.as_deref() {
+            Some("zip") => extract_zip(&data, entry)?,
+            Some("gz") => {
+                let (name, data) = extract_gzip(&data)?;
+                let name = name.or_else(|| Some(path.file_stem()?.to_str()?.to_string()));
+                (name.ok_or(ArchiveError::Corrupted)?, data)
+            }
+            _ => {
+                return Ok(RomFile {
+                    path: path.to_path_buf(),
+                    data,
+                })
+            }
+        };
+
+        // directories inside the archive are ignored
+        let file_name = Path::new(&name)
+            .file_name()
+            .ok_or(ArchiveError::Corrupted)?;
+        Ok(RomFile {
+            path: path.with_file_name(file_name),
+            data,
+        })
+    }
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16, ArchiveError> {
+    let bytes = data
+        .get(offset..offset + 2)
+        .ok_or(ArchiveError::Corrupted)?;
+    Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, ArchiveError> {
+    let bytes = data
+        .get(offset..offset + 4)
+        .ok_or(ArchiveError::Corrupted)?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn slice(data: &[u8], offset: usize, length: usize) -> Result<&[u8], ArchiveError> {
+    data.get(offset..offset + length)
+        .ok_or(ArchiveError::Corrupted)
+}
+
+fn is_rom_name(name: &str) -> bool {
+    match Path::new(name).extension().and_then(OsStr::to_str) {
+        Some(extension) => ROM_EXTENSIONS.contains(&extension.to_ascii_lowercase().as_str()),
+        None => false,
+    }
+}
+
+/// Extracts an entry of a zip archive, the one with the given name
+/// or the first Game Boy ROM. Returns its name and data.
+pub fn extract_zip(data: &[u8], entry: Option<&str>) -> Result<(String, Vec<u8>), ArchiveError> {
+    // the end of the central directory is followed by a comment of variable size
+    let end = (0..=data.len().saturating_sub(ZIP_END_SIZE))
+        .rev()
+        .find(|&offset| read_u32(data, offset) == Ok(ZIP_END_SIGNATURE))
+        .ok_or(ArchiveError::Corrupted)?;
+    let entry_count = read_u16(data, end + 10)?;
+    let mut offset = read_u32(data, end + 16)? as usize;
+
+    for _ in 0..entry_count {
+        if read_u32(data, offset)? != ZIP_ENTRY_SIGNATURE {
+            return Err(ArchiveError::Corrupted);
+        }
+        let name_length = usize::from(read_u16(data, offset + 28)?);
+        let extra_length = usize::from(read_u16(data, offset + 30)?);
+        let comment_length = usize::from(read_u16(data, offset + 32)?);
+        let name = slice(data, offset + ZIP_ENTRY_SIZE, name_length)?;
+        let name = String::from_utf8_lossy(name).into_owned();
+
+        let selected = match entry {
+            Some(entry) => name == entry || Path::new(&name).file_name() == Some(OsStr::new(entry)),
+            None => is_rom_name(&name),
+        };
+        if selected {
+            let rom = extract_zip_entry(data, offset)?;
+            return Ok((name, rom));
+        }
+        offset += ZIP_ENTRY_SIZE + name_length + extra_length + comment_length;
+    }
+
+    match entry {
+        Some(entry) => Err(ArchiveError::MissingEntry(entry.to_string())),
+        None => Err(ArchiveError::NoRom),
+    }
+}
+
+fn extract_zip_entry(data: &[u8], entry_offset: usize) -> Result<Vec<u8>, ArchiveError> {
+    let flags = read_u16(data, entry_offset + 8)?;
+    let method = read_u16(data, entry_offset + 10)?;
+    let checksum = read_u32(data, entry_offset + 16)?;
+    let compressed_size = read_u32(data, entry_offset + 20)? as usize;
+    let size = read_u32(data, entry_offset + 24)? as usize;
+    let local_offset = read_u32(data, entry_offset + 42)? as usize;
+    if flags & ZIP_ENCRYPTED_FLAG != 0 {
+        return Err(ArchiveError::Encrypted);
+    }
+
+    if read_u32(data, local_offset)? != ZIP_LOCAL_SIGNATURE {
+        return Err(ArchiveError::Corrupted);
+    }
+    let name_length = usize::from(read_u16(data, local_offset + 26)?);
+    let extra_length = usize::from(read_u16(data, local_offset + 28)?);
+    let start = local_offset + ZIP_LOCAL_SIZE + name_length + extra_length;
+    let compressed = slice(data, start, compressed_size)?;
+
+    let rom = match method {
+        ZIP_STORED => compressed.to_vec(),
+        ZIP_DEFLATED => inflate(compressed).ok_or(ArchiveError::Corrupted)?,
+        method => return Err(ArchiveError::UnsupportedCompression(method)),
+    };
+    if rom.len() != size || crc32(&rom) != checksum {
+        return Err(ArchiveError::WrongChecksum);
+    }
+    Ok(rom)
+}
+
+/// Extracts the content of a gzip file. Returns the original name of the file,
+/// if the archive has it, and its data.
+pub fn extract_gzip(data: &[u8]) -> Result<(Option<String>, Vec<u8>), ArchiveError> {
+    if !data.starts_with(&GZIP_MAGIC) || data.len() < GZIP_HEADER_SIZE + GZIP_FOOTER_SIZE {
+        return Err(ArchiveError::Corrupted);
+    }
+
+    let flags = data[3];
+    let mut offset = GZIP_HEADER_SIZE;
+    if flags & GZIP_EXTRA_FLAG != 0 {
+        offset += 2 + usize::from(read_u16(data, offset)?);
+    }
+    let mut name = None;
+    for &flag in [GZIP_NAME_FLAG, GZIP_COMMENT_FLAG].iter() {
+        if flags & flag != 0 {
+            // zero terminated strings
+            let length = data
+                .get(offset..)
+                .and_then(|data| data.iter().position(|&byte| byte == 0))
+                .ok_or(ArchiveError::Corrupted)?;
+            if flag == GZIP_NAME_FLAG {
+                name = Some(String::from_utf8_lossy(&data[offset..offset + length]).into_owned());
+            }
+            offset += length + 1;
+        }
+    }
+    if flags & GZIP_HEADER_CRC_FLAG != 0 {
+        offset += 2;
+    }
+
+    let footer = data.len() - GZIP_FOOTER_SIZE;
+    let compressed = data.get(offset..footer).ok_or(ArchiveError::Corrupted)?;
+    let content = inflate(compressed).ok_or(ArchiveError::Corrupted)?;
+    if crc32(&content) != read_u32(data, footer)?
+        || content.len() as u32 != read_u32(data, footer + 4)?
+    {
+        return Err(ArchiveError::WrongChecksum);
+    }
+    Ok((name, content))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // "rustyboy rustyboy rustyboy!" compressed with deflate
+    const DEFLATED: [u8; 15] = [
+        0x2B, 0x2A, 0x2D, 0x2E, 0xA9, 0x4C, 0xCA, 0xAF, 0x54, 0x28, 0x42, 0x67, 0x28, 0x02, 0x00,
+    ];
+    const CONTENT: &[u8] = b"rustyboy rustyboy rustyboy!";
+
+    struct ZipEntry<'a> {
+        name: &'a str,
+        method: u16,
+        data: &'a [u8],
+        content: &'a [u8],
+    }
+
+    fn zip(entries: &[ZipEntry<'_>]) -> Vec<u8> {
+        let mut archive = Vec::new();
+        let mut directory = Vec::new();
+        for entry in entries {
+            let mut header = Vec::new();
+            header.extend_from_slice(&[0x14, 0, 0, 0]);
+            header.extend_from_slice(&entry.method.to_le_bytes());
+            header.extend_from_slice(&[0; 4]);
+            header.extend_from_slice(&crc32(entry.content).to_le_bytes());
+            header.extend_from_slice(&(entry.data.len() as u32).to_le_bytes());
+            header.extend_from_slice(&(entry.content.len() as u32).to_le_bytes());
+            header.extend_from_slice(&(entry.name.len() as u16).to_le_bytes());
+            header.extend_from_slice(&[0; 2]);
+
+            directory.extend_from_slice(&ZIP_ENTRY_SIGNATURE.to_le_bytes());
+            directory.extend_from_slice(&[0x14, 0]);
+            directory.extend_from_slice(&header);
+            directory.extend_from_slice(&[0; 10]);
+            directory.extend_from_slice(&(archive.len() as u32).to_le_bytes());
+            directory.extend_from_slice(entry.name.as_bytes());
+
+            archive.extend_from_slice(&ZIP_LOCAL_SIGNATURE.to_le_bytes());
+            archive.extend_from_slice(&header);
+            archive.extend_from_slice(entry.name.as_bytes());
+            archive.extend_from_slice(entry.data);
+        }
+
+        let directory_offset = archive.len() as u32;
+        archive.extend_from_slice(&directory);
+        archive.extend_from_slice(&ZIP_END_SIGNATURE.to_le_bytes());
+        archive.extend_from_slice(&[0; 4]);
+        archive.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        archive.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        archive.extend_from_slice(&(directory.len() as u32).to_le_bytes());
+        archive.extend_from_slice(&directory_offset.to_le_bytes());
+        archive.extend_from_slice(&[0; 2]);
+        archive
+    }
+
+    fn test_zip() -> Vec<u8> {
+        zip(&[
+            ZipEntry {
+                name: "readme.txt",
+                method: ZIP_STORED,
+                data: b"hello",
+                content: b"hello",
+            },
+            ZipEntry {
+                name: "roms/Game.GB",
+                method: ZIP_DEFLATED,
+                data: &DEFLATED,
+                content: CONTENT,
+            },
+            ZipEntry {
+                name: "Other.gbc",
+                method: ZIP_STORED,
+                data: b"other",
+                content: b"other",
+            },
+        ])
+    }
+
+    #[test]
+    fn zip_first_rom() {
+        let (name, data) = extract_zip(&test_zip(), None).unwrap();
+        assert_eq!(name, "roms/Game.GB");
+        assert_eq!(data, CONTENT);
+    }
+
+    #[test]
+    fn zip_entry_by_name() {
+        let archive = test_zip();
+        assert_eq!(
+            extract_zip(&archive, Some("Other.gbc")).unwrap().1,
+            b"other"
+        );
+        assert_eq!(
+            extract_zip(&archive, Some("readme.txt")).unwrap().1,
+            b"hello"
+        );
+        assert_eq!(
+            extract_zip(&archive, Some("missing.gb")),
+            Err(ArchiveError::MissingEntry(String::from("missing.gb")))
+        );
+    }
+
+    #[test]
+    fn zip_errors() {
+        let archive = zip(&[ZipEntry {
+            name: "readme.txt",
+            method: ZIP_STORED,
+            data: b"hello",
+            content: b"hello",
+        }]);
+        assert_eq!(extract_zip(&archive, None), Err(ArchiveError::NoRom));
+
+        let archive = zip(&[ZipEntry {
+            name: "game.gb",
+            method: ZIP_STORED,
+            data: b"hellO",
+            content: b"hello",
+        }]);
+        assert_eq!(
+            extract_zip(&archive, None),
+            Err(ArchiveError::WrongChecksum)
+        );
+        assert_eq!(extract_zip(b"PK", None), Err(ArchiveError::Corrupted));
+    }
+
+    #[test]
+    fn gzip() {
+        let mut archive = vec![0x1F, 0x8B, 0x08, GZIP_NAME_FLAG, 0, 0, 0, 0, 0, 0xFF];
+        archive.extend_from_slice(b"game.gb\0");
+        archive.extend_from_slice(&DEFLATED);
+        archive.extend_from_slice(&crc32(CONTENT).to_le_bytes());
+        archive.extend_from_slice(&(CONTENT.len() as u32).to_le_bytes());
+        assert_eq!(
+            extract_gzip(&archive),
+            Ok((Some(String::from("game.gb")), CONTENT.to_vec()))
+        );
+
+        let last = archive.len() - 1;
+        archive[last] ^= 1;
+        assert_eq!(extract_gzip(&archive), Err(ArchiveError::WrongChecksum));
+        assert_eq!(extract_gzip(b"PK"), Err(ArchiveError::Corrupted));
+    }
+
+    #[test]
+    fn rom_file_paths() {
+        let directory = std::env::temp_dir();
+        let path = directory.join("rustyboy_rom_file_paths.zip");
+        fs::write(&path, test_zip()).unwrap();
+        let rom_file = RomFile::read(&path, None).ok().unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(rom_file.path, directory.join("Game.GB"));
+        assert_eq!(rom_file.data, CONTENT);
+    }
+}
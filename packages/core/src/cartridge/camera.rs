@@ -0,0 +1,284 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+/// Size of the images captured by the Game Boy Camera sensor
+pub const CAMERA_WIDTH: usize = 128;
+pub const CAMERA_HEIGHT: usize = 112;
+
+/// Gives the images seen by the sensor of the Game Boy Camera.
+/// Frames are grayscale, one byte per pixel from black (0) to white (255),
+/// row by row, and `CAMERA_WIDTH` by `CAMERA_HEIGHT` pixels.
+pub trait CameraSource {
+    /// Returns the image to use for the next capture
+    fn next_frame(&mut self) -> Vec<u8>;
+}
+
+/// Source returning the same image for every capture
+pub struct StillImage {
+    frame: Vec<u8>,
+}
+
+impl StillImage {
+    pub fn new(frame: Vec<u8>) -> StillImage {
+        StillImage { frame }
+    }
+
+    /// Loads an image file, scaled to the size of the sensor
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<StillImage, Box<dyn Error>> {
+        Ok(StillImage::new(load_frame(path.as_ref())?))
+    }
+}
+
+impl Default for StillImage {
+    /// A uniform gray image
+    fn default() -> StillImage {
+        StillImage::new(vec![0x80; CAMERA_WIDTH * CAMERA_HEIGHT])
+    }
+}
+
+impl CameraSource for StillImage {
+    fn next_frame(&mut self) -> Vec<u8> {
+        self.frame.clone()
+    }
+}
+
+/// Source returning each image in turn for every capture, looping at the end
+pub struct FrameSequence {
+    frames: Vec<Vec<u8>>,
+    index: usize,
+}
+
+impl FrameSequence {
+    pub fn new(frames: Vec<Vec<u8>>) -> FrameSequence {
+        FrameSequence { frames, index: 0 }
+    }
+
+    /// Loads every image file of a directory, in the order of their names
+    pub fn from_directory<P: AsRef<Path>>(path: P) -> Result<FrameSequence, Box<dyn Error>> {
+        let mut paths = fs::read_dir(path)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        paths.retain(|path| path.is_file());
+        paths.sort();
+
+        let frames = paths
+            .iter()
+            .map(|path| load_frame(path))
+            .collect::<Result<Vec<_>, _>>()?;
+        if frames.is_empty() {
+            return Err(String::from("no images in the directory").into());
+        }
+
+        Ok(FrameSequence::new(frames))
+    }
+}
+
+impl CameraSource for FrameSequence {
+    fn next_frame(&mut self) -> Vec<u8> {
+        match self.frames.get(self.index) {
+            Some(frame) => {
+                let frame = frame.clone();
+                self.index = (self.index + 1) % self.frames.len();
+                frame
+            }
+            None => StillImage::default().next_frame(),
+        }
+    }
+}
+
+fn load_frame(path: &Path) -> Result<Vec<u8>, Box<dyn Error>> {
+    let image = decode_netpbm(&fs::read(path)?)
+        .map_err(|error| format!("{}: {}", path.display(), error))?;
+    Ok(image.to_sensor_size())
+}
+
+/// A grayscale image of any size
+pub struct GrayImage {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+}
+
+impl GrayImage {
+    /// Crops the image to the aspect ratio of the sensor, keeping its center,
+    /// then scales it to the size of the sensor
+    pub fn to_sensor_size(&self) -> Vec<u8> {
+        let (width, height) = if self.width * CAMERA_HEIGHT > self.height * CAMERA_WIDTH {
+            (self.height * CAMERA_WIDTH / CAMERA_HEIGHT, self.height)
+        } else {
+            (self.width, self.width * CAMERA_HEIGHT / CAMERA_WIDTH)
+        };
+        let left = (self.width - width) / 2;
+        let top = (self.height - height) / 2;
+
+        let mut frame = Vec::with_capacity(CAMERA_WIDTH * CAMERA_HEIGHT);
+        for y in 0..CAMERA_HEIGHT {
+            let source_y = top + y * height / CAMERA_HEIGHT;
+            for x in 0..CAMERA_WIDTH {
+                let source_x = left + x * width / CAMERA_WIDTH;
+                frame.push(
+                    self.pixels
+                        .get(source_y * self.width + source_x)
+                        .cloned()
+                        .unwrap_or(0),
+                );
+            }
+        }
+        frame
+    }
+}
+
+/// Decodes a PGM or PPM image, in its binary or plain text variant.
+/// Colors are converted to grayscale.
+pub fn decode_netpbm(data: &[u8]) -> Result<GrayImage, String> {
+    let mut tokens = NetpbmTokens { data, position: 0 };
+    let magic = tokens.next_token()?;
+    let channels = match magic.as_str() {
+        "P2" | "P5" => 1,
+        "P3" | "P6" => 3,
+        _ => {
+            return Err(String::from(
+                "unsupported image format, expected a PGM or PPM",
+            ))
+        }
+    };
+    let width = tokens.next_number()?;
+    let height = tokens.next_number()?;
+    let max_value = tokens.next_number()?;
+    if width == 0 || height == 0 || max_value == 0 || max_value > 0xFFFF {
+        return Err(String::from("invalid image header"));
+    }
+
+    let sample_count = width
+        .checked_mul(height)
+        .and_then(|count| count.checked_mul(channels))
+        .ok_or_else(|| String::from("invalid image size"))?;
+    let samples: Vec<usize> = if magic == "P5" || magic == "P6" {
+        // a single whitespace separates the header from the binary samples
+        let bytes_per_sample = if max_value > 0xFF { 2 } else { 1 };
+        let data = &data[tokens.position..];
+        if data.len() / bytes_per_sample < sample_count {
+            return Err(String::from("the image is truncated"));
+        }
+        data[..sample_count * bytes_per_sample]
+            .chunks(bytes_per_sample)
+            .map(|sample| {
+                sample
+                    .iter()
+                    .fold(0, |value, &byte| value << 8 | usize::from(byte))
+            })
+            .collect()
+    } else {
+        (0..sample_count)
+            .map(|_| tokens.next_number())
+            .collect::<Result<_, _>>()?
+    };
+
+    let pixels = samples
+        .chunks(channels)
+        .map(|pixel| {
+            let sum: usize = pixel.iter().map(|&sample| sample.min(max_value)).sum();
+            (sum * 0xFF / (max_value * channels)) as u8
+        })
+        .collect();
+    Ok(GrayImage {
+        width,
+        height,
+        pixels,
+    })
+}
+
+/// Splits the text parts of a netpbm image in whitespace separated tokens
+struct NetpbmTokens<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> NetpbmTokens<'a> {
+    fn next_token(&mut self) -> Result<String, String> {
+        let mut token = String::new();
+        while let Some(&byte) = self.data.get(self.position) {
+            self.position += 1;
+            if byte == b'#' {
+                // comments last until the end of the line
+                while let Some(&byte) = self.data.get(self.position) {
+                    self.position += 1;
+                    if byte == b'\n' {
+                        break;
+                    }
+                }
+            } else if byte.is_ascii_whitespace() {
+                if !token.is_empty() {
+                    return Ok(token);
+                }
+            } else {
+                token.push(byte as char);
+            }
+        }
+
+        if token.is_empty() {
+            Err(String::from("unexpected end of the image"))
+        } else {
+            Ok(token)
+        }
+    }
+
+    fn next_number(&mut self) -> Result<usize, String> {
+        self.next_token()?
+            .parse()
+            .map_err(|_| String::from("invalid number in the image"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_plain_pgm() {
+        let image = decode_netpbm(b"P2\n# comment\n2 2\n15\n0 15\n5 10\n").unwrap();
+        assert_eq!((image.width, image.height), (2, 2));
+        assert_eq!(image.pixels, vec![0, 0xFF, 0x55, 0xAA]);
+    }
+
+    #[test]
+    fn decode_binary_ppm() {
+        let image = decode_netpbm(b"P6 2 1 255\n\xFF\x00\x00\xFF\xFF\xFF").unwrap();
+        assert_eq!(image.pixels, vec![0x55, 0xFF]);
+    }
+
+    #[test]
+    fn decode_errors() {
+        assert!(decode_netpbm(b"P6 2 1 255\n\xFF").is_err());
+        assert!(decode_netpbm(b"P1 2 1").is_err());
+        assert!(decode_netpbm(b"P5 2").is_err());
+    }
+
+    #[test]
+    fn scale_to_sensor() {
+        // twice as wide as the sensor, so the sides are cropped
+        let width = CAMERA_HEIGHT * 2;
+        let pixels = (0..width * CAMERA_HEIGHT)
+            .map(|i| if i % width < width / 2 { 0 } else { 0xFF })
+            .collect();
+        let image = GrayImage {
+            width,
+            height: CAMERA_HEIGHT,
+            pixels,
+        };
+
+        let frame = image.to_sensor_size();
+        assert_eq!(frame.len(), CAMERA_WIDTH * CAMERA_HEIGHT);
+        assert_eq!(frame[0], 0);
+        assert_eq!(frame[CAMERA_WIDTH - 1], 0xFF);
+    }
+
+    #[test]
+    fn sequence_loops() {
+        let mut sequence = FrameSequence::new(vec![vec![1], vec![2]]);
+        assert_eq!(sequence.next_frame(), vec![1]);
+        assert_eq!(sequence.next_frame(), vec![2]);
+        assert_eq!(sequence.next_frame(), vec![1]);
+    }
+}
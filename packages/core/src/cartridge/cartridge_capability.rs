@@ -1,3 +1,5 @@
+use crate::cartridge::cartridge_error::CartridgeError;
+
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum CartridgeCapability {
     ROM,
@@ -6,6 +8,8 @@ pub enum CartridgeCapability {
     MBC3,
     MBC4,
     MBC5,
+    MBC6,
+    MBC7,
     RAM,
     Battery,
     Timer,
@@ -15,10 +19,36 @@ pub enum CartridgeCapability {
     BandaiTama5,
     HuC1,
     HuC3,
+    /// Accelerometer, read through the MBC7
+    Sensor,
 }
 
 impl CartridgeCapability {
-    pub fn from_byte(value: u8) -> Result<Vec<CartridgeCapability>, String> {
+    /// Returns the name used for the capability in cartridge type names
+    pub fn name(&self) -> &'static str {
+        match self {
+            CartridgeCapability::ROM => "ROM",
+            CartridgeCapability::MBC1 => "MBC1",
+            CartridgeCapability::MBC2 => "MBC2",
+            CartridgeCapability::MBC3 => "MBC3",
+            CartridgeCapability::MBC4 => "MBC4",
+            CartridgeCapability::MBC5 => "MBC5",
+            CartridgeCapability::MBC6 => "MBC6",
+            CartridgeCapability::MBC7 => "MBC7",
+            CartridgeCapability::RAM => "RAM",
+            CartridgeCapability::Battery => "BATTERY",
+            CartridgeCapability::Timer => "TIMER",
+            CartridgeCapability::MMM01 => "MMM01",
+            CartridgeCapability::Rumble => "RUMBLE",
+            CartridgeCapability::PocketCamera => "POCKET CAMERA",
+            CartridgeCapability::BandaiTama5 => "BANDAI TAMA5",
+            CartridgeCapability::HuC1 => "HuC1",
+            CartridgeCapability::HuC3 => "HuC3",
+            CartridgeCapability::Sensor => "SENSOR",
+        }
+    }
+
+    pub fn from_byte(value: u8) -> Result<Vec<CartridgeCapability>, CartridgeError> {
         match value {
             0x00 => Ok(vec![CartridgeCapability::ROM]),
             0x01 => Ok(vec![CartridgeCapability::MBC1]),
@@ -90,15 +120,43 @@ impl CartridgeCapability {
                 CartridgeCapability::RAM,
                 CartridgeCapability::Battery,
             ]),
-            0xFC => Ok(vec![CartridgeCapability::PocketCamera]),
-            0xFD => Ok(vec![CartridgeCapability::BandaiTama5]),
-            0xFE => Ok(vec![CartridgeCapability::HuC3]),
+            0x20 => Ok(vec![
+                CartridgeCapability::MBC6,
+                CartridgeCapability::RAM,
+                CartridgeCapability::Battery,
+            ]),
+            0x22 => Ok(vec![
+                CartridgeCapability::MBC7,
+                CartridgeCapability::Sensor,
+                CartridgeCapability::Rumble,
+                CartridgeCapability::RAM,
+                CartridgeCapability::Battery,
+            ]),
+            0xFC => Ok(vec![
+                CartridgeCapability::PocketCamera,
+                CartridgeCapability::RAM,
+                CartridgeCapability::Battery,
+            ]),
+            // the TAMA5 has RAM and a clock built in, powered by a battery
+            0xFD => Ok(vec![
+                CartridgeCapability::BandaiTama5,
+                CartridgeCapability::Timer,
+                CartridgeCapability::RAM,
+                CartridgeCapability::Battery,
+            ]),
+            // every HuC3 cartridge has a battery for its RAM and clock
+            0xFE => Ok(vec![
+                CartridgeCapability::HuC3,
+                CartridgeCapability::Timer,
+                CartridgeCapability::RAM,
+                CartridgeCapability::Battery,
+            ]),
             0xFF => Ok(vec![
                 CartridgeCapability::HuC1,
                 CartridgeCapability::RAM,
                 CartridgeCapability::Battery,
             ]),
-            _ => Err(String::from("invalid cartridge type value")),
+            _ => Err(CartridgeError::InvalidCartridgeType(value)),
         }
     }
 }
@@ -0,0 +1,66 @@
+use crate::cartridge::archive::ArchiveError;
+use crate::cartridge::patch::PatchError;
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// Errors that prevent a cartridge from being loaded
+#[derive(Debug)]
+pub enum CartridgeError {
+    /// The file couldn't be read
+    Io(io::Error),
+    /// The data is too short to contain a header, its length is given
+    TooShort(usize),
+    InvalidCartridgeType(u8),
+    /// A patch couldn't be applied to the ROM
+    Patch(PatchError),
+    /// The ROM couldn't be extracted from its archive
+    Archive(ArchiveError),
+}
+
+impl fmt::Display for CartridgeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CartridgeError::Io(error) => write!(f, "couldn't read the cartridge: {}", error),
+            CartridgeError::TooShort(length) => write!(
+                f,
+                "the cartridge is too short to contain a header ({} bytes)",
+                length
+            ),
+            CartridgeError::InvalidCartridgeType(value) => {
+                write!(f, "invalid cartridge type value {:#04X}", value)
+            }
+            CartridgeError::Patch(error) => write!(f, "couldn't apply the patch: {}", error),
+            CartridgeError::Archive(error) => write!(f, "couldn't extract the ROM: {}", error),
+        }
+    }
+}
+
+impl Error for CartridgeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CartridgeError::Io(error) => Some(error),
+            CartridgeError::Patch(error) => Some(error),
+            CartridgeError::Archive(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for CartridgeError {
+    fn from(error: io::Error) -> Self {
+        CartridgeError::Io(error)
+    }
+}
+
+impl From<PatchError> for CartridgeError {
+    fn from(error: PatchError) -> Self {
+        CartridgeError::Patch(error)
+    }
+}
+
+impl From<ArchiveError> for CartridgeError {
+    fn from(error: ArchiveError) -> Self {
+        CartridgeError::Archive(error)
+    }
+}
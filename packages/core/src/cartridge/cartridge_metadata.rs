@@ -1,11 +1,21 @@
 use crate::cartridge::cartridge_capability::CartridgeCapability;
+use crate::cartridge::cartridge_error::CartridgeError;
+use crate::cartridge::licensee::{new_licensee_name, old_licensee_name, USE_NEW_LICENSEE_CODE};
 use crate::util::bytes_convert;
 use crate::util::ut8_decode_trim;
-use std::error::Error;
 use std::ops::RangeInclusive;
 
+// The offset of the Nintendo logo displayed by the boot ROM,
+// which refuses to run the cartridge if it doesn't match
+pub const NINTENDO_LOGO_OFFSET: usize = 0x0104;
+pub const NINTENDO_LOGO: [u8; 48] = [
+    0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0C, 0x00, 0x0D,
+    0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E, 0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99,
+    0xBB, 0xBB, 0x67, 0x63, 0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E,
+];
+
 // The range where the game's title resides in ASCII uppercase characters
-const GAME_TITLE_OFFSET: usize = 0x0134;
+pub const GAME_TITLE_OFFSET: usize = 0x0134;
 
 // The range where the manufacturer code resides
 // for newer cartridges, 4 characters uppercase
@@ -23,7 +33,7 @@ const SGB_FLAG_OFFSET: usize = 0x0146;
 const SBG_FLAG_ENABLED: u8 = 0x03;
 
 // The offset that determines the cartridge type
-const CARTRIDGE_TYPE_OFFSET: usize = 0x0147;
+pub const CARTRIDGE_TYPE_OFFSET: usize = 0x0147;
 
 // The offset that determines the ROM size
 const ROM_SIZE_OFFSET: usize = 0x0148;
@@ -43,11 +53,14 @@ const VERSION_OFFSET: usize = 0x014C;
 
 // Offset that determines the checksum of the cartridge header (0x0134..=014C),
 // obtained with "x=0:FOR i=0134h TO 014Ch:x=x-MEM[i]-1:NEXT"
-const HEADER_CHECKSUM_OFFSET: usize = 0x014D;
+pub const HEADER_CHECKSUM_OFFSET: usize = 0x014D;
 
-// Offset that determines the checksum of the whole cartridge ROM,
+// Range that determines the checksum of the whole cartridge ROM, in big endian,
 // obtained by summing the bytes of the cartridge (without checksum)
-// const GLOBAL_CHECKSUM_RANGE: RangeInclusive<usize> = (0x014E..=0x014F);
+pub const GLOBAL_CHECKSUM_RANGE: RangeInclusive<usize> = 0x014E..=0x014F;
+
+// The end of the header, files shorter than this aren't cartridges
+pub const HEADER_END: usize = 0x0150;
 
 type TitleSection = (String, Option<String>, Option<CGBFlag>);
 
@@ -59,40 +72,82 @@ pub struct CartridgeMetadata {
     pub new_licensee_code: Option<String>,
     pub sgb_enhanced: bool,
     pub capabilities: Vec<CartridgeCapability>,
-    pub rom_size: usize,
+    /// Size declared by the header, unknown on some bootlegs whose header is garbage
+    pub rom_size: Option<usize>,
+    pub rom_size_code: u8,
     pub ram_size: usize,
     pub destination: Destination,
     pub old_licensee_code: Option<u8>,
     pub version: u8,
     pub header_checksum: u8,
-    pub global_checksum: u8,
+    pub global_checksum: u16,
 }
 
 impl CartridgeMetadata {
-    pub fn from_buffer(buffer: &[u8]) -> Result<CartridgeMetadata, Box<dyn Error>> {
-        let (title, manufacturer_code, cgb_flag) = Self::parse_title_section(buffer)?;
+    pub fn from_buffer(buffer: &[u8]) -> Result<CartridgeMetadata, CartridgeError> {
+        if buffer.len() < HEADER_END {
+            return Err(CartridgeError::TooShort(buffer.len()));
+        }
+
+        let (title, manufacturer_code, cgb_flag) = Self::parse_title_section(buffer);
 
         Ok(CartridgeMetadata {
             title,
             manufacturer_code,
             cgb_flag,
-            new_licensee_code: Self::parse_new_licensee_code(buffer)?,
+            new_licensee_code: Self::parse_new_licensee_code(buffer),
             sgb_enhanced: buffer[SGB_FLAG_OFFSET] == SBG_FLAG_ENABLED,
             capabilities: CartridgeCapability::from_byte(buffer[CARTRIDGE_TYPE_OFFSET])?,
-            rom_size: Self::parse_rom_size(buffer)? as usize,
-            ram_size: Self::parse_ram_size(buffer)? as usize,
-            destination: Destination::from(buffer[DESTINATION_OFFSET])?,
+            rom_size: Self::parse_rom_size(buffer),
+            rom_size_code: buffer[ROM_SIZE_OFFSET],
+            ram_size: Self::parse_ram_size(buffer) as usize,
+            destination: Destination::from(buffer[DESTINATION_OFFSET]),
             old_licensee_code: Self::parse_old_licensee_code(buffer),
             version: buffer[VERSION_OFFSET],
             header_checksum: buffer[HEADER_CHECKSUM_OFFSET],
-            global_checksum: 0, // TODO
+            global_checksum: u16::from(buffer[*GLOBAL_CHECKSUM_RANGE.start()]) << 8
+                | u16::from(buffer[*GLOBAL_CHECKSUM_RANGE.end()]),
         })
     }
 
-    fn parse_title_section(buffer: &[u8]) -> Result<TitleSection, Box<dyn Error>> {
+    /// Returns the name of the company that published the game, if its code is known
+    pub fn publisher(&self) -> Option<&'static str> {
+        match self.old_licensee_code {
+            Some(code) => old_licensee_name(code),
+            None => new_licensee_name(self.new_licensee_code.as_deref()?),
+        }
+    }
+
+    /// Returns the hardware of the cartridge as named in the header, like "MBC1+RAM+BATTERY"
+    pub fn cartridge_type_name(&self) -> String {
+        self.capabilities
+            .iter()
+            .map(CartridgeCapability::name)
+            .collect::<Vec<_>>()
+            .join("+")
+    }
+
+    /// Returns the size of the ROM with its number of 16KB banks, like "64 KiB (4 banks)"
+    pub fn rom_size_name(&self) -> String {
+        match self.rom_size {
+            Some(rom_size) => format!("{} ({} banks)", size_name(rom_size), rom_size / 0x4000),
+            None => format!("Unknown ({:#04X})", self.rom_size_code),
+        }
+    }
+
+    /// Returns the size of the RAM declared in the header, like "8 KiB"
+    pub fn ram_size_name(&self) -> String {
+        if self.ram_size == 0 {
+            String::from("None")
+        } else {
+            size_name(self.ram_size)
+        }
+    }
+
+    fn parse_title_section(buffer: &[u8]) -> TitleSection {
         let mut title_end_offset = CGB_FLAG_OFFSET;
         let cgb_flag = CGBFlag::from(buffer[CGB_FLAG_OFFSET]);
-        let manufacturer_code = Self::parse_manufacturer_code(buffer)?;
+        let manufacturer_code = Self::parse_manufacturer_code(buffer);
         // I might be wrong about this, but I'm currently assuming that
         // only games that have the CGB flag have a manufacturer code
         if cgb_flag.is_some() {
@@ -101,65 +156,83 @@ impl CartridgeMetadata {
                 title_end_offset -= 4;
             }
         }
-        Ok((
-            ut8_decode_trim(buffer[(GAME_TITLE_OFFSET..title_end_offset)].to_vec())?,
+        // bootlegs and homebrews don't always put text there,
+        // which shouldn't prevent them from running
+        let title: Vec<u8> = buffer[GAME_TITLE_OFFSET..title_end_offset]
+            .iter()
+            .filter(|&&x| x != 0)
+            .cloned()
+            .collect();
+        (
+            String::from_utf8_lossy(&title).into_owned(),
             manufacturer_code,
             cgb_flag,
-        ))
+        )
     }
 
-    fn parse_manufacturer_code(buffer: &[u8]) -> Result<Option<String>, Box<dyn Error>> {
-        let code = ut8_decode_trim(buffer[MANUFACTURER_CODE_RANGE].to_vec())?;
+    fn parse_manufacturer_code(buffer: &[u8]) -> Option<String> {
+        let code = ut8_decode_trim(buffer[MANUFACTURER_CODE_RANGE].to_vec()).ok()?;
         if code.len() == 4 {
-            return Ok(Some(code));
+            return Some(code);
         }
-        Ok(None)
+        None
     }
 
-    fn parse_new_licensee_code(buffer: &[u8]) -> Result<Option<String>, Box<dyn Error>> {
-        let code = ut8_decode_trim(buffer[NEW_LICENSEE_CODE_RANGE].to_vec())?;
+    fn parse_new_licensee_code(buffer: &[u8]) -> Option<String> {
+        let code = ut8_decode_trim(buffer[NEW_LICENSEE_CODE_RANGE].to_vec()).ok()?;
         if code.len() == 2 {
-            return Ok(Some(code));
+            return Some(code);
         }
-        Ok(None)
+        None
     }
 
-    fn parse_rom_size(buffer: &[u8]) -> Result<f64, String> {
+    fn parse_rom_size(buffer: &[u8]) -> Option<usize> {
         match buffer[ROM_SIZE_OFFSET] {
-            0x00 => Ok(bytes_convert::from_kb(32.0)),
-            0x01 => Ok(bytes_convert::from_kb(64.0)),
-            0x02 => Ok(bytes_convert::from_kb(128.0)),
-            0x03 => Ok(bytes_convert::from_kb(256.0)),
-            0x04 => Ok(bytes_convert::from_kb(512.0)),
-            0x05 => Ok(bytes_convert::from_mb(1.0)),
-            0x06 => Ok(bytes_convert::from_mb(2.0)),
-            0x07 => Ok(bytes_convert::from_mb(4.0)),
-            0x52 => Ok(bytes_convert::from_mb(1.1)),
-            0x53 => Ok(bytes_convert::from_mb(1.2)),
-            0x54 => Ok(bytes_convert::from_mb(1.5)),
-            _ => Err(String::from("invalid ROM size value")),
-        }
-    }
-
-    fn parse_ram_size(buffer: &[u8]) -> Result<f64, String> {
+            // 32KB doubled n times
+            value @ 0x00..=0x08 => Some(0x8000 << value),
+            // the 72, 80 and 96 banks that are named 1.1, 1.2 and 1.5MB
+            0x52 => Some(72 * 0x4000),
+            0x53 => Some(80 * 0x4000),
+            0x54 => Some(96 * 0x4000),
+            _ => None,
+        }
+    }
+
+    fn parse_ram_size(buffer: &[u8]) -> f64 {
         match buffer[RAM_SIZE_OFFSET] {
-            0x00 => Ok(0.0),
-            0x01 => Ok(bytes_convert::from_kb(2.0)),
-            0x02 => Ok(bytes_convert::from_kb(8.0)),
-            0x03 => Ok(bytes_convert::from_kb(32.0)),
-            _ => Err(String::from("invalid RAM size value")),
+            0x01 => bytes_convert::from_kb(2.0),
+            0x02 => bytes_convert::from_kb(8.0),
+            0x03 => bytes_convert::from_kb(32.0),
+            0x04 => bytes_convert::from_kb(128.0),
+            0x05 => bytes_convert::from_kb(64.0),
+            // unknown values are treated as no RAM, controllers with
+            // built-in RAM like the MBC2 still get theirs
+            _ => 0.0,
         }
     }
 
     fn parse_old_licensee_code(buffer: &[u8]) -> Option<u8> {
         let value = buffer[OLD_LICENSEE_CODE_OFFSET];
         match value {
-            0x33 => None,
+            USE_NEW_LICENSEE_CODE => None,
             _ => Some(value),
         }
     }
 }
 
+fn size_name(bytes: usize) -> String {
+    if bytes >= 0x10_0000 {
+        let size = bytes as f64 / bytes_convert::from_mb(1.0);
+        if size.fract() == 0.0 {
+            format!("{} MiB", size)
+        } else {
+            format!("{:.1} MiB", size)
+        }
+    } else {
+        format!("{} KiB", bytes / 0x400)
+    }
+}
+
 #[derive(Debug)]
 pub enum CGBFlag {
     CGBOnly = 0x80,
@@ -178,16 +251,91 @@ impl CGBFlag {
 
 #[derive(Debug)]
 pub enum Destination {
-    Japanese = 0x00,
-    NonJapanese = 0x01,
+    Japanese,
+    NonJapanese,
+    /// A value that no licensed cartridge uses, kept as is
+    Unknown(u8),
 }
 
 impl Destination {
-    pub fn from(value: u8) -> Result<Destination, String> {
+    pub fn from(value: u8) -> Destination {
         match value {
-            0x00 => Ok(Destination::Japanese),
-            0x01 => Ok(Destination::NonJapanese),
-            _ => Err(String::from("invalid destination code")),
+            0x00 => Destination::Japanese,
+            0x01 => Destination::NonJapanese,
+            _ => Destination::Unknown(value),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Destination::Japanese => "Japan",
+            Destination::NonJapanese => "Overseas",
+            Destination::Unknown(_) => "Unknown",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::tests::test_rom::test_rom_with_type;
+
+    fn metadata(rom: &[u8]) -> CartridgeMetadata {
+        CartridgeMetadata::from_buffer(rom).ok().unwrap()
+    }
+
+    #[test]
+    fn publisher() {
+        let mut rom = test_rom_with_type("TEST", 0x00, 0);
+        rom[OLD_LICENSEE_CODE_OFFSET] = 0x01;
+        assert_eq!(metadata(&rom).publisher(), Some("Nintendo"));
+
+        // 0x33 points to the new licensee code
+        rom[OLD_LICENSEE_CODE_OFFSET] = USE_NEW_LICENSEE_CODE;
+        rom[NEW_LICENSEE_CODE_RANGE].copy_from_slice(b"A4");
+        assert_eq!(metadata(&rom).publisher(), Some("Konami"));
+
+        rom[NEW_LICENSEE_CODE_RANGE].copy_from_slice(b"\0\0");
+        assert_eq!(metadata(&rom).publisher(), None);
+    }
+
+    #[test]
+    fn readable_names() {
+        let mut rom = test_rom_with_type("TEST", 0x13, 3);
+        rom[ROM_SIZE_OFFSET] = 0x05;
+        let mbc3 = metadata(&rom);
+        assert_eq!(mbc3.cartridge_type_name(), "MBC3+RAM+BATTERY");
+        assert_eq!(mbc3.rom_size_name(), "1 MiB (64 banks)");
+        assert_eq!(mbc3.ram_size_name(), "32 KiB");
+        assert_eq!(mbc3.destination.name(), "Japan");
+
+        rom[RAM_SIZE_OFFSET] = 0;
+        let sizes = [
+            (0x00, "32 KiB (2 banks)"),
+            (0x08, "8 MiB (512 banks)"),
+            (0x52, "1.1 MiB (72 banks)"),
+            (0x53, "1.2 MiB (80 banks)"),
+            (0x54, "1.5 MiB (96 banks)"),
+        ];
+        for &(value, name) in sizes.iter() {
+            rom[ROM_SIZE_OFFSET] = value;
+            assert_eq!(metadata(&rom).rom_size_name(), name);
+        }
+        assert_eq!(metadata(&rom).ram_size_name(), "None");
+    }
+
+    #[test]
+    fn garbage_header() {
+        let mut rom = test_rom_with_type("TEST", 0x00, 0);
+        for value in rom[GAME_TITLE_OFFSET..HEADER_END].iter_mut() {
+            *value = 0xFF;
         }
+        let metadata = metadata(&rom);
+        assert_eq!(metadata.title, "\u{FFFD}".repeat(15));
+        assert_eq!(metadata.manufacturer_code, None);
+        assert_eq!(metadata.rom_size, None);
+        assert_eq!(metadata.rom_size_name(), "Unknown (0xFF)");
+        assert_eq!(metadata.ram_size, 0);
+        assert_eq!(metadata.destination.name(), "Unknown");
     }
 }
@@ -0,0 +1,131 @@
+use crate::cartridge::cartridge_metadata::{
+    CartridgeMetadata, GAME_TITLE_OFFSET, GLOBAL_CHECKSUM_RANGE, HEADER_CHECKSUM_OFFSET,
+    NINTENDO_LOGO, NINTENDO_LOGO_OFFSET,
+};
+
+/// Result of the checks made on the header of a cartridge.
+/// A failed check doesn't prevent the cartridge from running, but usually means
+/// that the dump is bad or that the cartridge is unlicensed.
+#[derive(Debug, PartialEq)]
+pub struct HeaderValidation {
+    /// Checksum of 0x0134-0x014C, which the boot ROM compares with the header
+    pub computed_header_checksum: u8,
+    pub header_checksum_valid: bool,
+    /// Sum of every byte of the ROM except the global checksum itself,
+    /// which nothing checks on hardware
+    pub computed_global_checksum: u16,
+    pub global_checksum_valid: bool,
+    /// Whether the header contains the logo that the boot ROM checks
+    pub logo_valid: bool,
+    /// Size of the ROM, which should be the one declared in the header
+    pub actual_rom_size: usize,
+    pub rom_size_valid: bool,
+}
+
+impl HeaderValidation {
+    /// Checks the header of the given ROM, whose metadata was parsed from it
+    pub fn new(metadata: &CartridgeMetadata, rom: &[u8]) -> HeaderValidation {
+        let computed_header_checksum = header_checksum(rom);
+        let computed_global_checksum = global_checksum(rom);
+        HeaderValidation {
+            computed_header_checksum,
+            header_checksum_valid: computed_header_checksum == metadata.header_checksum,
+            computed_global_checksum,
+            global_checksum_valid: computed_global_checksum == metadata.global_checksum,
+            logo_valid: rom.get(NINTENDO_LOGO_OFFSET..NINTENDO_LOGO_OFFSET + NINTENDO_LOGO.len())
+                == Some(&NINTENDO_LOGO[..]),
+            actual_rom_size: rom.len(),
+            rom_size_valid: metadata.rom_size == Some(rom.len()),
+        }
+    }
+
+    /// Returns true if every check passed
+    pub fn is_valid(&self) -> bool {
+        self.header_checksum_valid
+            && self.global_checksum_valid
+            && self.logo_valid
+            && self.rom_size_valid
+    }
+}
+
+fn header_checksum(rom: &[u8]) -> u8 {
+    rom.get(GAME_TITLE_OFFSET..HEADER_CHECKSUM_OFFSET)
+        .unwrap_or(&[])
+        .iter()
+        .fold(0u8, |x, &value| x.wrapping_sub(value).wrapping_sub(1))
+}
+
+fn global_checksum(rom: &[u8]) -> u16 {
+    rom.iter()
+        .enumerate()
+        .filter(|(address, _)| !GLOBAL_CHECKSUM_RANGE.contains(address))
+        .fold(0u16, |sum, (_, &value)| sum.wrapping_add(u16::from(value)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::tests::test_rom::test_rom;
+
+    fn validate(rom: &[u8]) -> HeaderValidation {
+        let metadata = CartridgeMetadata::from_buffer(rom).ok().unwrap();
+        HeaderValidation::new(&metadata, rom)
+    }
+
+    fn set_global_checksum(rom: &mut [u8]) {
+        let checksum = global_checksum(rom).to_be_bytes();
+        rom[GLOBAL_CHECKSUM_RANGE].copy_from_slice(&checksum);
+    }
+
+    #[test]
+    fn valid_header() {
+        let mut rom = test_rom("TEST");
+        rom[NINTENDO_LOGO_OFFSET..NINTENDO_LOGO_OFFSET + NINTENDO_LOGO.len()]
+            .copy_from_slice(&NINTENDO_LOGO);
+        set_global_checksum(&mut rom);
+
+        let validation = validate(&rom);
+        assert!(validation.is_valid());
+        assert_eq!(validation.actual_rom_size, 0x8000);
+    }
+
+    #[test]
+    fn invalid_header() {
+        let mut rom = test_rom("TEST");
+        set_global_checksum(&mut rom);
+        rom[0x014D] ^= 0xFF;
+        rom.truncate(0x4000);
+
+        let validation = validate(&rom);
+        assert!(!validation.is_valid());
+        assert!(!validation.header_checksum_valid);
+        assert_eq!(validation.computed_header_checksum, rom[0x014D] ^ 0xFF);
+        assert!(!validation.global_checksum_valid);
+        assert!(!validation.logo_valid);
+        assert!(!validation.rom_size_valid);
+    }
+
+    #[test]
+    fn rom_size_of_72_banks() {
+        let mut rom = test_rom("TEST");
+        rom.resize(72 * 0x4000, 0);
+        rom[0x0148] = 0x52;
+
+        let validation = validate(&rom);
+        assert!(validation.rom_size_valid);
+        assert_eq!(validation.actual_rom_size, 0x12_0000);
+
+        // 0x53 declares 80 banks
+        rom[0x0148] = 0x53;
+        assert!(!validate(&rom).rom_size_valid);
+    }
+
+    #[test]
+    fn global_checksum_ignores_itself() {
+        let mut rom = test_rom("TEST");
+        set_global_checksum(&mut rom);
+        let checksum = global_checksum(&rom);
+        rom[GLOBAL_CHECKSUM_RANGE].copy_from_slice(&[0x12, 0x34]);
+        assert_eq!(global_checksum(&rom), checksum);
+    }
+}
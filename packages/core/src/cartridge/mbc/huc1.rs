@@ -0,0 +1,140 @@
+use super::{mirror, write_mirrored, MemoryBankController};
+use crate::cartridge::cartridge_capability::CartridgeCapability;
+use crate::util::savestate::{
+    read_savestate_bool, read_savestate_byte, LoadSavestateError, Savestate, SavestateStream,
+};
+use std::cmp;
+
+/// Value written to 0x0000-0x1FFF to map the infrared register instead of the RAM
+const IR_MODE: u8 = 0x0E;
+/// Value read from the infrared register when no light is received.
+/// There is no link to another GameBoy, so nothing is ever received.
+const IR_NO_LIGHT: u8 = 0xC0;
+
+/// HuC1, made by Hudson. Similar to MBC1, with an infrared port instead of the banking mode.
+pub struct HuC1 {
+    rom_bank: u8,
+    ram_bank: u8,
+    ir_mode: bool,
+    ir_led: bool,
+}
+
+impl HuC1 {
+    pub fn new(_capabilities: &[CartridgeCapability]) -> HuC1 {
+        HuC1 {
+            rom_bank: 1,
+            ram_bank: 0,
+            ir_mode: false,
+            ir_led: false,
+        }
+    }
+}
+
+impl Savestate for HuC1 {
+    fn dump_savestate(&self, buffer: &mut Vec<u8>) {
+        buffer.push(self.rom_bank);
+        buffer.push(self.ram_bank);
+        buffer.push(self.ir_mode as u8);
+        buffer.push(self.ir_led as u8);
+    }
+
+    fn load_savestate<'a>(
+        &mut self,
+        buffer: &mut SavestateStream<'a>,
+    ) -> Result<(), LoadSavestateError> {
+        self.rom_bank = read_savestate_byte(buffer)?;
+        self.ram_bank = read_savestate_byte(buffer)?;
+        self.ir_mode = read_savestate_bool(buffer)?;
+        self.ir_led = read_savestate_bool(buffer)?;
+        Ok(())
+    }
+}
+
+impl MemoryBankController for HuC1 {
+    fn rom_bank(&self) -> u16 {
+        u16::from(self.rom_bank)
+    }
+
+    fn ram_bank(&self) -> u8 {
+        self.ram_bank
+    }
+
+    // there is no RAM enable register, the RAM is mapped unless the infrared port is
+    fn ram_enabled(&self) -> bool {
+        true
+    }
+
+    fn write_rom(&mut self, address: usize, value: u8) {
+        match address {
+            0..=0x1FFF => {
+                // switch between ram and infrared
+                self.ir_mode = value == IR_MODE;
+            }
+            0x2000..=0x3FFF => {
+                // change rom bank
+                self.rom_bank = cmp::max(value & 0x3F, 1);
+            }
+            0x4000..=0x5FFF => {
+                // change ram bank
+                self.ram_bank = value & 3;
+            }
+            _ => {}
+        }
+    }
+
+    fn read_ram(&self, address: usize, ram: &[u8]) -> u8 {
+        if self.ir_mode {
+            IR_NO_LIGHT
+        } else {
+            mirror(self.relative_ram_address(address), ram.len())
+                .map_or(0xFF, |address| ram[address])
+        }
+    }
+
+    fn write_ram(&mut self, address: usize, value: u8, ram: &mut [u8]) -> bool {
+        if self.ir_mode {
+            self.ir_led = value & 1 != 0;
+            false
+        } else {
+            write_mirrored(ram, self.relative_ram_address(address), value)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rom_bank_switching() {
+        let mut mbc = HuC1::new(&[]);
+        mbc.write_rom(0x2000, 0xFF);
+        assert_eq!(mbc.rom_bank(), 0x3F);
+        mbc.write_rom(0x2000, 0);
+        assert_eq!(mbc.rom_bank(), 1);
+    }
+
+    #[test]
+    fn ram_bank_switching() {
+        let mut mbc = HuC1::new(&[]);
+        let mut ram = vec![0; 0x8000];
+        mbc.write_rom(0x4000, 2);
+        mbc.write_ram(0xA001, 0x42, &mut ram);
+        assert_eq!(ram[0x4001], 0x42);
+        assert_eq!(mbc.read_ram(0xA001, &ram), 0x42);
+    }
+
+    #[test]
+    fn infrared_register() {
+        let mut mbc = HuC1::new(&[]);
+        let mut ram = vec![0; 0x2000];
+        mbc.write_rom(0, IR_MODE);
+        mbc.write_ram(0xA000, 1, &mut ram);
+        assert!(mbc.ir_led);
+        assert_eq!(ram[0], 0);
+        assert_eq!(mbc.read_ram(0xA000, &ram), IR_NO_LIGHT);
+
+        mbc.write_rom(0, 0x0A);
+        assert_eq!(mbc.read_ram(0xA000, &ram), 0);
+    }
+}
@@ -0,0 +1,446 @@
+use super::{mirror, write_mirrored, MemoryBankController};
+use crate::cartridge::cartridge_capability::CartridgeCapability;
+use crate::util::clock::{Clock, SystemClock};
+use crate::util::savestate::{
+    read_savestate_bool, read_savestate_byte, read_savestate_u16, write_savestate_u16,
+    LoadSavestateError, Savestate, SavestateStream,
+};
+use std::cmp;
+use std::rc::Rc;
+
+/// Size of the clock footer appended to battery saves by SameBoy
+pub const FOOTER_SIZE: usize = 17;
+/// Value read from the infrared register when no light is received.
+/// There is no link to another GameBoy, so nothing is ever received.
+const IR_NO_LIGHT: u8 = 0xC0;
+const MINUTES_PER_DAY: u16 = 24 * 60;
+
+/// HuC3, made by Hudson. On top of banking, the RAM area can map a clock
+/// controlled by sending commands, and an infrared port.
+pub struct HuC3 {
+    rom_bank: u8,
+    ram_bank: u8,
+    /// Selects what is mapped to 0xA000-0xBFFF
+    mode: u8,
+    clock: HuC3Clock,
+    /// Index of the clock nibble accessed by the read and write commands
+    access_index: u8,
+    access_flags: u8,
+    response: u8,
+    ir_led: bool,
+}
+
+impl HuC3 {
+    pub fn new(_capabilities: &[CartridgeCapability]) -> HuC3 {
+        HuC3 {
+            rom_bank: 1,
+            ram_bank: 0,
+            mode: 0,
+            clock: HuC3Clock::new(Rc::new(SystemClock)),
+            access_index: 0,
+            access_flags: 0,
+            response: 0,
+            ir_led: false,
+        }
+    }
+
+    /// Runs a clock command: the command is in the upper nibble and its argument in the lower one
+    /// Returns true if the command changed the clock
+    fn command(&mut self, value: u8) -> bool {
+        let argument = value & 0xF;
+        match value >> 4 {
+            1 => {
+                // read the nibble at the index then move to the next one
+                self.response = self.clock.nibble(self.access_index);
+                self.access_index = self.access_index.wrapping_add(1);
+            }
+            2 => return self.clock.set_nibble(self.access_index, argument),
+            3 => {
+                // write the nibble at the index then move to the next one
+                let changed = self.clock.set_nibble(self.access_index, argument);
+                self.access_index = self.access_index.wrapping_add(1);
+                return changed;
+            }
+            4 => self.access_index = self.access_index & 0xF0 | argument,
+            5 => self.access_index = self.access_index & 0x0F | argument << 4,
+            6 => self.access_flags = argument,
+            _ => {}
+        }
+
+        false
+    }
+}
+
+impl Savestate for HuC3 {
+    fn dump_savestate(&self, buffer: &mut Vec<u8>) {
+        buffer.push(self.rom_bank);
+        buffer.push(self.ram_bank);
+        buffer.push(self.mode);
+        buffer.push(self.access_index);
+        buffer.push(self.access_flags);
+        buffer.push(self.response);
+        buffer.push(self.ir_led as u8);
+        self.clock.dump_savestate(buffer);
+    }
+
+    fn load_savestate<'a>(
+        &mut self,
+        buffer: &mut SavestateStream<'a>,
+    ) -> Result<(), LoadSavestateError> {
+        self.rom_bank = read_savestate_byte(buffer)?;
+        self.ram_bank = read_savestate_byte(buffer)?;
+        self.mode = read_savestate_byte(buffer)?;
+        self.access_index = read_savestate_byte(buffer)?;
+        self.access_flags = read_savestate_byte(buffer)?;
+        self.response = read_savestate_byte(buffer)?;
+        self.ir_led = read_savestate_bool(buffer)?;
+        self.clock.load_savestate(buffer)
+    }
+}
+
+impl MemoryBankController for HuC3 {
+    fn rom_bank(&self) -> u16 {
+        u16::from(self.rom_bank)
+    }
+
+    fn ram_bank(&self) -> u8 {
+        self.ram_bank
+    }
+
+    fn ram_enabled(&self) -> bool {
+        matches!(self.mode, 0x0 | 0xA..=0xE)
+    }
+
+    fn write_rom(&mut self, address: usize, value: u8) {
+        match address {
+            0..=0x1FFF => {
+                // select what is mapped to the ram area
+                self.mode = value & 0xF;
+            }
+            0x2000..=0x3FFF => {
+                // change rom bank
+                self.rom_bank = cmp::max(value & 0x7F, 1);
+            }
+            0x4000..=0x5FFF => {
+                // change ram bank
+                self.ram_bank = value & 3;
+            }
+            _ => {}
+        }
+    }
+
+    fn read_ram(&self, address: usize, ram: &[u8]) -> u8 {
+        match self.mode {
+            0xC if self.access_flags == 2 => 1,
+            0xC => self.response,
+            // the clock is always ready for the next command
+            0xD => 1,
+            0xE => IR_NO_LIGHT,
+            _ => mirror(self.relative_ram_address(address), ram.len())
+                .map_or(0xFF, |address| ram[address]),
+        }
+    }
+
+    fn write_ram(&mut self, address: usize, value: u8, ram: &mut [u8]) -> bool {
+        match self.mode {
+            0xA => {
+                return write_mirrored(ram, self.relative_ram_address(address), value);
+            }
+            0xB => return self.command(value),
+            0xE => self.ir_led = value & 1 != 0,
+            // mode 0 maps the ram as read only, and commands run right away
+            // so there is nothing to do when the game signals them as ready
+            _ => {}
+        }
+
+        false
+    }
+
+    fn set_clock(&mut self, clock: Rc<dyn Clock>) {
+        self.clock.set_clock(clock);
+    }
+
+    fn dump_save_footer(&self, buffer: &mut Vec<u8>) {
+        self.clock.dump_footer(buffer);
+    }
+
+    fn load_save_footer(&mut self, footer: &[u8]) -> bool {
+        self.clock.load_footer(footer)
+    }
+}
+
+/// The HuC3 clock, counting minutes and days.
+/// Like the MBC3 real-time clock, it keeps running while the emulator is not.
+pub struct HuC3Clock {
+    clock: Rc<dyn Clock>,
+    /// Minutes elapsed in the current day
+    minutes: u16,
+    days: u16,
+    alarm_minutes: u16,
+    alarm_days: u16,
+    alarm_enabled: bool,
+    last_update: u64,
+}
+
+impl HuC3Clock {
+    pub fn new(clock: Rc<dyn Clock>) -> HuC3Clock {
+        HuC3Clock {
+            last_update: clock.now(),
+            clock,
+            minutes: 0,
+            days: 0,
+            alarm_minutes: 0,
+            alarm_days: 0,
+            alarm_enabled: false,
+        }
+    }
+
+    /// Replaces the source of time. The counters keep their current values.
+    pub fn set_clock(&mut self, clock: Rc<dyn Clock>) {
+        self.update();
+        self.last_update = clock.now();
+        self.clock = clock;
+    }
+
+    /// Returns the minutes elapsed in the current day and the number of days
+    pub fn time(&self) -> (u16, u16) {
+        let elapsed_minutes = self.clock.now().saturating_sub(self.last_update) / 60;
+        let minutes = u64::from(self.minutes) + elapsed_minutes;
+        let days = u64::from(self.days) + minutes / u64::from(MINUTES_PER_DAY);
+        ((minutes % u64::from(MINUTES_PER_DAY)) as u16, days as u16)
+    }
+
+    /// Returns a nibble of the registers: the minutes are at 0-2, the days at 3-6,
+    /// and the alarm is at 0x58-0x5F
+    fn nibble(&self, index: u8) -> u8 {
+        let (minutes, days) = self.time();
+        let value = match index {
+            0..=2 => minutes >> (index * 4),
+            3..=6 => days >> ((index - 3) * 4),
+            0x58..=0x5A => self.alarm_minutes >> ((index - 0x58) * 4),
+            0x5B..=0x5E => self.alarm_days >> ((index - 0x5B) * 4),
+            0x5F => self.alarm_enabled as u16,
+            _ => 0,
+        };
+        (value & 0xF) as u8
+    }
+
+    /// Returns true if the registers changed
+    fn set_nibble(&mut self, index: u8, value: u8) -> bool {
+        self.update();
+        let previous = self.registers();
+        let value = u16::from(value);
+        match index {
+            0..=2 => set_nibble(&mut self.minutes, index, value),
+            3..=6 => set_nibble(&mut self.days, index - 3, value),
+            0x58..=0x5A => set_nibble(&mut self.alarm_minutes, index - 0x58, value),
+            0x5B..=0x5E => set_nibble(&mut self.alarm_days, index - 0x5B, value),
+            0x5F => self.alarm_enabled = value & 1 != 0,
+            _ => {}
+        }
+        self.registers() != previous
+    }
+
+    fn registers(&self) -> (u16, u16, u16, u16, bool) {
+        (
+            self.minutes,
+            self.days,
+            self.alarm_minutes,
+            self.alarm_days,
+            self.alarm_enabled,
+        )
+    }
+
+    /// Dumps the clock in the footer format used by SameBoy: the UNIX timestamp
+    /// of the save as a 64-bit little endian value, followed by the 16-bit minutes, days,
+    /// alarm minutes and alarm days, and the alarm enable flag
+    pub fn dump_footer(&self, buffer: &mut Vec<u8>) {
+        let (minutes, days) = self.time();
+        buffer.extend_from_slice(&self.clock.now().to_le_bytes());
+        for value in [minutes, days, self.alarm_minutes, self.alarm_days].iter() {
+            buffer.extend_from_slice(&value.to_le_bytes());
+        }
+        buffer.push(self.alarm_enabled as u8);
+    }
+
+    /// Loads a footer made by `dump_footer` or by SameBoy,
+    /// advancing the clock by the time elapsed since it was saved.
+    /// Returns false if the footer has an unknown size.
+    pub fn load_footer(&mut self, footer: &[u8]) -> bool {
+        if footer.len() != FOOTER_SIZE {
+            return false;
+        }
+
+        let mut timestamp = [0; 8];
+        timestamp.copy_from_slice(&footer[..8]);
+        let mut values = footer[8..16]
+            .chunks(2)
+            .map(|chunk| u16::from_le_bytes([chunk[0], chunk[1]]));
+        self.minutes = values.next().unwrap_or(0) % MINUTES_PER_DAY;
+        self.days = values.next().unwrap_or(0);
+        self.alarm_minutes = values.next().unwrap_or(0);
+        self.alarm_days = values.next().unwrap_or(0);
+        self.alarm_enabled = footer[16] & 1 != 0;
+
+        self.last_update = u64::from_le_bytes(timestamp);
+        self.update();
+        true
+    }
+
+    fn update(&mut self) {
+        let (minutes, days) = self.time();
+        // keep the seconds that don't make a full minute yet
+        let now = self.clock.now();
+        let elapsed = now.saturating_sub(self.last_update);
+        self.last_update = now - elapsed % 60;
+        self.minutes = minutes;
+        self.days = days;
+    }
+}
+
+impl Savestate for HuC3Clock {
+    fn dump_savestate(&self, buffer: &mut Vec<u8>) {
+        let (minutes, days) = self.time();
+        write_savestate_u16(buffer, minutes);
+        write_savestate_u16(buffer, days);
+        write_savestate_u16(buffer, self.alarm_minutes);
+        write_savestate_u16(buffer, self.alarm_days);
+        buffer.push(self.alarm_enabled as u8);
+    }
+
+    fn load_savestate<'a>(
+        &mut self,
+        buffer: &mut SavestateStream<'a>,
+    ) -> Result<(), LoadSavestateError> {
+        self.minutes = read_savestate_u16(buffer)? % MINUTES_PER_DAY;
+        self.days = read_savestate_u16(buffer)?;
+        self.alarm_minutes = read_savestate_u16(buffer)?;
+        self.alarm_days = read_savestate_u16(buffer)?;
+        self.alarm_enabled = read_savestate_bool(buffer)?;
+        self.last_update = self.clock.now();
+        Ok(())
+    }
+}
+
+fn set_nibble(register: &mut u16, index: u8, value: u16) {
+    let shift = index * 4;
+    *register = *register & !(0xF << shift) | value << shift;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::clock::FakeClock;
+
+    fn huc3() -> (Rc<FakeClock>, HuC3) {
+        let clock = Rc::new(FakeClock::new(1000));
+        let mut mbc = HuC3::new(&[]);
+        mbc.set_clock(clock.clone());
+        (clock, mbc)
+    }
+
+    fn command(mbc: &mut HuC3, value: u8) -> bool {
+        mbc.write_rom(0, 0xB);
+        mbc.write_ram(0xA000, value, &mut [])
+    }
+
+    fn set_index(mbc: &mut HuC3, index: u8) {
+        command(mbc, 0x40 | index & 0xF);
+        command(mbc, 0x50 | index >> 4);
+    }
+
+    fn read_nibble(mbc: &mut HuC3) -> u8 {
+        command(mbc, 0x10);
+        mbc.write_rom(0, 0xC);
+        mbc.read_ram(0xA000, &[])
+    }
+
+    #[test]
+    fn bank_switching() {
+        let (_, mut mbc) = huc3();
+        mbc.write_rom(0x2000, 0xFF);
+        assert_eq!(mbc.rom_bank(), 0x7F);
+        mbc.write_rom(0x2000, 0);
+        assert_eq!(mbc.rom_bank(), 1);
+        mbc.write_rom(0x4000, 3);
+        assert_eq!(mbc.ram_bank(), 3);
+    }
+
+    #[test]
+    fn ram_modes() {
+        let (_, mut mbc) = huc3();
+        let mut ram = vec![0; 0x2000];
+        mbc.write_rom(0, 0xA);
+        mbc.write_ram(0xA010, 0x42, &mut ram);
+        assert_eq!(ram[0x10], 0x42);
+
+        // read only
+        mbc.write_rom(0, 0);
+        mbc.write_ram(0xA010, 0x24, &mut ram);
+        assert_eq!(mbc.read_ram(0xA010, &ram), 0x42);
+
+        mbc.write_rom(0, 0x5);
+        assert!(!mbc.ram_enabled());
+    }
+
+    #[test]
+    fn write_and_read_clock() {
+        let (clock, mut mbc) = huc3();
+
+        // 1439 minutes, 2 days
+        set_index(&mut mbc, 0);
+        for &nibble in [0xF, 0x9, 0x5, 0x2, 0x0, 0x0, 0x0].iter() {
+            command(&mut mbc, 0x30 | nibble);
+        }
+
+        clock.advance(60);
+        set_index(&mut mbc, 0);
+        let nibbles: Vec<u8> = (0..7).map(|_| read_nibble(&mut mbc)).collect();
+        assert_eq!(nibbles, vec![0, 0, 0, 3, 0, 0, 0]);
+    }
+
+    #[test]
+    fn clock_writes_change_the_save() {
+        let (_, mut mbc) = huc3();
+        set_index(&mut mbc, 0);
+        assert!(command(&mut mbc, 0x25));
+        assert!(!command(&mut mbc, 0x25));
+        assert!(!command(&mut mbc, 0x10));
+    }
+
+    #[test]
+    fn alarm_registers() {
+        let (_, mut mbc) = huc3();
+        set_index(&mut mbc, 0x5F);
+        command(&mut mbc, 0x21);
+        assert!(mbc.clock.alarm_enabled);
+        assert_eq!(read_nibble(&mut mbc), 1);
+    }
+
+    #[test]
+    fn infrared_register() {
+        let (_, mut mbc) = huc3();
+        mbc.write_rom(0, 0xE);
+        mbc.write_ram(0xA000, 1, &mut []);
+        assert!(mbc.ir_led);
+        assert_eq!(mbc.read_ram(0xA000, &[]), IR_NO_LIGHT);
+    }
+
+    #[test]
+    fn footer_advances_by_elapsed_time() {
+        let (clock, mut mbc) = huc3();
+        set_index(&mut mbc, 0);
+        command(&mut mbc, 0x35);
+
+        let mut footer = Vec::new();
+        mbc.dump_save_footer(&mut footer);
+        assert_eq!(footer.len(), FOOTER_SIZE);
+
+        clock.advance(24 * 60 * 60);
+        let (_, mut other) = huc3();
+        other.set_clock(clock.clone());
+        assert!(other.load_save_footer(&footer));
+        assert_eq!(other.clock.time(), (5, 1));
+        assert!(!other.load_save_footer(&footer[1..]));
+    }
+}
@@ -0,0 +1,101 @@
+use super::mbc5::MBC5;
+use super::MemoryBankController;
+use crate::cartridge::cartridge_capability::CartridgeCapability;
+use crate::util::savestate::{LoadSavestateError, Savestate, SavestateStream};
+
+/// Names found in the first bank of Li Cheng games
+const SIGNATURES: [&[u8]; 2] = [b"LI CHENG", b"LICHENG"];
+
+/// Li Cheng mapper, used by bootleg games. It is an MBC5 that ignores writes
+/// to its ROM bank register past 0x2100, which games use to confuse other controllers.
+pub struct LiCheng {
+    mbc5: MBC5,
+}
+
+impl LiCheng {
+    pub fn new(capabilities: &[CartridgeCapability]) -> LiCheng {
+        LiCheng {
+            mbc5: MBC5::new(capabilities),
+        }
+    }
+
+    /// Li Cheng games declare an MBC5 and credit the company in their first bank
+    pub fn is_li_cheng(capabilities: &[CartridgeCapability], rom: &[u8]) -> bool {
+        if !capabilities.contains(&CartridgeCapability::MBC5) {
+            return false;
+        }
+
+        let bank = &rom[..rom.len().min(0x4000)];
+        SIGNATURES.iter().any(|signature| {
+            bank.windows(signature.len())
+                .any(|window| window == *signature)
+        })
+    }
+}
+
+impl Savestate for LiCheng {
+    fn dump_savestate(&self, buffer: &mut Vec<u8>) {
+        self.mbc5.dump_savestate(buffer);
+    }
+
+    fn load_savestate<'a>(
+        &mut self,
+        buffer: &mut SavestateStream<'a>,
+    ) -> Result<(), LoadSavestateError> {
+        self.mbc5.load_savestate(buffer)
+    }
+}
+
+impl MemoryBankController for LiCheng {
+    fn rom_bank(&self) -> u16 {
+        self.mbc5.rom_bank()
+    }
+
+    fn ram_bank(&self) -> u8 {
+        self.mbc5.ram_bank()
+    }
+
+    fn ram_enabled(&self) -> bool {
+        self.mbc5.ram_enabled()
+    }
+
+    fn write_rom(&mut self, address: usize, value: u8) {
+        if !(0x2101..=0x2FFF).contains(&address) {
+            self.mbc5.write_rom(address, value);
+        }
+    }
+
+    fn rumble(&self) -> bool {
+        self.mbc5.rumble()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::tests::test_rom::test_rom;
+
+    #[test]
+    fn ignored_bank_writes() {
+        let mut mbc = LiCheng::new(&[]);
+        mbc.write_rom(0x2000, 4);
+        assert_eq!(mbc.rom_bank(), 4);
+        mbc.write_rom(0x2100, 5);
+        assert_eq!(mbc.rom_bank(), 5);
+        mbc.write_rom(0x2180, 6);
+        assert_eq!(mbc.rom_bank(), 5);
+        mbc.write_rom(0x3000, 1);
+        assert_eq!(mbc.rom_bank(), 0x105);
+    }
+
+    #[test]
+    fn detection() {
+        let mut rom = test_rom("TEST");
+        let mbc5 = [CartridgeCapability::MBC5];
+        assert!(!LiCheng::is_li_cheng(&mbc5, &rom));
+
+        rom[0x200..0x208].copy_from_slice(b"LI CHENG");
+        assert!(LiCheng::is_li_cheng(&mbc5, &rom));
+        assert!(!LiCheng::is_li_cheng(&[CartridgeCapability::MBC1], &rom));
+    }
+}
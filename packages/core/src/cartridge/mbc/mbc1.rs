@@ -1,13 +1,22 @@
 use super::MemoryBankController;
 use crate::cartridge::cartridge_capability::CartridgeCapability;
+use crate::cartridge::cartridge_metadata::{NINTENDO_LOGO, NINTENDO_LOGO_OFFSET};
 use crate::util::savestate::{
-    read_savestate_bool, read_savestate_byte, LoadSavestateError, Savestate, SavestateStream,
+    read_savestate_bool, read_savestate_byte, read_savestate_enum, LoadSavestateError, Savestate,
+    SavestateStream,
 };
+use std::cmp;
 
 pub struct MBC1 {
     mode: MBC1Mode,
     ram_enabled: bool,
-    register: u8,
+    /// 5-bit register selecting the ROM bank in 0x4000-0x7FFF
+    rom_bank: u8,
+    /// 2-bit register selecting the RAM bank or the upper bits of the ROM bank
+    bank_set: u8,
+    /// Multicarts (MBC1M) wire the bank set register one bit lower,
+    /// ignoring the upper bit of the ROM bank register
+    multicart: bool,
 }
 
 impl MBC1 {
@@ -15,10 +24,36 @@ impl MBC1 {
         MBC1 {
             mode: MBC1Mode::MaxROM,
             ram_enabled: false,
-            register: 1,
+            rom_bank: 1,
+            bank_set: 0,
+            multicart: false,
         }
     }
 
+    pub fn multicart(capabilities: &[CartridgeCapability]) -> MBC1 {
+        MBC1 {
+            multicart: true,
+            ..MBC1::new(capabilities)
+        }
+    }
+
+    /// Detects MBC1M multicarts, which are made of multiple 256KB games
+    /// that each start with their own header
+    pub fn is_multicart(rom: &[u8]) -> bool {
+        if rom.len() != 0x10_0000 {
+            return false;
+        }
+
+        let games = rom
+            .chunks(0x4_0000)
+            .filter(|game| {
+                game[NINTENDO_LOGO_OFFSET..NINTENDO_LOGO_OFFSET + NINTENDO_LOGO.len()]
+                    == NINTENDO_LOGO
+            })
+            .count();
+        games > 1
+    }
+
     pub fn mode(&self) -> &MBC1Mode {
         &self.mode
     }
@@ -31,32 +66,20 @@ impl MBC1 {
         self.ram_enabled = enabled;
     }
 
-    fn set_rom_bank(&mut self, value: u8) {
-        let mask = self.rom_bank_mask();
-        let value = if value == 0x60 || value == 0x40 || value == 0x20 || value == 0 {
-            value + 1
-        } else {
-            value
-        };
-        self.register = (!mask & self.register) | (value & mask);
-    }
-
-    fn set_ram_bank(&mut self, value: u8) {
-        let mask = self.rom_bank_mask();
-        let value = if self.mode == MBC1Mode::MaxROM {
-            0
+    fn bank_set_shift(&self) -> u8 {
+        if self.multicart {
+            4
         } else {
-            value & 3
-        } << 5;
-
-        self.register = (self.register & mask) | value;
+            5
+        }
     }
 
-    fn rom_bank_mask(&self) -> u8 {
-        if self.mode == MBC1Mode::MaxRAM {
-            0b1_1111
+    /// Returns the bank mapped to 0x0000-0x3FFF, which is affected by the bank set in mode 1
+    fn lower_rom_bank(&self) -> u16 {
+        if let MBC1Mode::MaxRAM = self.mode() {
+            u16::from(self.bank_set << self.bank_set_shift())
         } else {
-            0xFF
+            0
         }
     }
 }
@@ -65,35 +88,37 @@ impl Savestate for MBC1 {
     fn dump_savestate(&self, buffer: &mut Vec<u8>) {
         buffer.push(self.mode as u8);
         buffer.push(self.ram_enabled as u8);
-        buffer.push(self.register);
+        buffer.push(self.bank_set << 5 | self.rom_bank);
     }
 
     fn load_savestate<'a>(
         &mut self,
         buffer: &mut SavestateStream<'a>,
     ) -> Result<(), LoadSavestateError> {
-        self.mode = buffer
-            .next()
-            .cloned()
-            .and_then(MBC1Mode::from)
-            .ok_or(LoadSavestateError::InvalidSavestate)?;
+        self.mode = read_savestate_enum(buffer, "MBC1Mode", MBC1Mode::from)?;
         self.ram_enabled = read_savestate_bool(buffer)?;
-        self.register = read_savestate_byte(buffer)?;
+        let register = read_savestate_byte(buffer)?;
+        self.rom_bank = register & 0b1_1111;
+        self.bank_set = (register >> 5) & 0b11;
         Ok(())
     }
 }
 
 impl MemoryBankController for MBC1 {
     fn rom_bank(&self) -> u16 {
-        let mask = self.rom_bank_mask();
-        u16::from(self.register & mask)
+        let rom_bank = if self.multicart {
+            self.rom_bank & 0b1111
+        } else {
+            self.rom_bank
+        };
+        u16::from(self.bank_set << self.bank_set_shift() | rom_bank)
     }
 
     fn ram_bank(&self) -> u8 {
         if self.mode == MBC1Mode::MaxROM {
             0
         } else {
-            self.register >> 5
+            self.bank_set
         }
     }
 
@@ -101,26 +126,28 @@ impl MemoryBankController for MBC1 {
         self.ram_enabled
     }
 
+    fn relative_rom_address(&self, address: usize) -> usize {
+        let bank = if address < 0x4000 {
+            self.lower_rom_bank()
+        } else {
+            self.rom_bank()
+        };
+        (address & 0x3FFF) + bank as usize * 0x4000
+    }
+
     fn write_rom(&mut self, address: usize, value: u8) {
         match address {
             0..=0x1FFF => {
                 // toggle ram bank
-                self.set_ram_enabled(value == 0x0A);
+                self.set_ram_enabled(value & 0xF == 0x0A);
             }
             0x2000..=0x3FFF => {
-                // change rom bank
-                self.set_rom_bank((value & 0b1_1111) | (self.rom_bank() as u8 & 0b110_0000));
+                // change rom bank, where 0 is treated as 1 before being masked for multicarts
+                self.rom_bank = cmp::max(value & 0b1_1111, 1);
             }
             0x4000..=0x5FFF => {
-                let value = value & 0b11;
                 // change ram bank/rom bank set
-                if let MBC1Mode::MaxRAM = self.mode() {
-                    if self.ram_enabled {
-                        self.set_ram_bank(value);
-                    }
-                } else {
-                    self.set_rom_bank((self.rom_bank() as u8 & 0b1_1111) | (value << 5));
-                }
+                self.bank_set = value & 0b11;
             }
             0x6000..=0x7FFF => {
                 // change mode
@@ -211,4 +238,66 @@ mod tests {
         mbc.write_rom(0x4000, 3);
         assert_eq!(mbc.ram_bank(), 3);
     }
+
+    #[test]
+    fn ram_bank_ignored_in_mode_0() {
+        let mut mbc = MBC1::new(&[]);
+        mbc.write_rom(0x4000, 2);
+        assert_eq!(mbc.ram_bank(), 0);
+        mbc.write_rom(0x6000, 1);
+        assert_eq!(mbc.ram_bank(), 2);
+    }
+
+    #[test]
+    fn lower_region_remap() {
+        let mut mbc = MBC1::new(&[]);
+        mbc.write_rom(0x4000, 2);
+        assert_eq!(mbc.relative_rom_address(0x0123), 0x0123);
+
+        mbc.write_rom(0x6000, 1);
+        assert_eq!(mbc.relative_rom_address(0x0123), 0x40 * 0x4000 + 0x0123);
+        assert_eq!(mbc.relative_rom_address(0x4123), 0x41 * 0x4000 + 0x0123);
+    }
+
+    #[test]
+    fn multicart_banking() {
+        let mut mbc = MBC1::multicart(&[]);
+        mbc.write_rom(0x2000, 0x12);
+        mbc.write_rom(0x4000, 3);
+        assert_eq!(mbc.rom_bank(), 0x32);
+        assert_eq!(mbc.relative_rom_address(0), 0);
+
+        // the zero check uses all 5 bits, so 0x10 selects the first bank of the game
+        mbc.write_rom(0x2000, 0x10);
+        assert_eq!(mbc.rom_bank(), 0x30);
+
+        mbc.write_rom(0x6000, 1);
+        assert_eq!(mbc.relative_rom_address(0), 0x30 * 0x4000);
+    }
+
+    #[test]
+    fn multicart_detection() {
+        let mut rom = vec![0; 0x10_0000];
+        rom[NINTENDO_LOGO_OFFSET..NINTENDO_LOGO_OFFSET + NINTENDO_LOGO.len()]
+            .copy_from_slice(&NINTENDO_LOGO);
+        assert!(!MBC1::is_multicart(&rom));
+
+        let offset = 0x4_0000 + NINTENDO_LOGO_OFFSET;
+        rom[offset..offset + NINTENDO_LOGO.len()].copy_from_slice(&NINTENDO_LOGO);
+        assert!(MBC1::is_multicart(&rom));
+        assert!(!MBC1::is_multicart(&rom[..0x8_0000]));
+    }
+
+    #[test]
+    fn savestate_round_trip() {
+        let mut mbc = MBC1::new(&[]);
+        mbc.write_rom(0x2000, 0x15);
+        mbc.write_rom(0x4000, 2);
+        let mut buffer = Vec::new();
+        mbc.dump_savestate(&mut buffer);
+
+        let mut loaded = MBC1::new(&[]);
+        assert!(loaded.load_savestate(&mut buffer.iter()).is_ok());
+        assert_eq!(loaded.rom_bank(), 0x55);
+    }
 }
@@ -4,8 +4,11 @@ use crate::util::savestate::{
     read_savestate_bool, read_savestate_byte, LoadSavestateError, Savestate, SavestateStream,
 };
 use std::cmp;
+use std::mem;
+
+/// Size of the built-in RAM, which stores 512 half-bytes
+const RAM_SIZE: usize = 512;
 
-// TODO: RAM is 512*4bit, maybe should only return the 4 necessary bits on read
 pub struct MBC2 {
     rom_bank: u8,
     ram_enabled: bool,
@@ -66,6 +69,23 @@ impl MemoryBankController for MBC2 {
             _ => {}
         }
     }
+
+    fn ram_size(&self, _header_ram_size: usize) -> usize {
+        RAM_SIZE
+    }
+
+    // only the lower 4 bits are stored, and the RAM is echoed across the whole area
+    fn read_ram(&self, address: usize, ram: &[u8]) -> u8 {
+        ram.get((address - 0xA000) % RAM_SIZE)
+            .map_or(0xFF, |value| value | 0xF0)
+    }
+
+    fn write_ram(&mut self, address: usize, value: u8, ram: &mut [u8]) -> bool {
+        match ram.get_mut((address - 0xA000) % RAM_SIZE) {
+            Some(cell) => mem::replace(cell, value & 0xF) != value & 0xF,
+            None => false,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -92,6 +112,24 @@ mod tests {
         assert_eq!(mbc.rom_bank(), 1);
     }
 
+    #[test]
+    fn ram_half_bytes() {
+        let mut mbc = MBC2::new(&[]);
+        let mut ram = vec![0; mbc.ram_size(0)];
+        mbc.write_ram(0xA000, 0xAB, &mut ram);
+        assert_eq!(ram[0], 0xB);
+        assert_eq!(mbc.read_ram(0xA000, &ram), 0xFB);
+    }
+
+    #[test]
+    fn ram_echo() {
+        let mut mbc = MBC2::new(&[]);
+        let mut ram = vec![0; mbc.ram_size(0)];
+        mbc.write_ram(0xA1FF, 5, &mut ram);
+        assert_eq!(mbc.read_ram(0xA3FF, &ram), 0xF5);
+        assert_eq!(mbc.read_ram(0xBFFF, &ram), 0xF5);
+    }
+
     #[test]
     fn enable_ram() {
         let mut mbc = MBC2::new(&[]);
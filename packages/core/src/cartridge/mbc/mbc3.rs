@@ -1,8 +1,9 @@
 use super::real_time_clock::{RTCRegister, RealTimeClock};
-use super::MemoryBankController;
+use super::{mirror, write_mirrored, MemoryBankController};
 use crate::cartridge::cartridge_capability::CartridgeCapability;
 use crate::util::savestate::{
-    read_savestate_bool, read_savestate_byte, LoadSavestateError, Savestate, SavestateStream,
+    read_savestate_bool, read_savestate_byte, read_savestate_enum, LoadSavestateError, Savestate,
+    SavestateStream,
 };
 use std::cmp;
 
@@ -12,12 +13,15 @@ pub struct MBC3 {
     ram_bank: u8,
     mode: MBC3Mode,
     clock: Option<RealTimeClock>,
+    /// MBC30 has an extra ROM bank bit and RAM bank bit,
+    /// for 4MB of ROM and 64KB of RAM
+    mbc30: bool,
 }
 
 impl MBC3 {
     pub fn new(capabilities: &[CartridgeCapability]) -> MBC3 {
         let clock = if capabilities.contains(&CartridgeCapability::Timer) {
-            Some(RealTimeClock::new())
+            Some(RealTimeClock::default())
         } else {
             None
         };
@@ -27,9 +31,22 @@ impl MBC3 {
             ram_bank: 0,
             mode: MBC3Mode::RAM,
             clock,
+            mbc30: false,
         }
     }
 
+    pub fn mbc30(capabilities: &[CartridgeCapability]) -> MBC3 {
+        MBC3 {
+            mbc30: true,
+            ..MBC3::new(capabilities)
+        }
+    }
+
+    /// MBC30 is only needed for cartridges that don't fit in a regular MBC3
+    pub fn is_mbc30(rom_size: usize, ram_size: usize) -> bool {
+        rom_size > 0x20_0000 || ram_size > 0x8000
+    }
+
     pub fn mode(&self) -> &MBC3Mode {
         &self.mode
     }
@@ -59,16 +76,11 @@ impl Savestate for MBC3 {
         self.rom_bank = read_savestate_byte(buffer)?;
         self.ram_enabled = read_savestate_bool(buffer)?;
         self.ram_bank = read_savestate_byte(buffer)?;
-        self.mode = buffer
-            .next()
-            .cloned()
-            .and_then(MBC3Mode::from)
-            .ok_or(LoadSavestateError::InvalidSavestate)?;
+        self.mode = read_savestate_enum(buffer, "MBC3Mode", MBC3Mode::from)?;
         Ok(())
     }
 }
 
-// TODO: implement RTC correctly
 impl MemoryBankController for MBC3 {
     fn rom_bank(&self) -> u16 {
         u16::from(self.rom_bank)
@@ -90,7 +102,8 @@ impl MemoryBankController for MBC3 {
             }
             0x2000..=0x3FFF => {
                 // change rom bank
-                self.rom_bank = cmp::max(value & 0x7F, 1);
+                let mask = if self.mbc30 { 0xFF } else { 0x7F };
+                self.rom_bank = cmp::max(value & mask, 1);
             }
             0x4000..=0x5FFF => {
                 // change ram bank/rtc register
@@ -99,7 +112,7 @@ impl MemoryBankController for MBC3 {
                         // ram bank
                         if self.ram_enabled() {
                             self.mode = MBC3Mode::RAM;
-                            self.ram_bank = value & 3;
+                            self.ram_bank = if self.mbc30 { value } else { value & 3 };
                         }
                     }
                     0x8..=0xC => {
@@ -117,23 +130,40 @@ impl MemoryBankController for MBC3 {
             0x6000..=0x7FFF => {
                 // latch clock data
                 if let Some(clock) = &mut self.clock {
-                    clock.latch();
+                    clock.latch(value);
                 }
             }
             _ => {}
         }
     }
 
+    fn real_time_clock(&self) -> Option<&RealTimeClock> {
+        self.clock.as_ref()
+    }
+
+    fn real_time_clock_mut(&mut self) -> Option<&mut RealTimeClock> {
+        self.clock.as_mut()
+    }
+
     fn read_ram(&self, address: usize, buffer: &[u8]) -> u8 {
         if let MBC3Mode::RAM = self.mode() {
-            let address = self.relative_ram_address(address);
-            buffer[address]
+            mirror(self.relative_ram_address(address), buffer.len())
+                .map_or(0xFF, |address| buffer[address])
         } else if let Some(clock) = self.clock() {
             clock.active_value()
         } else {
             0 // TODO: i should really find a default for these
         }
     }
+
+    fn write_ram(&mut self, address: usize, value: u8, ram: &mut [u8]) -> bool {
+        match (self.mode, &mut self.clock) {
+            (MBC3Mode::RAM, _) => write_mirrored(ram, self.relative_ram_address(address), value),
+            // setting or halting the clock has to be saved like the RAM
+            (MBC3Mode::RTC, Some(clock)) => clock.write(value),
+            _ => false,
+        }
+    }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq)]
@@ -155,6 +185,8 @@ impl MBC3Mode {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::util::clock::FakeClock;
+    use std::rc::Rc;
 
     #[test]
     fn rom_bank_switching() {
@@ -203,6 +235,40 @@ mod tests {
         assert_eq!(mbc.ram_bank(), 3);
     }
 
+    #[test]
+    fn mbc30_banks() {
+        let mut mbc = MBC3::mbc30(&[]);
+        mbc.set_ram_enabled(true);
+        mbc.write_rom(0x2000, 0xFF);
+        assert_eq!(mbc.rom_bank(), 0xFF);
+        mbc.write_rom(0x4000, 7);
+        assert_eq!(mbc.ram_bank(), 7);
+
+        let mut mbc = MBC3::new(&[]);
+        mbc.set_ram_enabled(true);
+        mbc.write_rom(0x2000, 0xFF);
+        assert_eq!(mbc.rom_bank(), 0x7F);
+        mbc.write_rom(0x4000, 7);
+        assert_eq!(mbc.ram_bank(), 3);
+    }
+
+    #[test]
+    fn rtc_write_and_latch() {
+        let clock = Rc::new(FakeClock::new(0));
+        let mut mbc = MBC3::new(&[CartridgeCapability::Timer]);
+        mbc.real_time_clock_mut().unwrap().set_clock(clock.clone());
+        mbc.set_ram_enabled(true);
+
+        mbc.write_rom(0x4000, 9);
+        mbc.write_ram(0xA000, 30, &mut []);
+        clock.advance(61);
+        mbc.write_rom(0x6000, 0);
+        mbc.write_rom(0x6000, 1);
+        assert_eq!(mbc.read_ram(0xA000, &[]) & 0x3F, 31);
+        mbc.write_rom(0x4000, 8);
+        assert_eq!(mbc.read_ram(0xA000, &[]) & 0x3F, 1);
+    }
+
     #[test]
     fn rtc_register_switching() {
         let mut mbc = MBC3::new(&[CartridgeCapability::Timer]);
@@ -5,31 +5,56 @@ use crate::util::savestate::{
     LoadSavestateError, Savestate, SavestateStream,
 };
 
+/// Bit of the RAM bank register that controls the motor on rumble cartridges
+const RUMBLE_BIT: u8 = 0b1000;
+
 pub struct MBC5 {
     rom_bank: u16,
     ram_enabled: bool,
     ram_bank: u8,
+    has_rumble: bool,
+    rumble: bool,
 }
 
 impl MBC5 {
-    pub fn new(_capabilities: &[CartridgeCapability]) -> MBC5 {
+    pub fn new(capabilities: &[CartridgeCapability]) -> MBC5 {
         MBC5 {
-            rom_bank: 0,
+            rom_bank: 1,
             ram_enabled: false,
             ram_bank: 0,
+            has_rumble: capabilities.contains(&CartridgeCapability::Rumble),
+            rumble: false,
         }
     }
 
     pub fn set_ram_enabled(&mut self, enabled: bool) {
         self.ram_enabled = enabled;
     }
+
+    fn ram_bank_register(&self) -> u8 {
+        if self.rumble {
+            self.ram_bank | RUMBLE_BIT
+        } else {
+            self.ram_bank
+        }
+    }
+
+    // rumble cartridges use the upper bit of the RAM bank for the motor
+    fn set_ram_bank_register(&mut self, value: u8) {
+        if self.has_rumble {
+            self.ram_bank = value & 0x7;
+            self.rumble = value & RUMBLE_BIT != 0;
+        } else {
+            self.ram_bank = value & 0xF;
+        }
+    }
 }
 
 impl Savestate for MBC5 {
     fn dump_savestate(&self, buffer: &mut Vec<u8>) {
         write_savestate_u16(buffer, self.rom_bank);
         buffer.push(self.ram_enabled as u8);
-        buffer.push(self.ram_bank);
+        buffer.push(self.ram_bank_register());
     }
 
     fn load_savestate<'a>(
@@ -38,7 +63,7 @@ impl Savestate for MBC5 {
     ) -> Result<(), LoadSavestateError> {
         self.rom_bank = read_savestate_u16(buffer)?;
         self.ram_enabled = read_savestate_bool(buffer)?;
-        self.ram_bank = read_savestate_byte(buffer)?;
+        self.set_ram_bank_register(read_savestate_byte(buffer)?);
         Ok(())
     }
 }
@@ -72,13 +97,15 @@ impl MemoryBankController for MBC5 {
             }
             0x4000..=0x5FFF => {
                 // change ram bank
-                if self.ram_enabled {
-                    self.ram_bank = value & 0xF;
-                }
+                self.set_ram_bank_register(value);
             }
             _ => {}
         }
     }
+
+    fn rumble(&self) -> bool {
+        self.rumble
+    }
 }
 
 #[cfg(test)]
@@ -122,4 +149,36 @@ mod tests {
         mbc.write_rom(0x4000, 0x1F);
         assert_eq!(mbc.ram_bank(), 0x0F);
     }
+
+    #[test]
+    fn rumble() {
+        let mut mbc = MBC5::new(&[CartridgeCapability::MBC5, CartridgeCapability::Rumble]);
+        mbc.write_rom(0x4000, 0x0B);
+        assert!(mbc.rumble());
+        assert_eq!(mbc.ram_bank(), 3);
+
+        mbc.write_rom(0x4000, 0x03);
+        assert!(!mbc.rumble());
+    }
+
+    #[test]
+    fn no_rumble() {
+        let mut mbc = MBC5::new(&[]);
+        mbc.write_rom(0x4000, 0x0B);
+        assert!(!mbc.rumble());
+        assert_eq!(mbc.ram_bank(), 0x0B);
+    }
+
+    #[test]
+    fn rumble_savestate() {
+        let mut mbc = MBC5::new(&[CartridgeCapability::MBC5, CartridgeCapability::Rumble]);
+        mbc.write_rom(0x4000, 0x0A);
+        let mut buffer = Vec::new();
+        mbc.dump_savestate(&mut buffer);
+
+        let mut loaded = MBC5::new(&[CartridgeCapability::MBC5, CartridgeCapability::Rumble]);
+        assert!(loaded.load_savestate(&mut buffer.iter()).is_ok());
+        assert!(loaded.rumble());
+        assert_eq!(loaded.ram_bank(), 2);
+    }
 }
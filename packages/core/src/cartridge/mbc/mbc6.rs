@@ -0,0 +1,419 @@
+use super::{mirror, MemoryBankController};
+use crate::cartridge::cartridge_capability::CartridgeCapability;
+use crate::util::savestate::{
+    read_savestate_bool, read_savestate_byte, read_savestate_enum, LoadSavestateError, Savestate,
+    SavestateStream,
+};
+
+/// Size of the flash memory, which is split in 8KB banks like the ROM
+pub const FLASH_SIZE: usize = 0x10_0000;
+/// Size of the sectors erased at once by the flash
+const FLASH_SECTOR_SIZE: usize = 0x2_0000;
+/// Value written to the bank select registers to map the flash instead of the ROM
+const FLASH_SELECT: u8 = 0x08;
+/// Manufacturer and device ids of the Macronix MX29F008 flash
+const FLASH_ID: [u8; 2] = [0xC2, 0x81];
+
+/// MBC6, only used by Net de Get. The ROM and RAM areas are split in two halves
+/// with their own banks, and the ROM halves can map a flash memory instead.
+/// The flash is saved after the RAM in battery saves.
+pub struct MBC6 {
+    ram_enabled: bool,
+    /// 4KB RAM banks mapped to 0xA000-0xAFFF and 0xB000-0xBFFF
+    ram_banks: [u8; 2],
+    /// 8KB ROM or flash banks mapped to 0x4000-0x5FFF and 0x6000-0x7FFF
+    rom_banks: [u8; 2],
+    flash_selected: [bool; 2],
+    flash_enabled: bool,
+    flash_write_enabled: bool,
+    flash: Flash,
+}
+
+impl MBC6 {
+    pub fn new(_capabilities: &[CartridgeCapability]) -> MBC6 {
+        MBC6 {
+            ram_enabled: false,
+            ram_banks: [0, 0],
+            rom_banks: [2, 3],
+            flash_selected: [false, false],
+            flash_enabled: false,
+            flash_write_enabled: false,
+            flash: Flash::new(),
+        }
+    }
+
+    // 0 for 0x4000-0x5FFF and 1 for 0x6000-0x7FFF
+    fn half(address: usize) -> usize {
+        (address >> 13) & 1
+    }
+
+    fn flash_address(&self, address: usize) -> usize {
+        let half = Self::half(address);
+        usize::from(self.rom_banks[half]) * 0x2000 + (address & 0x1FFF)
+    }
+}
+
+impl Savestate for MBC6 {
+    fn dump_savestate(&self, buffer: &mut Vec<u8>) {
+        buffer.push(self.ram_enabled as u8);
+        buffer.extend_from_slice(&self.ram_banks);
+        buffer.extend_from_slice(&self.rom_banks);
+        buffer.push(self.flash_selected[0] as u8);
+        buffer.push(self.flash_selected[1] as u8);
+        buffer.push(self.flash_enabled as u8);
+        buffer.push(self.flash_write_enabled as u8);
+        self.flash.dump_savestate(buffer);
+    }
+
+    fn load_savestate<'a>(
+        &mut self,
+        buffer: &mut SavestateStream<'a>,
+    ) -> Result<(), LoadSavestateError> {
+        self.ram_enabled = read_savestate_bool(buffer)?;
+        for bank in self.ram_banks.iter_mut().chain(self.rom_banks.iter_mut()) {
+            *bank = read_savestate_byte(buffer)?;
+        }
+        for selected in self.flash_selected.iter_mut() {
+            *selected = read_savestate_bool(buffer)?;
+        }
+        self.flash_enabled = read_savestate_bool(buffer)?;
+        self.flash_write_enabled = read_savestate_bool(buffer)?;
+        self.flash.load_savestate(buffer)
+    }
+}
+
+impl MemoryBankController for MBC6 {
+    /// Returns the bank of 0x4000-0x5FFF, in 16KB banks like other controllers
+    fn rom_bank(&self) -> u16 {
+        u16::from(self.rom_banks[0] >> 1)
+    }
+
+    fn ram_bank(&self) -> u8 {
+        self.ram_banks[0]
+    }
+
+    fn ram_enabled(&self) -> bool {
+        self.ram_enabled
+    }
+
+    fn relative_rom_address(&self, address: usize) -> usize {
+        if address < 0x4000 {
+            address
+        } else {
+            usize::from(self.rom_banks[Self::half(address)]) * 0x2000 + (address & 0x1FFF)
+        }
+    }
+
+    fn read_rom(&self, address: usize, rom: &[u8]) -> u8 {
+        if address >= 0x4000 && self.flash_selected[Self::half(address)] {
+            if self.flash_enabled {
+                self.flash.read(self.flash_address(address))
+            } else {
+                0xFF
+            }
+        } else {
+            mirror(self.relative_rom_address(address), rom.len())
+                .map_or(0xFF, |address| rom[address])
+        }
+    }
+
+    fn write_rom(&mut self, address: usize, value: u8) {
+        match address {
+            0..=0x03FF => self.ram_enabled = value == 0x0A,
+            0x0400..=0x07FF => self.ram_banks[0] = value & 7,
+            0x0800..=0x0BFF => self.ram_banks[1] = value & 7,
+            0x0C00..=0x0FFF => self.flash_enabled = value & 1 != 0,
+            0x1000 => self.flash_write_enabled = value & 1 != 0,
+            0x2000..=0x27FF => self.rom_banks[0] = value & 0x7F,
+            0x2800..=0x2FFF => self.flash_selected[0] = value == FLASH_SELECT,
+            0x3000..=0x37FF => self.rom_banks[1] = value & 0x7F,
+            0x3800..=0x3FFF => self.flash_selected[1] = value == FLASH_SELECT,
+            0x4000..=0x7FFF if self.flash_enabled && self.flash_selected[Self::half(address)] => {
+                let flash_address = self.flash_address(address);
+                self.flash
+                    .write(flash_address, value, self.flash_write_enabled);
+            }
+            _ => {}
+        }
+    }
+
+    fn relative_ram_address(&self, address: usize) -> usize {
+        let half = (address >> 12) & 1;
+        usize::from(self.ram_banks[half]) * 0x1000 + (address & 0x0FFF)
+    }
+
+    fn dump_save_footer(&self, buffer: &mut Vec<u8>) {
+        buffer.extend_from_slice(&self.flash.memory);
+    }
+
+    fn load_save_footer(&mut self, footer: &[u8]) -> bool {
+        if footer.len() != FLASH_SIZE {
+            return false;
+        }
+
+        self.flash.memory.copy_from_slice(footer);
+        true
+    }
+}
+
+/// Emulates the commands of the flash memory.
+/// Programming and erasing complete instantly, so the status never has to be polled.
+struct Flash {
+    memory: Vec<u8>,
+    state: FlashState,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum FlashState {
+    /// Reading the memory
+    Read,
+    /// Received 0xAA at 0x5555
+    Unlock1,
+    /// Received 0x55 at 0x2AAA, waiting for the command
+    Unlock2,
+    /// Received the erase command, waiting for the unlock sequence again
+    Erase,
+    EraseUnlock1,
+    EraseUnlock2,
+    /// Waiting for the byte to program
+    Program,
+    /// Reading the ids of the chip
+    Id,
+}
+
+impl FlashState {
+    fn from(value: u8) -> Option<FlashState> {
+        match value {
+            0 => Some(FlashState::Read),
+            1 => Some(FlashState::Unlock1),
+            2 => Some(FlashState::Unlock2),
+            3 => Some(FlashState::Erase),
+            4 => Some(FlashState::EraseUnlock1),
+            5 => Some(FlashState::EraseUnlock2),
+            6 => Some(FlashState::Program),
+            7 => Some(FlashState::Id),
+            _ => None,
+        }
+    }
+}
+
+impl Flash {
+    fn new() -> Flash {
+        Flash {
+            memory: vec![0xFF; FLASH_SIZE],
+            state: FlashState::Read,
+        }
+    }
+
+    fn read(&self, address: usize) -> u8 {
+        if self.state == FlashState::Id {
+            FLASH_ID[address & 1]
+        } else {
+            self.memory[address]
+        }
+    }
+
+    fn write(&mut self, address: usize, value: u8, write_enabled: bool) {
+        // commands are recognized by the lower 15 bits of their address
+        let command_address = address & 0x7FFF;
+        self.state = match (self.state, command_address, value) {
+            (FlashState::Program, _, _) => {
+                // programming can only clear bits, erasing sets them back
+                if write_enabled {
+                    self.memory[address] &= value;
+                }
+                FlashState::Read
+            }
+            (_, _, 0xF0) => FlashState::Read,
+            (FlashState::Read, 0x5555, 0xAA) | (FlashState::Id, 0x5555, 0xAA) => {
+                FlashState::Unlock1
+            }
+            (FlashState::Unlock1, 0x2AAA, 0x55) => FlashState::Unlock2,
+            (FlashState::Unlock2, 0x5555, 0x80) => FlashState::Erase,
+            (FlashState::Unlock2, 0x5555, 0x90) => FlashState::Id,
+            (FlashState::Unlock2, 0x5555, 0xA0) => FlashState::Program,
+            (FlashState::Erase, 0x5555, 0xAA) => FlashState::EraseUnlock1,
+            (FlashState::EraseUnlock1, 0x2AAA, 0x55) => FlashState::EraseUnlock2,
+            (FlashState::EraseUnlock2, 0x5555, 0x10) => {
+                if write_enabled {
+                    self.memory.iter_mut().for_each(|value| *value = 0xFF);
+                }
+                FlashState::Read
+            }
+            (FlashState::EraseUnlock2, _, 0x30) => {
+                if write_enabled {
+                    let start = address & !(FLASH_SECTOR_SIZE - 1);
+                    self.memory[start..start + FLASH_SECTOR_SIZE]
+                        .iter_mut()
+                        .for_each(|value| *value = 0xFF);
+                }
+                FlashState::Read
+            }
+            (FlashState::Id, _, _) => FlashState::Id,
+            _ => FlashState::Read,
+        };
+    }
+}
+
+impl Savestate for Flash {
+    fn dump_savestate(&self, buffer: &mut Vec<u8>) {
+        buffer.push(self.state as u8);
+        buffer.extend_from_slice(&self.memory);
+    }
+
+    fn load_savestate<'a>(
+        &mut self,
+        buffer: &mut SavestateStream<'a>,
+    ) -> Result<(), LoadSavestateError> {
+        self.state = read_savestate_enum(buffer, "FlashState", FlashState::from)?;
+        for value in self.memory.iter_mut() {
+            *value = read_savestate_byte(buffer)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the unlock sequence, in the flash mapped at 0x4000-0x5FFF
+    fn unlock(mbc: &mut MBC6) {
+        mbc.write_rom(0x2000, 2);
+        mbc.write_rom(0x5555, 0xAA);
+        mbc.write_rom(0x2000, 1);
+        mbc.write_rom(0x4AAA, 0x55);
+    }
+
+    fn flash_command(mbc: &mut MBC6, command: u8) {
+        unlock(mbc);
+        mbc.write_rom(0x2000, 2);
+        mbc.write_rom(0x5555, command);
+    }
+
+    fn flash_mbc() -> MBC6 {
+        let mut mbc = MBC6::new(&[]);
+        mbc.write_rom(0x0C00, 1);
+        mbc.write_rom(0x1000, 1);
+        mbc.write_rom(0x2800, FLASH_SELECT);
+        mbc.write_rom(0x3800, FLASH_SELECT);
+        mbc
+    }
+
+    #[test]
+    fn rom_bank_switching() {
+        let mut mbc = MBC6::new(&[]);
+        assert_eq!(mbc.relative_rom_address(0x4000), 0x4000);
+        assert_eq!(mbc.relative_rom_address(0x6000), 0x6000);
+
+        mbc.write_rom(0x2000, 0x05);
+        mbc.write_rom(0x3000, 0x7F);
+        assert_eq!(mbc.relative_rom_address(0x4123), 0xA123);
+        assert_eq!(mbc.relative_rom_address(0x6123), 0xFE123);
+        assert_eq!(mbc.relative_rom_address(0x1234), 0x1234);
+    }
+
+    #[test]
+    fn ram_bank_switching() {
+        let mut mbc = MBC6::new(&[]);
+        mbc.write_rom(0x0400, 3);
+        mbc.write_rom(0x0800, 7);
+        assert_eq!(mbc.relative_ram_address(0xA123), 0x3123);
+        assert_eq!(mbc.relative_ram_address(0xB123), 0x7123);
+
+        let mut ram = vec![0; 0x8000];
+        mbc.write_ram(0xB000, 0x42, &mut ram);
+        assert_eq!(ram[0x7000], 0x42);
+    }
+
+    #[test]
+    fn flash_mapping() {
+        let mut mbc = MBC6::new(&[]);
+        let rom = vec![0x11; 0x10_0000];
+        mbc.write_rom(0x2800, FLASH_SELECT);
+
+        // disabled flash reads open bus
+        assert_eq!(mbc.read_rom(0x4000, &rom), 0xFF);
+        assert_eq!(mbc.read_rom(0x6000, &rom), 0x11);
+
+        mbc.write_rom(0x0C00, 1);
+        mbc.write_rom(0x2000, 4);
+        mbc.flash.memory[4 * 0x2000 + 1] = 0x42;
+        assert_eq!(mbc.read_rom(0x4001, &rom), 0x42);
+    }
+
+    #[test]
+    fn flash_program() {
+        let mut mbc = flash_mbc();
+        let rom = vec![];
+
+        // writes without the program command are ignored
+        mbc.write_rom(0x3000, 9);
+        mbc.write_rom(0x6010, 0x42);
+        assert_eq!(mbc.read_rom(0x6010, &rom), 0xFF);
+
+        flash_command(&mut mbc, 0xA0);
+        mbc.write_rom(0x6010, 0x42);
+        assert_eq!(mbc.read_rom(0x6010, &rom), 0x42);
+        assert_eq!(mbc.flash.memory[9 * 0x2000 + 0x10], 0x42);
+
+        // 0xF0 is programmed instead of resetting the flash
+        flash_command(&mut mbc, 0xA0);
+        mbc.write_rom(0x6011, 0xF0);
+        assert_eq!(mbc.read_rom(0x6011, &rom), 0xF0);
+    }
+
+    #[test]
+    fn flash_program_needs_write_enable() {
+        let mut mbc = flash_mbc();
+        mbc.write_rom(0x1000, 0);
+        flash_command(&mut mbc, 0xA0);
+        mbc.write_rom(0x4000, 0);
+        assert_eq!(mbc.read_rom(0x4000, &[]), 0xFF);
+    }
+
+    #[test]
+    fn flash_erase() {
+        let mut mbc = flash_mbc();
+        mbc.flash.memory.iter_mut().for_each(|value| *value = 0);
+
+        flash_command(&mut mbc, 0x80);
+        unlock(&mut mbc);
+        // the sector erase command is written in the sector
+        mbc.write_rom(0x2000, 0x10);
+        mbc.write_rom(0x4000, 0x30);
+        assert_eq!(mbc.flash.memory[0x2_0000], 0xFF);
+        assert_eq!(mbc.flash.memory[0x3_FFFF], 0xFF);
+        assert_eq!(mbc.flash.memory[0x1_FFFF], 0);
+        assert_eq!(mbc.flash.memory[0x4_0000], 0);
+
+        flash_command(&mut mbc, 0x80);
+        flash_command(&mut mbc, 0x10);
+        assert!(mbc.flash.memory.iter().all(|&value| value == 0xFF));
+    }
+
+    #[test]
+    fn flash_id() {
+        let mut mbc = flash_mbc();
+        flash_command(&mut mbc, 0x90);
+        assert_eq!(mbc.read_rom(0x4000, &[]), FLASH_ID[0]);
+        assert_eq!(mbc.read_rom(0x4001, &[]), FLASH_ID[1]);
+
+        mbc.write_rom(0x4000, 0xF0);
+        assert_eq!(mbc.read_rom(0x4000, &[]), 0xFF);
+    }
+
+    #[test]
+    fn flash_footer() {
+        let mut mbc = flash_mbc();
+        mbc.flash.memory[0x1234] = 0x42;
+        let mut footer = Vec::new();
+        mbc.dump_save_footer(&mut footer);
+        assert_eq!(footer.len(), FLASH_SIZE);
+
+        let mut other = MBC6::new(&[]);
+        assert!(other.load_save_footer(&footer));
+        assert_eq!(other.flash.memory[0x1234], 0x42);
+        assert!(!other.load_save_footer(&footer[1..]));
+    }
+}
@@ -0,0 +1,509 @@
+use super::MemoryBankController;
+use crate::cartridge::cartridge_capability::CartridgeCapability;
+use crate::util::bits::get_bit;
+use crate::util::savestate::{
+    read_savestate_bool, read_savestate_byte, read_savestate_enum, read_savestate_u16,
+    write_savestate_u16, LoadSavestateError, Savestate, SavestateStream,
+};
+use std::cmp;
+
+/// Size of the 93LC56 EEPROM, which stores 128 16-bit words
+const EEPROM_SIZE: usize = 256;
+/// Value of the accelerometer when the cartridge is flat
+const ACCELEROMETER_CENTER: f32 = 0x81D0 as f32;
+/// Change of the accelerometer value for 1g
+const ACCELEROMETER_GRAVITY: f32 = 0x70 as f32;
+
+/// MBC7, used by cartridges with an accelerometer like Kirby Tilt 'n' Tumble.
+/// Instead of RAM, it has a serial EEPROM accessed through a register.
+pub struct MBC7 {
+    rom_bank: u8,
+    ram_enabled: bool,
+    registers_enabled: bool,
+    tilt: (f32, f32),
+    latch_erased: bool,
+    x_latch: u16,
+    y_latch: u16,
+    eeprom: Eeprom,
+}
+
+impl MBC7 {
+    pub fn new(_capabilities: &[CartridgeCapability]) -> MBC7 {
+        MBC7 {
+            rom_bank: 1,
+            ram_enabled: false,
+            registers_enabled: false,
+            tilt: (0.0, 0.0),
+            latch_erased: false,
+            x_latch: 0x8000,
+            y_latch: 0x8000,
+            eeprom: Eeprom::new(),
+        }
+    }
+
+    fn latch_accelerometer(&mut self) {
+        let (x, y) = self.tilt;
+        self.x_latch = (ACCELEROMETER_CENTER + x * ACCELEROMETER_GRAVITY) as u16;
+        self.y_latch = (ACCELEROMETER_CENTER + y * ACCELEROMETER_GRAVITY) as u16;
+    }
+}
+
+impl Savestate for MBC7 {
+    fn dump_savestate(&self, buffer: &mut Vec<u8>) {
+        buffer.push(self.rom_bank);
+        buffer.push(self.ram_enabled as u8);
+        buffer.push(self.registers_enabled as u8);
+        buffer.push(self.latch_erased as u8);
+        write_savestate_u16(buffer, self.x_latch);
+        write_savestate_u16(buffer, self.y_latch);
+        self.eeprom.dump_savestate(buffer);
+    }
+
+    fn load_savestate<'a>(
+        &mut self,
+        buffer: &mut SavestateStream<'a>,
+    ) -> Result<(), LoadSavestateError> {
+        self.rom_bank = read_savestate_byte(buffer)?;
+        self.ram_enabled = read_savestate_bool(buffer)?;
+        self.registers_enabled = read_savestate_bool(buffer)?;
+        self.latch_erased = read_savestate_bool(buffer)?;
+        self.x_latch = read_savestate_u16(buffer)?;
+        self.y_latch = read_savestate_u16(buffer)?;
+        self.eeprom.load_savestate(buffer)
+    }
+}
+
+impl MemoryBankController for MBC7 {
+    fn rom_bank(&self) -> u16 {
+        u16::from(self.rom_bank)
+    }
+
+    fn ram_bank(&self) -> u8 {
+        0
+    }
+
+    fn ram_enabled(&self) -> bool {
+        self.ram_enabled && self.registers_enabled
+    }
+
+    fn write_rom(&mut self, address: usize, value: u8) {
+        match address {
+            0..=0x1FFF => {
+                // first ram enable
+                self.ram_enabled = value == 0x0A;
+            }
+            0x2000..=0x3FFF => {
+                // change rom bank
+                self.rom_bank = cmp::max(value & 0x7F, 1);
+            }
+            0x4000..=0x5FFF => {
+                // second ram enable
+                self.registers_enabled = value == 0x40;
+            }
+            _ => {}
+        }
+    }
+
+    fn ram_size(&self, _header_ram_size: usize) -> usize {
+        EEPROM_SIZE
+    }
+
+    fn read_ram(&self, address: usize, _ram: &[u8]) -> u8 {
+        if address >= 0xB000 {
+            return 0xFF;
+        }
+
+        match (address >> 4) & 0xF {
+            2 => self.x_latch as u8,
+            3 => (self.x_latch >> 8) as u8,
+            4 => self.y_latch as u8,
+            5 => (self.y_latch >> 8) as u8,
+            6 => 0,
+            8 => self.eeprom.read(),
+            _ => 0xFF,
+        }
+    }
+
+    fn write_ram(&mut self, address: usize, value: u8, ram: &mut [u8]) -> bool {
+        if address >= 0xB000 {
+            return false;
+        }
+
+        match (address >> 4) & 0xF {
+            0 if value == 0x55 => {
+                self.latch_erased = true;
+                self.x_latch = 0x8000;
+                self.y_latch = 0x8000;
+            }
+            1 if value == 0xAA && self.latch_erased => {
+                self.latch_erased = false;
+                self.latch_accelerometer();
+            }
+            8 => return self.eeprom.write(value, ram),
+            _ => {}
+        }
+
+        false
+    }
+
+    fn set_tilt(&mut self, x: f32, y: f32) {
+        self.tilt = (x, y);
+    }
+}
+
+/// Emulates the 93LC56 serial EEPROM in its 16-bit organization.
+/// Bits are shifted in and out on the rising edges of the clock while the chip is selected.
+struct Eeprom {
+    chip_select: bool,
+    clock: bool,
+    data_in: bool,
+    data_out: bool,
+    write_enabled: bool,
+    state: EepromState,
+    /// Bits shifted in or out for the current state
+    shift_register: u16,
+    bit_count: u8,
+    address: u8,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum EepromState {
+    /// Waiting for the start bit of a command
+    Idle,
+    /// Receiving the opcode and address
+    Command,
+    /// Sending the words starting at the address
+    Reading,
+    /// Receiving the word to write at the address
+    Writing,
+    /// Receiving the word to write everywhere
+    WritingAll,
+}
+
+impl EepromState {
+    fn from(value: u8) -> Option<EepromState> {
+        match value {
+            0 => Some(EepromState::Idle),
+            1 => Some(EepromState::Command),
+            2 => Some(EepromState::Reading),
+            3 => Some(EepromState::Writing),
+            4 => Some(EepromState::WritingAll),
+            _ => None,
+        }
+    }
+}
+
+impl Eeprom {
+    fn new() -> Eeprom {
+        Eeprom {
+            chip_select: false,
+            clock: false,
+            data_in: false,
+            data_out: false,
+            write_enabled: false,
+            state: EepromState::Idle,
+            shift_register: 0,
+            bit_count: 0,
+            address: 0,
+        }
+    }
+
+    /// Returns the state of the pins: chip select (7), clock (6), data in (1) and data out (0)
+    fn read(&self) -> u8 {
+        (self.chip_select as u8) << 7
+            | (self.clock as u8) << 6
+            | (self.data_in as u8) << 1
+            | self.data_out as u8
+    }
+
+    /// Sets the state of the pins, returns true if the memory changed
+    fn write(&mut self, value: u8, memory: &mut [u8]) -> bool {
+        let chip_select = get_bit(value, 7);
+        let clock = get_bit(value, 6);
+        self.data_in = get_bit(value, 1);
+
+        let mut changed = false;
+        if !chip_select {
+            // deselecting the chip aborts the current command
+            self.state = EepromState::Idle;
+        } else if clock && !self.clock {
+            changed = self.rising_edge(memory);
+        }
+
+        self.chip_select = chip_select;
+        self.clock = clock;
+        changed
+    }
+
+    fn rising_edge(&mut self, memory: &mut [u8]) -> bool {
+        match self.state {
+            EepromState::Idle => {
+                if self.data_in {
+                    self.state = EepromState::Command;
+                    self.shift_register = 0;
+                    self.bit_count = 0;
+                }
+            }
+            EepromState::Command => {
+                self.shift_in();
+                // 2-bit opcode followed by an 8-bit address
+                if self.bit_count == 10 {
+                    return self.execute(memory);
+                }
+            }
+            EepromState::Reading => {
+                self.data_out = get_bit_u16(self.shift_register, 15);
+                self.shift_register <<= 1;
+                self.bit_count += 1;
+                if self.bit_count == 16 {
+                    // reads continue with the next word until the chip is deselected
+                    self.address = (self.address + 1) & 0x7F;
+                    self.shift_register = word(memory, self.address);
+                    self.bit_count = 0;
+                }
+            }
+            EepromState::Writing | EepromState::WritingAll => {
+                self.shift_in();
+                if self.bit_count == 16 {
+                    let mut changed = false;
+                    if self.write_enabled {
+                        if self.state == EepromState::WritingAll {
+                            for address in 0..(EEPROM_SIZE / 2) as u8 {
+                                changed |= set_word(memory, address, self.shift_register);
+                            }
+                        } else {
+                            changed = set_word(memory, self.address, self.shift_register);
+                        }
+                    }
+                    self.finish();
+                    return changed;
+                }
+            }
+        }
+
+        false
+    }
+
+    fn shift_in(&mut self) {
+        self.shift_register = self.shift_register << 1 | self.data_in as u16;
+        self.bit_count += 1;
+    }
+
+    fn execute(&mut self, memory: &mut [u8]) -> bool {
+        let opcode = self.shift_register >> 8;
+        // commands without an address use its two highest bits as an extra opcode
+        let extra_opcode = (self.shift_register >> 6) & 3;
+        self.address = (self.shift_register & 0x7F) as u8;
+        self.shift_register = 0;
+        self.bit_count = 0;
+
+        match opcode {
+            0b10 => {
+                // read, starting with a dummy 0 bit
+                self.state = EepromState::Reading;
+                self.data_out = false;
+                self.shift_register = word(memory, self.address);
+            }
+            0b01 => self.state = EepromState::Writing,
+            0b11 => {
+                // erase
+                let changed = self.write_enabled && set_word(memory, self.address, 0xFFFF);
+                self.finish();
+                return changed;
+            }
+            _ => match extra_opcode {
+                0b00 => {
+                    self.write_enabled = false;
+                    self.finish();
+                }
+                0b01 => self.state = EepromState::WritingAll,
+                0b10 => {
+                    let changed = self.write_enabled && memory.iter().any(|&value| value != 0xFF);
+                    if self.write_enabled {
+                        for value in memory.iter_mut() {
+                            *value = 0xFF;
+                        }
+                    }
+                    self.finish();
+                    return changed;
+                }
+                _ => {
+                    self.write_enabled = true;
+                    self.finish();
+                }
+            },
+        }
+
+        false
+    }
+
+    /// Writes complete instantly, so data out signals that the chip is ready
+    fn finish(&mut self) {
+        self.state = EepromState::Idle;
+        self.data_out = true;
+    }
+}
+
+impl Savestate for Eeprom {
+    fn dump_savestate(&self, buffer: &mut Vec<u8>) {
+        buffer.push(self.chip_select as u8);
+        buffer.push(self.clock as u8);
+        buffer.push(self.data_in as u8);
+        buffer.push(self.data_out as u8);
+        buffer.push(self.write_enabled as u8);
+        buffer.push(self.state as u8);
+        write_savestate_u16(buffer, self.shift_register);
+        buffer.push(self.bit_count);
+        buffer.push(self.address);
+    }
+
+    fn load_savestate<'a>(
+        &mut self,
+        buffer: &mut SavestateStream<'a>,
+    ) -> Result<(), LoadSavestateError> {
+        self.chip_select = read_savestate_bool(buffer)?;
+        self.clock = read_savestate_bool(buffer)?;
+        self.data_in = read_savestate_bool(buffer)?;
+        self.data_out = read_savestate_bool(buffer)?;
+        self.write_enabled = read_savestate_bool(buffer)?;
+        self.state = read_savestate_enum(buffer, "EepromState", EepromState::from)?;
+        self.shift_register = read_savestate_u16(buffer)?;
+        self.bit_count = read_savestate_byte(buffer)?;
+        self.address = read_savestate_byte(buffer)?;
+        Ok(())
+    }
+}
+
+fn get_bit_u16(value: u16, bit: u8) -> bool {
+    (value >> bit) & 1 != 0
+}
+
+// words are stored in little endian, like other emulators do
+fn word(memory: &[u8], address: u8) -> u16 {
+    let index = address as usize * 2;
+    match memory.get(index..index + 2) {
+        Some(bytes) => u16::from_le_bytes([bytes[0], bytes[1]]),
+        None => 0xFFFF,
+    }
+}
+
+/// Returns true if the word changed
+fn set_word(memory: &mut [u8], address: u8, value: u16) -> bool {
+    let index = address as usize * 2;
+    match memory.get_mut(index..index + 2) {
+        Some(bytes) if bytes != value.to_le_bytes() => {
+            bytes.copy_from_slice(&value.to_le_bytes());
+            true
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EEPROM_REGISTER: usize = 0xA080;
+    const CHIP_SELECT: u8 = 0x80;
+    const CLOCK: u8 = 0x40;
+
+    fn mbc() -> MBC7 {
+        let mut mbc = MBC7::new(&[]);
+        mbc.write_rom(0, 0x0A);
+        mbc.write_rom(0x4000, 0x40);
+        mbc
+    }
+
+    fn send_bits(mbc: &mut MBC7, ram: &mut [u8], value: u32, count: u8) {
+        for i in (0..count).rev() {
+            let bit = ((value >> i) & 1) as u8;
+            mbc.write_ram(EEPROM_REGISTER, CHIP_SELECT | bit << 1, ram);
+            mbc.write_ram(EEPROM_REGISTER, CHIP_SELECT | CLOCK | bit << 1, ram);
+        }
+    }
+
+    // start bit, opcode and address
+    fn send_command(mbc: &mut MBC7, ram: &mut [u8], opcode: u32, address: u32) {
+        mbc.write_ram(EEPROM_REGISTER, 0, ram);
+        send_bits(mbc, ram, 1 << 10 | opcode << 8 | address, 11);
+    }
+
+    fn receive_word(mbc: &mut MBC7, ram: &mut [u8]) -> u16 {
+        let mut value = 0;
+        for _ in 0..16 {
+            send_bits(mbc, ram, 0, 1);
+            value = value << 1 | u16::from(mbc.read_ram(EEPROM_REGISTER, ram) & 1);
+        }
+        value
+    }
+
+    #[test]
+    fn registers_need_both_enables() {
+        let mut mbc = MBC7::new(&[]);
+        mbc.write_rom(0, 0x0A);
+        assert!(!mbc.ram_enabled());
+        mbc.write_rom(0x4000, 0x40);
+        assert!(mbc.ram_enabled());
+    }
+
+    #[test]
+    fn accelerometer_latch() {
+        let mut mbc = mbc();
+        mbc.set_tilt(1.0, -0.5);
+
+        // latching without erasing first does nothing
+        mbc.write_ram(0xA010, 0xAA, &mut []);
+        assert_eq!(mbc.read_ram(0xA030, &[]), 0x80);
+
+        mbc.write_ram(0xA000, 0x55, &mut []);
+        mbc.write_ram(0xA010, 0xAA, &mut []);
+        let x = u16::from(mbc.read_ram(0xA030, &[])) << 8 | u16::from(mbc.read_ram(0xA020, &[]));
+        let y = u16::from(mbc.read_ram(0xA050, &[])) << 8 | u16::from(mbc.read_ram(0xA040, &[]));
+        assert_eq!(x, 0x81D0 + 0x70);
+        assert_eq!(y, 0x81D0 - 0x38);
+
+        // the latch doesn't follow the tilt
+        mbc.set_tilt(0.0, 0.0);
+        assert_eq!(mbc.read_ram(0xA020, &[]), x as u8);
+    }
+
+    #[test]
+    fn eeprom_write_and_read() {
+        let mut mbc = mbc();
+        let mut ram = vec![0xFF; EEPROM_SIZE];
+
+        // writes are ignored until enabled
+        send_command(&mut mbc, &mut ram, 0b01, 5);
+        send_bits(&mut mbc, &mut ram, 0x1234, 16);
+        assert_eq!(word(&ram, 5), 0xFFFF);
+
+        send_command(&mut mbc, &mut ram, 0b00, 0b1100_0000);
+        send_command(&mut mbc, &mut ram, 0b01, 5);
+        send_bits(&mut mbc, &mut ram, 0x1234, 16);
+        assert_eq!(ram[10..12], [0x34, 0x12]);
+        assert_eq!(mbc.read_ram(EEPROM_REGISTER, &ram) & 1, 1);
+
+        send_command(&mut mbc, &mut ram, 0b10, 5);
+        assert_eq!(mbc.read_ram(EEPROM_REGISTER, &ram) & 1, 0);
+        assert_eq!(receive_word(&mut mbc, &mut ram), 0x1234);
+        assert_eq!(receive_word(&mut mbc, &mut ram), 0xFFFF);
+    }
+
+    #[test]
+    fn eeprom_erase() {
+        let mut mbc = mbc();
+        let mut ram = vec![0; EEPROM_SIZE];
+        send_command(&mut mbc, &mut ram, 0b00, 0b1100_0000);
+
+        send_command(&mut mbc, &mut ram, 0b11, 3);
+        assert_eq!(word(&ram, 3), 0xFFFF);
+        assert_eq!(word(&ram, 4), 0);
+
+        send_command(&mut mbc, &mut ram, 0b00, 0b0100_0000);
+        send_bits(&mut mbc, &mut ram, 0xABCD, 16);
+        assert!(ram.chunks(2).all(|bytes| bytes == [0xCD, 0xAB]));
+
+        send_command(&mut mbc, &mut ram, 0b00, 0b1000_0000);
+        assert!(ram.iter().all(|&value| value == 0xFF));
+    }
+}
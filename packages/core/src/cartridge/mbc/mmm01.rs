@@ -0,0 +1,241 @@
+use super::MemoryBankController;
+use crate::cartridge::cartridge_capability::CartridgeCapability;
+use crate::cartridge::cartridge_metadata::{
+    CARTRIDGE_TYPE_OFFSET, NINTENDO_LOGO, NINTENDO_LOGO_OFFSET,
+};
+use crate::util::bits::get_bit;
+use crate::util::savestate::{
+    read_savestate_bool, read_savestate_byte, LoadSavestateError, Savestate, SavestateStream,
+};
+
+/// Bank mapped to 0x0000-0x3FFF before a game is selected: the menu is in the last 32KB
+const MENU_BANK: u16 = 0x1FE;
+
+/// MMM01, used by multi-game cartridges. It starts in a locked state showing the menu
+/// at the end of the ROM. The menu sets the bank and size of the selected game,
+/// then maps it, after which the game sees a regular MBC1 of its own size.
+pub struct MMM01 {
+    mapped: bool,
+    ram_enabled: bool,
+    rom_bank_low: u8,
+    rom_bank_mid: u8,
+    rom_bank_high: u8,
+    /// Bits 1-4 of the ROM bank that can't be changed by the game once it is mapped
+    rom_bank_mask: u8,
+    ram_bank_low: u8,
+    ram_bank_high: u8,
+    mode: bool,
+    mode_locked: bool,
+}
+
+impl MMM01 {
+    pub fn new(_capabilities: &[CartridgeCapability]) -> MMM01 {
+        MMM01 {
+            mapped: false,
+            ram_enabled: false,
+            rom_bank_low: 0,
+            rom_bank_mid: 0,
+            rom_bank_high: 0,
+            rom_bank_mask: 0,
+            ram_bank_low: 0,
+            ram_bank_high: 0,
+            mode: false,
+            mode_locked: false,
+        }
+    }
+
+    /// MMM01 dumps have the header of the menu, which declares the MMM01,
+    /// in their last 32KB. The header at the start of the ROM is the one of the first game.
+    pub fn is_mmm01(rom: &[u8]) -> bool {
+        if rom.len() < 0x8000 {
+            return false;
+        }
+
+        let menu = &rom[rom.len() - 0x8000..];
+        menu[NINTENDO_LOGO_OFFSET..NINTENDO_LOGO_OFFSET + NINTENDO_LOGO.len()] == NINTENDO_LOGO
+            && (0x0B..=0x0D).contains(&menu[CARTRIDGE_TYPE_OFFSET])
+    }
+
+    // bits of the lower ROM bank register that the game can't change
+    fn frozen_bits(&self) -> u8 {
+        self.rom_bank_mask << 1
+    }
+
+    fn game_bank(&self, low: u8) -> u16 {
+        u16::from(self.rom_bank_high) << 7 | u16::from(self.rom_bank_mid) << 5 | u16::from(low)
+    }
+}
+
+impl Savestate for MMM01 {
+    fn dump_savestate(&self, buffer: &mut Vec<u8>) {
+        buffer.push(self.mapped as u8);
+        buffer.push(self.ram_enabled as u8);
+        buffer.push(self.rom_bank_low);
+        buffer.push(self.rom_bank_mid);
+        buffer.push(self.rom_bank_high);
+        buffer.push(self.rom_bank_mask);
+        buffer.push(self.ram_bank_low);
+        buffer.push(self.ram_bank_high);
+        buffer.push(self.mode as u8);
+        buffer.push(self.mode_locked as u8);
+    }
+
+    fn load_savestate<'a>(
+        &mut self,
+        buffer: &mut SavestateStream<'a>,
+    ) -> Result<(), LoadSavestateError> {
+        self.mapped = read_savestate_bool(buffer)?;
+        self.ram_enabled = read_savestate_bool(buffer)?;
+        self.rom_bank_low = read_savestate_byte(buffer)?;
+        self.rom_bank_mid = read_savestate_byte(buffer)?;
+        self.rom_bank_high = read_savestate_byte(buffer)?;
+        self.rom_bank_mask = read_savestate_byte(buffer)?;
+        self.ram_bank_low = read_savestate_byte(buffer)?;
+        self.ram_bank_high = read_savestate_byte(buffer)?;
+        self.mode = read_savestate_bool(buffer)?;
+        self.mode_locked = read_savestate_bool(buffer)?;
+        Ok(())
+    }
+}
+
+impl MemoryBankController for MMM01 {
+    fn rom_bank(&self) -> u16 {
+        if !self.mapped {
+            return MENU_BANK + 1;
+        }
+
+        // like MBC1, bank 0 of the game can't be mapped to 0x4000-0x7FFF
+        let frozen = self.frozen_bits();
+        let low = if self.rom_bank_low & !frozen & 0x1F == 0 {
+            self.rom_bank_low | 1
+        } else {
+            self.rom_bank_low
+        };
+        self.game_bank(low)
+    }
+
+    fn ram_bank(&self) -> u8 {
+        self.ram_bank_high << 2 | self.ram_bank_low
+    }
+
+    fn ram_enabled(&self) -> bool {
+        self.ram_enabled
+    }
+
+    fn relative_rom_address(&self, address: usize) -> usize {
+        let bank = if address >= 0x4000 {
+            self.rom_bank()
+        } else if self.mapped {
+            // the first bank of the game
+            self.game_bank(self.rom_bank_low & self.frozen_bits())
+        } else {
+            MENU_BANK
+        };
+        (address & 0x3FFF) + bank as usize * 0x4000
+    }
+
+    fn write_rom(&mut self, address: usize, value: u8) {
+        match address {
+            0..=0x1FFF => {
+                // ram enable, and mapping of the game
+                self.ram_enabled = value & 0xF == 0xA;
+                if !self.mapped {
+                    self.mapped = get_bit(value, 6);
+                }
+            }
+            0x2000..=0x3FFF => {
+                // rom bank, the upper bits select the game
+                let writable = if self.mapped {
+                    !self.frozen_bits() & 0x1F
+                } else {
+                    self.rom_bank_mid = (value >> 5) & 3;
+                    0x1F
+                };
+                self.rom_bank_low = self.rom_bank_low & !writable | value & writable;
+            }
+            0x4000..=0x5FFF => {
+                // ram bank, the upper bits select the game
+                self.ram_bank_low = value & 3;
+                if !self.mapped {
+                    self.ram_bank_high = (value >> 2) & 3;
+                    self.rom_bank_high = (value >> 4) & 3;
+                    self.mode_locked = get_bit(value, 6);
+                }
+            }
+            0x6000..=0x7FFF => {
+                // banking mode, and size of the game
+                if !self.mode_locked {
+                    self.mode = get_bit(value, 0);
+                }
+                if !self.mapped {
+                    self.rom_bank_mask = (value >> 2) & 0xF;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::tests::test_rom::test_rom_with_type;
+
+    #[test]
+    fn starts_in_menu() {
+        let mbc = MMM01::new(&[]);
+        assert!(!mbc.mapped);
+        assert_eq!(mbc.relative_rom_address(0x0100), 0x1FE * 0x4000 + 0x100);
+        assert_eq!(mbc.relative_rom_address(0x4100), 0x1FF * 0x4000 + 0x100);
+    }
+
+    #[test]
+    fn map_game() {
+        let mut mbc = MMM01::new(&[]);
+        // 64KB game at bank 0xC8, so bits 2-4 of the bank are frozen
+        mbc.write_rom(0x2000, 0x48);
+        mbc.write_rom(0x4000, 0x10);
+        mbc.write_rom(0x6000, 0b1110 << 2);
+        mbc.write_rom(0, 0x40);
+        assert!(mbc.mapped);
+        assert_eq!(mbc.relative_rom_address(0x0000), 0xC8 * 0x4000);
+        assert_eq!(mbc.relative_rom_address(0x4000), 0xC9 * 0x4000);
+
+        // the game can only switch between its own banks
+        mbc.write_rom(0x2000, 0x1F);
+        assert_eq!(mbc.rom_bank(), 0xCB);
+        mbc.write_rom(0x2000, 0);
+        assert_eq!(mbc.rom_bank(), 0xC9);
+    }
+
+    #[test]
+    fn mapping_is_locked() {
+        let mut mbc = MMM01::new(&[]);
+        mbc.write_rom(0, 0x40);
+        mbc.write_rom(0, 0x0A);
+        assert!(mbc.mapped);
+        assert!(mbc.ram_enabled());
+
+        // the registers selecting the game can't be changed anymore
+        mbc.write_rom(0x4000, 0x3F);
+        assert_eq!(mbc.ram_bank(), 3);
+        assert_eq!(mbc.rom_bank(), 1);
+        mbc.write_rom(0x2000, 0x60);
+        assert_eq!(mbc.rom_bank(), 1);
+    }
+
+    #[test]
+    fn detection() {
+        let mut rom = vec![0; 0x4_0000];
+        assert!(!MMM01::is_mmm01(&rom));
+
+        let menu = test_rom_with_type("MENU", 0x0B, 0);
+        let start = rom.len() - 0x8000;
+        rom[start..start + 0x150].copy_from_slice(&menu[..0x150]);
+        assert!(!MMM01::is_mmm01(&rom));
+        rom[start + NINTENDO_LOGO_OFFSET..start + NINTENDO_LOGO_OFFSET + NINTENDO_LOGO.len()]
+            .copy_from_slice(&NINTENDO_LOGO);
+        assert!(MMM01::is_mmm01(&rom));
+        assert!(!MMM01::is_mmm01(&rom[..0x4000]));
+    }
+}
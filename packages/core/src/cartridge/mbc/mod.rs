@@ -1,21 +1,54 @@
+mod huc1;
+pub mod huc3;
+mod li_cheng;
 mod mbc1;
 mod mbc2;
 mod mbc3;
 mod mbc5;
-mod real_time_clock;
+mod mbc6;
+mod mbc7;
+mod mmm01;
+mod no_mbc;
+mod pocket_camera;
+pub mod real_time_clock;
+mod sachen;
+mod tama5;
+mod wisdom_tree;
 
+use crate::cartridge::camera::CameraSource;
 use crate::cartridge::cartridge_capability::CartridgeCapability;
 use crate::cartridge::cartridge_metadata::CartridgeMetadata;
+use crate::cartridge::mbc::huc1::HuC1;
+use crate::cartridge::mbc::huc3::HuC3;
+use crate::cartridge::mbc::li_cheng::LiCheng;
 use crate::cartridge::mbc::mbc1::MBC1;
 use crate::cartridge::mbc::mbc2::MBC2;
 use crate::cartridge::mbc::mbc3::MBC3;
 use crate::cartridge::mbc::mbc5::MBC5;
+use crate::cartridge::mbc::mbc6::MBC6;
+use crate::cartridge::mbc::mbc7::MBC7;
+use crate::cartridge::mbc::mmm01::MMM01;
+use crate::cartridge::mbc::no_mbc::NoMBC;
+use crate::cartridge::mbc::pocket_camera::PocketCamera;
+use crate::cartridge::mbc::real_time_clock::RealTimeClock;
+use crate::cartridge::mbc::sachen::SachenMMC1;
+use crate::cartridge::mbc::tama5::TAMA5;
+use crate::cartridge::mbc::wisdom_tree::WisdomTree;
+use crate::util::clock::Clock;
 use crate::util::savestate::Savestate;
+use std::mem;
+use std::rc::Rc;
+
+/// Largest ROM that an MBC1 can map, bigger cartridges need an MBC5
+const MBC1_MAX_ROM_SIZE: usize = 0x20_0000;
 
 pub struct MBCFactory;
 impl MBCFactory {
-    pub fn from_metadata(metadata: &CartridgeMetadata) -> Option<Box<dyn MemoryBankController>> {
-        let variant = MBCVariant::from_capabilities(&metadata.capabilities)?;
+    pub fn from_metadata(
+        metadata: &CartridgeMetadata,
+        rom: &[u8],
+    ) -> Option<Box<dyn MemoryBankController>> {
+        let variant = MBCVariant::from_cartridge(metadata, rom)?;
         Self::from_variant(&variant, &metadata.capabilities)
     }
 
@@ -25,55 +58,204 @@ impl MBCFactory {
     ) -> Option<Box<dyn MemoryBankController>> {
         match variant {
             MBCVariant::MBC1 => Some(Box::new(MBC1::new(capabilities))),
+            MBCVariant::MBC1M => Some(Box::new(MBC1::multicart(capabilities))),
             MBCVariant::MBC2 => Some(Box::new(MBC2::new(capabilities))),
             MBCVariant::MBC3 => Some(Box::new(MBC3::new(capabilities))),
+            MBCVariant::MBC30 => Some(Box::new(MBC3::mbc30(capabilities))),
             MBCVariant::MBC5 => Some(Box::new(MBC5::new(capabilities))),
+            MBCVariant::MBC6 => Some(Box::new(MBC6::new(capabilities))),
+            MBCVariant::MBC7 => Some(Box::new(MBC7::new(capabilities))),
+            MBCVariant::MMM01 => Some(Box::new(MMM01::new(capabilities))),
+            MBCVariant::HuC1 => Some(Box::new(HuC1::new(capabilities))),
+            MBCVariant::HuC3 => Some(Box::new(HuC3::new(capabilities))),
+            MBCVariant::TAMA5 => Some(Box::new(TAMA5::new(capabilities))),
+            MBCVariant::PocketCamera => Some(Box::new(PocketCamera::new(capabilities))),
+            MBCVariant::WisdomTree => Some(Box::new(WisdomTree::new(capabilities))),
+            MBCVariant::SachenMMC1 => Some(Box::new(SachenMMC1::new(capabilities))),
+            MBCVariant::LiCheng => Some(Box::new(LiCheng::new(capabilities))),
+            MBCVariant::NoMBC => Some(Box::new(NoMBC)),
         }
     }
 }
 
+/// Mirrors an address past the end of the ROM or RAM, like hardware does by ignoring
+/// the bank bits that aren't connected to anything.
+/// Returns None if there is no memory to map to.
+pub fn mirror(address: usize, size: usize) -> Option<usize> {
+    if size == 0 {
+        return None;
+    }
+
+    // sizes that aren't a power of two only come from bad dumps, so wrap them around
+    let address = address & (size.next_power_of_two() - 1);
+    Some(address % size)
+}
+
+/// Writes a byte of the RAM at an address mirrored with `mirror`,
+/// returns true if its value changed
+pub fn write_mirrored(ram: &mut [u8], address: usize, value: u8) -> bool {
+    match mirror(address, ram.len()) {
+        Some(address) => mem::replace(&mut ram[address], value) != value,
+        None => false,
+    }
+}
+
 pub trait MemoryBankController: Savestate {
     fn rom_bank(&self) -> u16;
     fn ram_bank(&self) -> u8;
     fn ram_enabled(&self) -> bool;
 
+    /// Maps an address in 0x0000-0x7FFF to its offset in the ROM.
+    /// The result can be past the end of the ROM, and is mirrored by the cartridge.
     fn relative_rom_address(&self, address: usize) -> usize {
-        let current_bank = self.rom_bank().saturating_sub(1) as usize;
-        address + current_bank * 0x4000
+        if address < 0x4000 {
+            address
+        } else {
+            (address - 0x4000) + self.rom_bank() as usize * 0x4000
+        }
+    }
+
+    /// Reads a byte in 0x0000-0x7FFF, for controllers that can map something else than the ROM
+    fn read_rom(&self, address: usize, rom: &[u8]) -> u8 {
+        mirror(self.relative_rom_address(address), rom.len()).map_or(0xFF, |address| rom[address])
     }
 
     fn write_rom(&mut self, _address: usize, _value: u8) {}
 
+    /// Returns the size of the RAM to allocate for the cartridge,
+    /// for controllers that have RAM built in instead of the size declared in the header
+    fn ram_size(&self, header_ram_size: usize) -> usize {
+        header_ram_size
+    }
+
     fn read_ram(&self, address: usize, ram: &[u8]) -> u8 {
-        let address = self.relative_ram_address(address);
-        if address < ram.len() {
-            ram[address]
-        } else {
-            0xFF
-        }
+        mirror(self.relative_ram_address(address), ram.len()).map_or(0xFF, |address| ram[address])
     }
 
-    fn write_ram(&self, address: usize, value: u8, ram: &mut [u8]) {
-        let address = self.relative_ram_address(address);
-        if address < ram.len() {
-            ram[address] = value
-        }
+    /// Writes a byte in 0xA000-0xBFFF.
+    /// Returns true if the RAM changed, which then needs to be saved.
+    fn write_ram(&mut self, address: usize, value: u8, ram: &mut [u8]) -> bool {
+        write_mirrored(ram, self.relative_ram_address(address), value)
     }
 
     fn relative_ram_address(&self, address: usize) -> usize {
         let current_bank = self.ram_bank() as usize;
         address + current_bank * 0x2000 - 0xA000
     }
+
+    /// Returns true if the rumble motor of the cartridge is on
+    fn rumble(&self) -> bool {
+        false
+    }
+
+    /// Sets the tilt measured by the accelerometer of the cartridge, in g
+    fn set_tilt(&mut self, _x: f32, _y: f32) {}
+
+    /// Sets the images seen by the sensor of the cartridge, if it has a camera
+    fn set_camera_source(&mut self, _source: Box<dyn CameraSource>) {}
+
+    /// Removes the source of images of the camera, to give it to another controller
+    fn take_camera_source(&mut self) -> Option<Box<dyn CameraSource>> {
+        None
+    }
+
+    fn real_time_clock(&self) -> Option<&RealTimeClock> {
+        None
+    }
+
+    fn real_time_clock_mut(&mut self) -> Option<&mut RealTimeClock> {
+        None
+    }
+
+    /// Replaces the source of time used by the clock of the cartridge, if it has one
+    fn set_clock(&mut self, clock: Rc<dyn Clock>) {
+        if let Some(real_time_clock) = self.real_time_clock_mut() {
+            real_time_clock.set_clock(clock);
+        }
+    }
+
+    /// Dumps the data saved after the RAM in battery saves, like the state of a clock
+    fn dump_save_footer(&self, buffer: &mut Vec<u8>) {
+        if let Some(clock) = self.real_time_clock() {
+            clock.dump_footer(buffer);
+        }
+    }
+
+    /// Loads the data saved after the RAM in battery saves.
+    /// Returns false if the cartridge saves nothing there or if the footer isn't recognized.
+    fn load_save_footer(&mut self, footer: &[u8]) -> bool {
+        match self.real_time_clock_mut() {
+            Some(clock) => clock.load_footer(footer),
+            None => false,
+        }
+    }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum MBCVariant {
     MBC1,
+    MBC1M,
     MBC2,
     MBC3,
+    MBC30,
     MBC5,
+    MBC6,
+    MBC7,
+    MMM01,
+    HuC1,
+    HuC3,
+    TAMA5,
+    PocketCamera,
+    WisdomTree,
+    SachenMMC1,
+    LiCheng,
+    NoMBC,
 }
 
 impl MBCVariant {
+    /// Finds the controller of a cartridge from its header, looking at the sizes
+    /// and at the ROM for variants that can't be told apart by their type alone
+    pub fn from_cartridge(metadata: &CartridgeMetadata, rom: &[u8]) -> Option<MBCVariant> {
+        if MMM01::is_mmm01(rom) {
+            return Some(MBCVariant::MMM01);
+        }
+        if let Some(variant) = Self::from_unlicensed(metadata, rom) {
+            return Some(variant);
+        }
+
+        // bootlegs can have a size that isn't in the header, the file has the right one
+        let rom_size = metadata.rom_size.unwrap_or(rom.len());
+        match Self::from_capabilities(&metadata.capabilities)? {
+            MBCVariant::MBC1 if MBC1::is_multicart(rom) => Some(MBCVariant::MBC1M),
+            MBCVariant::MBC3 if MBC3::is_mbc30(rom_size, metadata.ram_size) => {
+                Some(MBCVariant::MBC30)
+            }
+            variant => Some(variant),
+        }
+    }
+
+    /// Detects unlicensed cartridges, whose header is often wrong about their controller
+    fn from_unlicensed(metadata: &CartridgeMetadata, rom: &[u8]) -> Option<MBCVariant> {
+        if SachenMMC1::is_sachen(rom) {
+            return Some(MBCVariant::SachenMMC1);
+        }
+        if LiCheng::is_li_cheng(&metadata.capabilities, rom) {
+            return Some(MBCVariant::LiCheng);
+        }
+
+        // bootlegs declare no controller or an MBC1 while their ROM is too big for it
+        match Self::from_capabilities(&metadata.capabilities)? {
+            MBCVariant::NoMBC if WisdomTree::is_wisdom_tree(rom) => Some(MBCVariant::WisdomTree),
+            MBCVariant::NoMBC if rom.len() > 0x8000 && rom.len() <= MBC1_MAX_ROM_SIZE => {
+                Some(MBCVariant::MBC1)
+            }
+            MBCVariant::NoMBC | MBCVariant::MBC1 if rom.len() > MBC1_MAX_ROM_SIZE => {
+                Some(MBCVariant::MBC5)
+            }
+            _ => None,
+        }
+    }
+
     pub fn from_capabilities(capabilities: &[CartridgeCapability]) -> Option<MBCVariant> {
         if capabilities.contains(&CartridgeCapability::MBC1) {
             Some(MBCVariant::MBC1)
@@ -83,6 +265,22 @@ impl MBCVariant {
             Some(MBCVariant::MBC3)
         } else if capabilities.contains(&CartridgeCapability::MBC5) {
             Some(MBCVariant::MBC5)
+        } else if capabilities.contains(&CartridgeCapability::MBC6) {
+            Some(MBCVariant::MBC6)
+        } else if capabilities.contains(&CartridgeCapability::MBC7) {
+            Some(MBCVariant::MBC7)
+        } else if capabilities.contains(&CartridgeCapability::MMM01) {
+            Some(MBCVariant::MMM01)
+        } else if capabilities.contains(&CartridgeCapability::HuC1) {
+            Some(MBCVariant::HuC1)
+        } else if capabilities.contains(&CartridgeCapability::HuC3) {
+            Some(MBCVariant::HuC3)
+        } else if capabilities.contains(&CartridgeCapability::BandaiTama5) {
+            Some(MBCVariant::TAMA5)
+        } else if capabilities.contains(&CartridgeCapability::PocketCamera) {
+            Some(MBCVariant::PocketCamera)
+        } else if capabilities.contains(&CartridgeCapability::ROM) {
+            Some(MBCVariant::NoMBC)
         } else {
             None
         }
@@ -92,12 +290,26 @@ impl MBCVariant {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::util::tests::test_rom::{test_rom, test_rom_with_type};
 
-    static MBC_VARIANTS: [MBCVariant; 4] = [
+    // MMM01 is missing since it starts by mapping its menu, at the end of the ROM
+    static MBC_VARIANTS: [MBCVariant; 16] = [
         MBCVariant::MBC1,
+        MBCVariant::MBC1M,
         MBCVariant::MBC2,
         MBCVariant::MBC3,
+        MBCVariant::MBC30,
         MBCVariant::MBC5,
+        MBCVariant::MBC6,
+        MBCVariant::MBC7,
+        MBCVariant::HuC1,
+        MBCVariant::HuC3,
+        MBCVariant::TAMA5,
+        MBCVariant::PocketCamera,
+        MBCVariant::WisdomTree,
+        MBCVariant::SachenMMC1,
+        MBCVariant::LiCheng,
+        MBCVariant::NoMBC,
     ];
 
     #[test]
@@ -109,4 +321,51 @@ mod tests {
         }
     }
 
+    fn detect(rom: &[u8]) -> Option<MBCVariant> {
+        let metadata = CartridgeMetadata::from_buffer(rom).ok().unwrap();
+        MBCVariant::from_cartridge(&metadata, rom)
+    }
+
+    #[test]
+    fn bootleg_detection() {
+        let mut rom = test_rom_with_type("TEST", 0x00, 0);
+        assert_eq!(detect(&rom), Some(MBCVariant::NoMBC));
+        rom.resize(0x4_0000, 0);
+        assert_eq!(detect(&rom), Some(MBCVariant::MBC1));
+        rom.resize(0x40_0000, 0);
+        assert_eq!(detect(&rom), Some(MBCVariant::MBC5));
+
+        let mut rom = test_rom_with_type("TEST", 0x01, 0);
+        rom.resize(0x40_0000, 0);
+        assert_eq!(detect(&rom), Some(MBCVariant::MBC5));
+
+        let mut rom = test_rom("WISDOM TREE");
+        rom.resize(0x4_0000, 0);
+        assert_eq!(detect(&rom), Some(MBCVariant::WisdomTree));
+    }
+
+    #[test]
+    fn garbage_header_detection() {
+        // 0xFF is the type of the HuC1 with RAM and a battery
+        let mut rom = test_rom("TEST");
+        for value in rom[0x0134..0x0150].iter_mut() {
+            *value = 0xFF;
+        }
+        assert_eq!(detect(&rom), Some(MBCVariant::HuC1));
+
+        // without a ROM size in the header, the MBC30 is told apart by the file length
+        rom[0x0147] = 0x10;
+        assert_eq!(detect(&rom), Some(MBCVariant::MBC3));
+        rom.resize(0x40_0000, 0);
+        assert_eq!(detect(&rom), Some(MBCVariant::MBC30));
+    }
+
+    #[test]
+    fn mirroring() {
+        assert_eq!(mirror(0x8000, 0x8000), Some(0));
+        assert_eq!(mirror(0x1_4123, 0x8000), Some(0x4123));
+        assert_eq!(mirror(0x4000, 0x6000), Some(0x4000));
+        assert_eq!(mirror(0x7000, 0x6000), Some(0x1000));
+        assert_eq!(mirror(0, 0), None);
+    }
 }
@@ -0,0 +1,52 @@
+use super::MemoryBankController;
+use crate::util::savestate::{LoadSavestateError, Savestate, SavestateStream};
+
+/// Cartridges without a memory bank controller: the ROM is mapped directly
+/// and the optional RAM is always accessible
+pub struct NoMBC;
+
+impl Savestate for NoMBC {
+    fn dump_savestate(&self, _buffer: &mut Vec<u8>) {}
+
+    fn load_savestate<'a>(
+        &mut self,
+        _buffer: &mut SavestateStream<'a>,
+    ) -> Result<(), LoadSavestateError> {
+        Ok(())
+    }
+}
+
+impl MemoryBankController for NoMBC {
+    fn rom_bank(&self) -> u16 {
+        1
+    }
+
+    fn ram_bank(&self) -> u8 {
+        0
+    }
+
+    fn ram_enabled(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ram_access() {
+        let mut mbc = NoMBC;
+        let mut ram = vec![0; 0x2000];
+        mbc.write_ram(0xA123, 0x42, &mut ram);
+        assert_eq!(ram[0x123], 0x42);
+        assert_eq!(mbc.read_ram(0xA123, &ram), 0x42);
+    }
+
+    #[test]
+    fn read_without_ram() {
+        let mut mbc = NoMBC;
+        mbc.write_rom(0, 0);
+        assert_eq!(mbc.read_ram(0xA000, &[]), 0xFF);
+    }
+}
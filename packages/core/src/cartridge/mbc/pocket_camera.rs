@@ -0,0 +1,330 @@
+use super::{mirror, write_mirrored, MemoryBankController};
+use crate::cartridge::camera::{CameraSource, StillImage, CAMERA_HEIGHT, CAMERA_WIDTH};
+use crate::cartridge::cartridge_capability::CartridgeCapability;
+use crate::util::bits::get_bit;
+use crate::util::savestate::{
+    read_savestate_bool, read_savestate_byte, LoadSavestateError, Savestate, SavestateStream,
+};
+use std::cmp;
+
+const RAM_SIZE: usize = 0x2_0000;
+const REGISTER_COUNT: usize = 0x36;
+/// Bit of the RAM bank register that maps the sensor registers to 0xA000-0xBFFF
+const REGISTERS_BIT: u8 = 0x10;
+
+const CAPTURE_REGISTER: usize = 0;
+const EDGE_MODE_REGISTER: usize = 1;
+const EXPOSURE_HIGH_REGISTER: usize = 2;
+const EXPOSURE_LOW_REGISTER: usize = 3;
+const EDGE_RATIO_INVERT_REGISTER: usize = 4;
+/// Start of the 4x4 dithering matrix, made of 3 thresholds per pixel
+const DITHERING_REGISTER: usize = 6;
+
+/// Value of the edge mode bits that enables the 2D edge enhancement
+const EDGE_ENHANCEMENT_2D: u8 = 0xE0;
+const EDGE_RATIOS: [f32; 8] = [0.5, 0.75, 1.0, 1.25, 2.0, 3.0, 4.0, 5.0];
+/// Exposure at which the pixels of the source are used as they are
+const NEUTRAL_EXPOSURE: f32 = 4096.0;
+
+/// Offset in the RAM where captured images are written, as tiles
+const IMAGE_OFFSET: usize = 0x100;
+
+/// Game Boy Camera, with its M64282FP image sensor.
+/// Captures are taken from a `CameraSource` and complete instantly.
+pub struct PocketCamera {
+    rom_bank: u8,
+    ram_bank: u8,
+    ram_write_enabled: bool,
+    registers_mapped: bool,
+    registers: [u8; REGISTER_COUNT],
+    source: Box<dyn CameraSource>,
+}
+
+impl PocketCamera {
+    pub fn new(_capabilities: &[CartridgeCapability]) -> PocketCamera {
+        PocketCamera {
+            rom_bank: 1,
+            ram_bank: 0,
+            ram_write_enabled: false,
+            registers_mapped: false,
+            registers: [0; REGISTER_COUNT],
+            source: Box::new(StillImage::default()),
+        }
+    }
+
+    fn exposure(&self) -> f32 {
+        let exposure = u16::from(self.registers[EXPOSURE_HIGH_REGISTER]) << 8
+            | u16::from(self.registers[EXPOSURE_LOW_REGISTER]);
+        f32::from(exposure) / NEUTRAL_EXPOSURE
+    }
+
+    // brightness of a pixel after the exposure, the borders repeat the nearest pixel
+    fn exposed_pixel(&self, frame: &[u8], x: isize, y: isize) -> f32 {
+        let x = x.clamp(0, CAMERA_WIDTH as isize - 1) as usize;
+        let y = y.clamp(0, CAMERA_HEIGHT as isize - 1) as usize;
+        let pixel = frame.get(y * CAMERA_WIDTH + x).cloned().unwrap_or(0);
+        f32::from(pixel) * self.exposure()
+    }
+
+    fn processed_pixel(&self, frame: &[u8], x: usize, y: usize) -> u8 {
+        let (x, y) = (x as isize, y as isize);
+        let mut color = self.exposed_pixel(frame, x, y);
+        if self.registers[EDGE_MODE_REGISTER] & EDGE_ENHANCEMENT_2D == EDGE_ENHANCEMENT_2D {
+            let ratio =
+                EDGE_RATIOS[usize::from(self.registers[EDGE_RATIO_INVERT_REGISTER] >> 4) & 7];
+            let neighbors = self.exposed_pixel(frame, x - 1, y)
+                + self.exposed_pixel(frame, x + 1, y)
+                + self.exposed_pixel(frame, x, y - 1)
+                + self.exposed_pixel(frame, x, y + 1);
+            color += (color * 4.0 - neighbors) * ratio;
+        }
+
+        let color = color.clamp(0.0, 255.0) as u8;
+        if get_bit(self.registers[EDGE_RATIO_INVERT_REGISTER], 3) {
+            255 - color
+        } else {
+            color
+        }
+    }
+
+    // the dithering matrix gives 3 thresholds for each pixel of a 4x4 block
+    fn dither(&self, color: u8, x: usize, y: usize) -> u8 {
+        let thresholds = DITHERING_REGISTER + ((y & 3) * 4 + (x & 3)) * 3;
+        let thresholds = &self.registers[thresholds..thresholds + 3];
+        if color < thresholds[0] {
+            3
+        } else if color < thresholds[1] {
+            2
+        } else if color < thresholds[2] {
+            1
+        } else {
+            0
+        }
+    }
+
+    /// Takes a picture and writes it to the RAM as 16x14 tiles
+    fn capture(&mut self, ram: &mut [u8]) {
+        let frame = self.source.next_frame();
+        for y in 0..CAMERA_HEIGHT {
+            for x in 0..CAMERA_WIDTH {
+                let color = self.dither(self.processed_pixel(&frame, x, y), x, y);
+                let tile = (y / 8) * (CAMERA_WIDTH / 8) + x / 8;
+                let address = IMAGE_OFFSET + tile * 16 + (y % 8) * 2;
+                if address + 1 >= ram.len() {
+                    return;
+                }
+
+                let mask = 0x80 >> (x % 8);
+                for (plane, byte) in ram[address..address + 2].iter_mut().enumerate() {
+                    if get_bit(color, plane as u8) {
+                        *byte |= mask;
+                    } else {
+                        *byte &= !mask;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Savestate for PocketCamera {
+    fn dump_savestate(&self, buffer: &mut Vec<u8>) {
+        buffer.push(self.rom_bank);
+        buffer.push(self.ram_bank);
+        buffer.push(self.ram_write_enabled as u8);
+        buffer.push(self.registers_mapped as u8);
+        buffer.extend_from_slice(&self.registers);
+    }
+
+    fn load_savestate<'a>(
+        &mut self,
+        buffer: &mut SavestateStream<'a>,
+    ) -> Result<(), LoadSavestateError> {
+        self.rom_bank = read_savestate_byte(buffer)?;
+        self.ram_bank = read_savestate_byte(buffer)?;
+        self.ram_write_enabled = read_savestate_bool(buffer)?;
+        self.registers_mapped = read_savestate_bool(buffer)?;
+        for register in self.registers.iter_mut() {
+            *register = read_savestate_byte(buffer)?;
+        }
+        Ok(())
+    }
+}
+
+impl MemoryBankController for PocketCamera {
+    fn rom_bank(&self) -> u16 {
+        u16::from(self.rom_bank)
+    }
+
+    fn ram_bank(&self) -> u8 {
+        self.ram_bank
+    }
+
+    // the RAM can always be read, only writes need it to be enabled
+    fn ram_enabled(&self) -> bool {
+        true
+    }
+
+    fn write_rom(&mut self, address: usize, value: u8) {
+        match address {
+            0..=0x1FFF => self.ram_write_enabled = value & 0xF == 0xA,
+            0x2000..=0x3FFF => self.rom_bank = cmp::max(value & 0x3F, 1),
+            0x4000..=0x5FFF => {
+                self.registers_mapped = value & REGISTERS_BIT != 0;
+                self.ram_bank = value & 0xF;
+            }
+            _ => {}
+        }
+    }
+
+    fn ram_size(&self, _header_ram_size: usize) -> usize {
+        RAM_SIZE
+    }
+
+    fn read_ram(&self, address: usize, ram: &[u8]) -> u8 {
+        if !self.registers_mapped {
+            return mirror(self.relative_ram_address(address), ram.len())
+                .map_or(0xFF, |address| ram[address]);
+        }
+
+        // only the capture register can be read, and captures are never busy
+        match address & 0x7F {
+            CAPTURE_REGISTER => self.registers[CAPTURE_REGISTER] & !1,
+            _ => 0,
+        }
+    }
+
+    fn write_ram(&mut self, address: usize, value: u8, ram: &mut [u8]) -> bool {
+        if !self.registers_mapped {
+            return self.ram_write_enabled
+                && write_mirrored(ram, self.relative_ram_address(address), value);
+        }
+
+        let register = address & 0x7F;
+        if register == CAPTURE_REGISTER {
+            self.registers[CAPTURE_REGISTER] = value & 0x7;
+            if get_bit(value, 0) {
+                self.capture(ram);
+                return true;
+            }
+        } else if register < REGISTER_COUNT {
+            self.registers[register] = value;
+        }
+
+        false
+    }
+
+    fn set_camera_source(&mut self, source: Box<dyn CameraSource>) {
+        self.source = source;
+    }
+
+    fn take_camera_source(&mut self) -> Option<Box<dyn CameraSource>> {
+        Some(std::mem::replace(
+            &mut self.source,
+            Box::new(StillImage::default()),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn camera_with_frame(frame: Vec<u8>) -> PocketCamera {
+        let mut camera = PocketCamera::new(&[]);
+        camera.set_camera_source(Box::new(StillImage::new(frame)));
+        camera.write_rom(0x4000, REGISTERS_BIT);
+        // neutral exposure, and thresholds splitting the colors in 4 equal ranges
+        camera.write_ram(0xA002, 0x10, &mut []);
+        camera.write_ram(0xA003, 0x00, &mut []);
+        for pixel in 0..16 {
+            for (i, &threshold) in [0x40, 0x80, 0xC0].iter().enumerate() {
+                camera.write_ram(0xA006 + pixel * 3 + i, threshold, &mut []);
+            }
+        }
+        camera
+    }
+
+    #[test]
+    fn banking() {
+        let mut camera = PocketCamera::new(&[]);
+        camera.write_rom(0x2000, 0);
+        assert_eq!(camera.rom_bank(), 1);
+        camera.write_rom(0x2000, 0x7F);
+        assert_eq!(camera.rom_bank(), 0x3F);
+
+        camera.write_rom(0x4000, 0x0F);
+        assert_eq!(camera.relative_ram_address(0xA000), 0xF * 0x2000);
+        assert_eq!(camera.ram_size(0), RAM_SIZE);
+    }
+
+    #[test]
+    fn ram_writes_need_enable() {
+        let mut camera = PocketCamera::new(&[]);
+        let mut ram = vec![0; RAM_SIZE];
+        camera.write_ram(0xA000, 0x12, &mut ram);
+        assert_eq!(camera.read_ram(0xA000, &ram), 0);
+
+        camera.write_rom(0, 0x0A);
+        camera.write_ram(0xA000, 0x12, &mut ram);
+        assert_eq!(camera.read_ram(0xA000, &ram), 0x12);
+    }
+
+    #[test]
+    fn registers_mapping() {
+        let mut camera = PocketCamera::new(&[]);
+        let mut ram = vec![0x55; RAM_SIZE];
+        camera.write_rom(0, 0x0A);
+        camera.write_rom(0x4000, REGISTERS_BIT);
+        camera.write_ram(0xA001, 0xE0, &mut ram);
+        assert_eq!(camera.registers[EDGE_MODE_REGISTER], 0xE0);
+        assert_eq!(camera.read_ram(0xA001, &ram), 0);
+        assert_eq!(camera.read_ram(0xA080, &ram), 0);
+        assert_eq!(ram[1], 0x55);
+
+        camera.write_rom(0x4000, 0);
+        assert_eq!(camera.read_ram(0xA001, &ram), 0x55);
+    }
+
+    #[test]
+    fn capture() {
+        // columns of increasing brightness, one color per 2 pixels
+        let frame = (0..CAMERA_WIDTH * CAMERA_HEIGHT)
+            .map(|i| [0x00, 0x50, 0x90, 0xFF][(i % 8) / 2])
+            .collect();
+        let mut camera = camera_with_frame(frame);
+        let mut ram = vec![0; RAM_SIZE];
+        camera.write_ram(0xA000, 1, &mut ram);
+        assert_eq!(camera.read_ram(0xA000, &ram), 0);
+
+        // colors 3, 2, 1 and 0 from left to right in every row of every tile
+        for row in 0..CAMERA_HEIGHT / 8 * 16 * 8 {
+            let address = IMAGE_OFFSET + row * 2;
+            assert_eq!(ram[address..address + 2], [0b1100_1100, 0b1111_0000]);
+        }
+    }
+
+    #[test]
+    fn capture_inverted() {
+        let mut camera = camera_with_frame(vec![0; CAMERA_WIDTH * CAMERA_HEIGHT]);
+        let mut ram = vec![0; RAM_SIZE];
+        camera.write_ram(0xA004, 0x08, &mut ram);
+        camera.write_ram(0xA000, 1, &mut ram);
+        assert_eq!(ram[IMAGE_OFFSET..IMAGE_OFFSET + 2], [0, 0]);
+    }
+
+    #[test]
+    fn edge_enhancement() {
+        // a single bright pixel stands out more against the dark pixels around it
+        let mut frame = vec![0x40; CAMERA_WIDTH * CAMERA_HEIGHT];
+        frame[CAMERA_WIDTH + 1] = 0x60;
+        let mut camera = camera_with_frame(frame.clone());
+        assert_eq!(camera.processed_pixel(&frame, 1, 1), 0x60);
+        assert_eq!(camera.processed_pixel(&frame, 1, 0), 0x40);
+
+        camera.registers[EDGE_MODE_REGISTER] = EDGE_ENHANCEMENT_2D;
+        camera.registers[EDGE_RATIO_INVERT_REGISTER] = 2 << 4;
+        assert_eq!(camera.processed_pixel(&frame, 1, 1), 0xE0);
+        assert_eq!(camera.processed_pixel(&frame, 1, 0), 0x20);
+    }
+}
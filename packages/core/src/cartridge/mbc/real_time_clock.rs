@@ -1,28 +1,55 @@
-// TODO: implement writing to clock
+use crate::util::clock::{Clock, SystemClock};
+use crate::util::savestate::{
+    read_savestate_bool, read_savestate_byte, read_savestate_enum, LoadSavestateError, Savestate,
+    SavestateStream,
+};
+use std::rc::Rc;
 
+const DAY_HIGH_BIT: u8 = 0b1;
+const HALT_BIT: u8 = 0b100_0000;
+const DAY_CARRY_BIT: u8 = 0b1000_0000;
+
+/// Size of the clock footer appended to battery saves by VBA-M, BGB and SameBoy,
+/// with a 64-bit timestamp
+pub const FOOTER_SIZE: usize = 48;
+/// Size of the older variant of the footer, with a 32-bit timestamp
+pub const SHORT_FOOTER_SIZE: usize = 44;
+
+/// The MBC3 real-time clock.
+/// The counters keep running while the emulator is not, using the time given by a `Clock`.
+/// Time is only accounted for when the counters are accessed, so nothing has to be clocked.
+#[derive(Clone)]
 pub struct RealTimeClock {
+    clock: Rc<dyn Clock>,
     active_register: RTCRegister,
+    state: ClockState,
     latched_state: ClockState,
+    last_update: u64,
     prelatch_triggered: bool,
 }
 
 impl RealTimeClock {
-    pub fn new() -> RealTimeClock {
+    pub fn new(clock: Rc<dyn Clock>) -> RealTimeClock {
         RealTimeClock {
-            active_register: RTCRegister::Seconds, // TODO: is it tho
-            latched_state: ClockState::now(),
+            last_update: clock.now(),
+            clock,
+            active_register: RTCRegister::Seconds,
+            state: ClockState::default(),
+            latched_state: ClockState::default(),
             prelatch_triggered: false,
         }
     }
 
+    /// Replaces the source of time. The counters keep their current values.
+    pub fn set_clock(&mut self, clock: Rc<dyn Clock>) {
+        self.update();
+        self.last_update = clock.now();
+        self.clock = clock;
+    }
+
+    /// Returns the latched value of the active register
     pub fn active_value(&self) -> u8 {
-        match self.active_register() {
-            RTCRegister::Seconds => self.latched_state.seconds,
-            RTCRegister::Minutes => self.latched_state.minutes,
-            RTCRegister::Hours => self.latched_state.hours,
-            RTCRegister::DayLow => self.latched_state.day_counter as u8,
-            RTCRegister::DayHigh => (self.latched_state.day_counter >> 8) as u8,
-        }
+        self.latched_state.register(self.active_register) | !self.active_register.mask()
     }
 
     pub fn active_register(&self) -> &RTCRegister {
@@ -33,23 +60,119 @@ impl RealTimeClock {
         self.active_register = register;
     }
 
-    pub fn latch(&mut self) {
-        if !self.prelatch_triggered {
-            self.prelatch_triggered = true;
-            return;
+    /// Writes to the active register, changing the running counters.
+    /// Returns true if the counters changed.
+    pub fn write(&mut self, value: u8) -> bool {
+        self.update();
+        let previous = self.state;
+        self.state.set_register(self.active_register, value);
+        self.state != previous
+    }
+
+    /// Latches the counters when 0 then 1 are written
+    pub fn latch(&mut self, value: u8) {
+        if value == 1 && self.prelatch_triggered {
+            self.update();
+            self.latched_state = self.state;
+        }
+
+        self.prelatch_triggered = value == 0;
+    }
+
+    /// Returns the value of the running counters
+    pub fn state(&self) -> ClockState {
+        let mut state = self.state;
+        if !state.halted {
+            state.advance(self.clock.now().saturating_sub(self.last_update));
+        }
+        state
+    }
+
+    pub fn latched_state(&self) -> ClockState {
+        self.latched_state
+    }
+
+    /// Dumps the clock in the footer format used by other emulators:
+    /// the running then latched registers as 32-bit little endian values,
+    /// followed by the UNIX timestamp of the save as a 64-bit value
+    pub fn dump_footer(&self, buffer: &mut Vec<u8>) {
+        for state in [self.state(), self.latched_state].iter() {
+            for &register in REGISTERS.iter() {
+                buffer.extend_from_slice(&u32::from(state.register(register)).to_le_bytes());
+            }
+        }
+        buffer.extend_from_slice(&self.clock.now().to_le_bytes());
+    }
+
+    /// Loads a footer made by `dump_footer` or by another emulator,
+    /// advancing the clock by the time elapsed since it was saved.
+    /// Returns false if the footer has an unknown size.
+    pub fn load_footer(&mut self, footer: &[u8]) -> bool {
+        if footer.len() != FOOTER_SIZE && footer.len() != SHORT_FOOTER_SIZE {
+            return false;
+        }
+
+        let mut values = footer
+            .chunks(4)
+            .map(|chunk| u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]));
+        for state in [&mut self.state, &mut self.latched_state].iter_mut() {
+            for (&register, value) in REGISTERS.iter().zip(&mut values) {
+                state.set_register(register, value as u8);
+            }
         }
 
-        self.latched_state = ClockState::now()
+        let timestamp = values.next().unwrap_or(0);
+        let timestamp = if footer.len() == FOOTER_SIZE {
+            u64::from(timestamp) | u64::from(values.next().unwrap_or(0)) << 32
+        } else {
+            u64::from(timestamp)
+        };
+
+        self.last_update = timestamp;
+        self.update();
+        true
+    }
+
+    fn update(&mut self) {
+        self.state = self.state();
+        self.last_update = self.clock.now();
+    }
+}
+
+impl Default for RealTimeClock {
+    fn default() -> RealTimeClock {
+        RealTimeClock::new(Rc::new(SystemClock))
+    }
+}
+
+impl Savestate for RealTimeClock {
+    fn dump_savestate(&self, buffer: &mut Vec<u8>) {
+        buffer.push(self.active_register as u8);
+        buffer.push(self.prelatch_triggered as u8);
+        self.latched_state.dump_savestate(buffer);
+        self.state().dump_savestate(buffer);
+    }
+
+    fn load_savestate<'a>(
+        &mut self,
+        buffer: &mut SavestateStream<'a>,
+    ) -> Result<(), LoadSavestateError> {
+        self.active_register = read_savestate_enum(buffer, "RTCRegister", RTCRegister::from_value)?;
+        self.prelatch_triggered = read_savestate_bool(buffer)?;
+        self.latched_state.load_savestate(buffer)?;
+        self.state.load_savestate(buffer)?;
+        self.last_update = self.clock.now();
+        Ok(())
     }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum RTCRegister {
-    Seconds,
-    Minutes,
-    Hours,
-    DayLow,
-    DayHigh,
+    Seconds = 8,
+    Minutes = 9,
+    Hours = 0xA,
+    DayLow = 0xB,
+    DayHigh = 0xC,
 }
 
 impl RTCRegister {
@@ -63,22 +186,277 @@ impl RTCRegister {
             _ => None,
         }
     }
+
+    /// Returns the bits of the register that are actually stored
+    pub fn mask(self) -> u8 {
+        match self {
+            RTCRegister::Seconds | RTCRegister::Minutes => 0x3F,
+            RTCRegister::Hours => 0x1F,
+            RTCRegister::DayLow => 0xFF,
+            RTCRegister::DayHigh => DAY_CARRY_BIT | HALT_BIT | DAY_HIGH_BIT,
+        }
+    }
 }
 
+/// Values of the clock counters
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
 pub struct ClockState {
-    seconds: u8,
-    minutes: u8,
-    hours: u8,
-    day_counter: u16,
+    pub seconds: u8,
+    pub minutes: u8,
+    pub hours: u8,
+    /// 9-bit day counter
+    pub days: u16,
+    pub halted: bool,
+    /// Set when the day counter overflows, until it is cleared by the game
+    pub day_carry: bool,
 }
 
 impl ClockState {
-    pub fn now() -> ClockState {
-        ClockState {
-            seconds: 0,
-            minutes: 0,
-            hours: 0,
-            day_counter: 0,
+    pub fn register(&self, register: RTCRegister) -> u8 {
+        match register {
+            RTCRegister::Seconds => self.seconds,
+            RTCRegister::Minutes => self.minutes,
+            RTCRegister::Hours => self.hours,
+            RTCRegister::DayLow => self.days as u8,
+            RTCRegister::DayHigh => {
+                let mut value = (self.days >> 8) as u8 & DAY_HIGH_BIT;
+                if self.halted {
+                    value |= HALT_BIT;
+                }
+                if self.day_carry {
+                    value |= DAY_CARRY_BIT;
+                }
+                value
+            }
+        }
+    }
+
+    pub fn set_register(&mut self, register: RTCRegister, value: u8) {
+        let value = value & register.mask();
+        match register {
+            RTCRegister::Seconds => self.seconds = value,
+            RTCRegister::Minutes => self.minutes = value,
+            RTCRegister::Hours => self.hours = value,
+            RTCRegister::DayLow => self.days = (self.days & 0x100) | u16::from(value),
+            RTCRegister::DayHigh => {
+                self.days = (self.days & 0xFF) | (u16::from(value & DAY_HIGH_BIT) << 8);
+                self.halted = value & HALT_BIT != 0;
+                self.day_carry = value & DAY_CARRY_BIT != 0;
+            }
         }
     }
+
+    /// Advances the counters by the given number of seconds.
+    /// Out of range values written by the game are brought back in range on the next carry.
+    pub fn advance(&mut self, seconds: u64) {
+        if seconds == 0 {
+            return;
+        }
+
+        let total = u64::from(self.seconds) + seconds;
+        self.seconds = (total % 60) as u8;
+        let total = u64::from(self.minutes) + total / 60;
+        self.minutes = (total % 60) as u8;
+        let total = u64::from(self.hours) + total / 60;
+        self.hours = (total % 24) as u8;
+        let days = u64::from(self.days) + total / 24;
+        if days > 0x1FF {
+            self.day_carry = true;
+        }
+        self.days = (days % 0x200) as u16;
+    }
+}
+
+impl Savestate for ClockState {
+    fn dump_savestate(&self, buffer: &mut Vec<u8>) {
+        for &register in REGISTERS.iter() {
+            buffer.push(self.register(register));
+        }
+    }
+
+    fn load_savestate<'a>(
+        &mut self,
+        buffer: &mut SavestateStream<'a>,
+    ) -> Result<(), LoadSavestateError> {
+        for &register in REGISTERS.iter() {
+            self.set_register(register, read_savestate_byte(buffer)?);
+        }
+        Ok(())
+    }
+}
+
+const REGISTERS: [RTCRegister; 5] = [
+    RTCRegister::Seconds,
+    RTCRegister::Minutes,
+    RTCRegister::Hours,
+    RTCRegister::DayLow,
+    RTCRegister::DayHigh,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::clock::FakeClock;
+
+    fn rtc() -> (Rc<FakeClock>, RealTimeClock) {
+        let clock = Rc::new(FakeClock::new(1000));
+        let rtc = RealTimeClock::new(clock.clone());
+        (clock, rtc)
+    }
+
+    fn latch(rtc: &mut RealTimeClock) {
+        rtc.latch(0);
+        rtc.latch(1);
+    }
+
+    fn write(rtc: &mut RealTimeClock, register: RTCRegister, value: u8) {
+        rtc.set_active_register(register);
+        rtc.write(value);
+    }
+
+    #[test]
+    fn counts_elapsed_time() {
+        let (clock, mut rtc) = rtc();
+        clock.advance(2 * 86400 + 3 * 3600 + 4 * 60 + 5);
+        latch(&mut rtc);
+        assert_eq!(
+            rtc.latched_state(),
+            ClockState {
+                seconds: 5,
+                minutes: 4,
+                hours: 3,
+                days: 2,
+                halted: false,
+                day_carry: false,
+            }
+        );
+    }
+
+    #[test]
+    fn day_rollover() {
+        let (clock, mut rtc) = rtc();
+        write(&mut rtc, RTCRegister::Hours, 23);
+        write(&mut rtc, RTCRegister::Minutes, 59);
+        write(&mut rtc, RTCRegister::Seconds, 59);
+        write(&mut rtc, RTCRegister::DayLow, 0xFF);
+        write(&mut rtc, RTCRegister::DayHigh, 1);
+
+        clock.advance(1);
+        latch(&mut rtc);
+        rtc.set_active_register(RTCRegister::DayLow);
+        assert_eq!(rtc.active_value(), 0);
+        rtc.set_active_register(RTCRegister::DayHigh);
+        assert_eq!(
+            rtc.active_value() & RTCRegister::DayHigh.mask(),
+            DAY_CARRY_BIT
+        );
+
+        // the carry stays set until cleared
+        clock.advance(86400);
+        latch(&mut rtc);
+        assert!(rtc.latched_state().day_carry);
+        assert_eq!(rtc.latched_state().days, 1);
+        rtc.write(0);
+        latch(&mut rtc);
+        assert!(!rtc.latched_state().day_carry);
+    }
+
+    #[test]
+    fn halt_stops_counting() {
+        let (clock, mut rtc) = rtc();
+        clock.advance(10);
+        write(&mut rtc, RTCRegister::DayHigh, HALT_BIT);
+        clock.advance(100);
+        latch(&mut rtc);
+        assert_eq!(rtc.latched_state().seconds, 10);
+
+        rtc.write(0);
+        clock.advance(5);
+        latch(&mut rtc);
+        assert_eq!(rtc.latched_state().seconds, 15);
+    }
+
+    #[test]
+    fn reads_are_latched() {
+        let (clock, mut rtc) = rtc();
+        clock.advance(10);
+        rtc.set_active_register(RTCRegister::Seconds);
+        assert_eq!(rtc.active_value() & 0x3F, 0);
+
+        rtc.latch(1);
+        assert_eq!(rtc.active_value() & 0x3F, 0);
+
+        latch(&mut rtc);
+        assert_eq!(rtc.active_value() & 0x3F, 10);
+    }
+
+    #[test]
+    fn writes_are_masked() {
+        let (_, mut rtc) = rtc();
+        write(&mut rtc, RTCRegister::Seconds, 0xFF);
+        write(&mut rtc, RTCRegister::Hours, 0xFF);
+        latch(&mut rtc);
+        assert_eq!(rtc.latched_state().seconds, 0x3F);
+        assert_eq!(rtc.latched_state().hours, 0x1F);
+
+        rtc.set_active_register(RTCRegister::Hours);
+        assert_eq!(rtc.active_value(), 0xFF);
+    }
+
+    #[test]
+    fn footer_round_trip() {
+        let (clock, mut rtc) = rtc();
+        clock.advance(90);
+        latch(&mut rtc);
+        clock.advance(30);
+        let mut footer = Vec::new();
+        rtc.dump_footer(&mut footer);
+        assert_eq!(footer.len(), FOOTER_SIZE);
+
+        let (_, mut loaded) = self::rtc();
+        assert!(loaded.load_footer(&footer));
+        assert_eq!(loaded.state(), rtc.state());
+        assert_eq!(loaded.latched_state(), rtc.latched_state());
+    }
+
+    #[test]
+    fn footer_advances_by_elapsed_time() {
+        let mut footer = Vec::new();
+        for &value in [5, 4, 3, 0xFF, 1, 0, 0, 0, 0, 0].iter() {
+            footer.extend_from_slice(&(value as u32).to_le_bytes());
+        }
+        footer.extend_from_slice(&940u32.to_le_bytes());
+        assert_eq!(footer.len(), SHORT_FOOTER_SIZE);
+
+        // the fake clock is at 1000, 60 seconds after the save
+        let (_, mut rtc) = rtc();
+        assert!(rtc.load_footer(&footer));
+        assert_eq!(
+            rtc.state(),
+            ClockState {
+                seconds: 5,
+                minutes: 5,
+                hours: 3,
+                days: 0x1FF,
+                halted: false,
+                day_carry: false,
+            }
+        );
+        assert_eq!(rtc.latched_state(), ClockState::default());
+        assert!(!rtc.load_footer(&footer[..40]));
+    }
+
+    #[test]
+    fn savestate_round_trip() {
+        let (clock, mut rtc) = rtc();
+        clock.advance(12345);
+        latch(&mut rtc);
+        let mut buffer = Vec::new();
+        rtc.dump_savestate(&mut buffer);
+
+        let (_, mut loaded) = self::rtc();
+        assert!(loaded.load_savestate(&mut buffer.iter()).is_ok());
+        assert_eq!(loaded.latched_state(), rtc.latched_state());
+        assert_eq!(loaded.state(), rtc.state());
+    }
 }
@@ -0,0 +1,153 @@
+use super::MemoryBankController;
+use crate::cartridge::cartridge_capability::CartridgeCapability;
+use crate::cartridge::cartridge_metadata::{NINTENDO_LOGO, NINTENDO_LOGO_OFFSET};
+use crate::util::savestate::{read_savestate_byte, LoadSavestateError, Savestate, SavestateStream};
+
+/// Bits of the ROM bank register that unlock the base bank and mask registers
+const UNLOCK_BITS: u8 = 0x30;
+
+/// Sachen MMC1, used by Sachen's unlicensed games.
+/// The ROM banks are split between an outer base bank, for multi-game cartridges,
+/// and an inner bank selected by the game, the mask telling which bits come from which.
+///
+/// The cartridges hide the Nintendo logo from the header: while the boot ROM runs,
+/// the mapper scrambles the addresses of the header to read the logo from elsewhere.
+/// The boot ROM isn't emulated, so this is only used to detect the cartridges.
+pub struct SachenMMC1 {
+    base_bank: u8,
+    rom_bank: u8,
+    mask: u8,
+}
+
+impl SachenMMC1 {
+    pub fn new(_capabilities: &[CartridgeCapability]) -> SachenMMC1 {
+        SachenMMC1 {
+            base_bank: 0,
+            rom_bank: 1,
+            mask: 0,
+        }
+    }
+
+    /// Detects Sachen cartridges by their logo protection:
+    /// the logo isn't in the header, but can be read through the scrambled addresses
+    pub fn is_sachen(rom: &[u8]) -> bool {
+        if rom.len() < 0x8000 {
+            return false;
+        }
+
+        let header_logo = &rom[NINTENDO_LOGO_OFFSET..NINTENDO_LOGO_OFFSET + NINTENDO_LOGO.len()];
+        header_logo != NINTENDO_LOGO
+            && NINTENDO_LOGO
+                .iter()
+                .enumerate()
+                .all(|(i, &byte)| rom[scramble_header_address(NINTENDO_LOGO_OFFSET + i)] == byte)
+    }
+}
+
+/// Address read for the header at the given address while the logo protection is active:
+/// the access is moved to 0x0180-0x01FF and address lines 0 and 6, 1 and 4 are swapped
+fn scramble_header_address(address: usize) -> usize {
+    let address = address | 0x80;
+    address & !0x53
+        | (address & 0x40) >> 6
+        | (address & 0x10) >> 3
+        | (address & 0x02) << 3
+        | (address & 0x01) << 6
+}
+
+impl Savestate for SachenMMC1 {
+    fn dump_savestate(&self, buffer: &mut Vec<u8>) {
+        buffer.push(self.base_bank);
+        buffer.push(self.rom_bank);
+        buffer.push(self.mask);
+    }
+
+    fn load_savestate<'a>(
+        &mut self,
+        buffer: &mut SavestateStream<'a>,
+    ) -> Result<(), LoadSavestateError> {
+        self.base_bank = read_savestate_byte(buffer)?;
+        self.rom_bank = read_savestate_byte(buffer)?;
+        self.mask = read_savestate_byte(buffer)?;
+        Ok(())
+    }
+}
+
+impl MemoryBankController for SachenMMC1 {
+    fn rom_bank(&self) -> u16 {
+        u16::from(self.base_bank & self.mask | self.rom_bank & !self.mask)
+    }
+
+    fn ram_bank(&self) -> u8 {
+        0
+    }
+
+    fn ram_enabled(&self) -> bool {
+        false
+    }
+
+    fn relative_rom_address(&self, address: usize) -> usize {
+        if address < 0x4000 {
+            usize::from(self.base_bank & self.mask) * 0x4000 + address
+        } else {
+            usize::from(self.rom_bank()) * 0x4000 + (address - 0x4000)
+        }
+    }
+
+    fn write_rom(&mut self, address: usize, value: u8) {
+        let unlocked = self.rom_bank & UNLOCK_BITS == UNLOCK_BITS;
+        match address {
+            0..=0x1FFF if unlocked => self.base_bank = value,
+            0x2000..=0x3FFF => self.rom_bank = if value == 0 { 1 } else { value },
+            0x4000..=0x5FFF if unlocked => self.mask = value,
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scrambling() {
+        assert_eq!(scramble_header_address(0x0104), 0x0184);
+        assert_eq!(scramble_header_address(0x0101), 0x01C0);
+        assert_eq!(scramble_header_address(0x0152), 0x0193);
+        assert_eq!(scramble_header_address(0x01FF), 0x01FF);
+    }
+
+    #[test]
+    fn bank_switching() {
+        let mut mbc = SachenMMC1::new(&[]);
+        mbc.write_rom(0x2000, 0);
+        assert_eq!(mbc.rom_bank(), 1);
+
+        // the base bank and mask can't be changed until the upper bits of the bank are set
+        mbc.write_rom(0x0000, 0x10);
+        assert_eq!(mbc.base_bank, 0);
+        mbc.write_rom(0x2000, 0x30);
+        mbc.write_rom(0x0000, 0x10);
+        mbc.write_rom(0x4000, 0xF0);
+        mbc.write_rom(0x2000, 0x03);
+        assert_eq!(mbc.rom_bank(), 0x13);
+        assert_eq!(mbc.relative_rom_address(0x0000), 0x10 * 0x4000);
+        assert_eq!(mbc.relative_rom_address(0x4000), 0x13 * 0x4000);
+    }
+
+    #[test]
+    fn detection() {
+        let mut rom = vec![0; 0x8000];
+        assert!(!SachenMMC1::is_sachen(&rom));
+
+        for (i, &byte) in NINTENDO_LOGO.iter().enumerate() {
+            rom[scramble_header_address(NINTENDO_LOGO_OFFSET + i)] = byte;
+        }
+        assert!(SachenMMC1::is_sachen(&rom));
+
+        // licensed cartridges have the logo in the header
+        rom[NINTENDO_LOGO_OFFSET..NINTENDO_LOGO_OFFSET + NINTENDO_LOGO.len()]
+            .copy_from_slice(&NINTENDO_LOGO);
+        assert!(!SachenMMC1::is_sachen(&rom));
+    }
+}
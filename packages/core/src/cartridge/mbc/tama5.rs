@@ -0,0 +1,497 @@
+use super::MemoryBankController;
+use crate::cartridge::cartridge_capability::CartridgeCapability;
+use crate::util::clock::{Clock, SystemClock};
+use crate::util::savestate::{read_savestate_byte, LoadSavestateError, Savestate, SavestateStream};
+use std::mem;
+use std::rc::Rc;
+
+/// Size of the RAM built in the TAMA5
+const RAM_SIZE: usize = 32;
+/// Size of the clock footer appended to battery saves: the UNIX timestamp of the save
+/// followed by the calendar registers
+pub const FOOTER_SIZE: usize = 8 + 7;
+
+const ROM_BANK_LOW: u8 = 0x0;
+const ROM_BANK_HIGH: u8 = 0x1;
+const WRITE_LOW: u8 = 0x4;
+const WRITE_HIGH: u8 = 0x5;
+/// Holds the command in its upper 3 bits and the upper bit of the address
+const ADDRESS_HIGH: u8 = 0x6;
+/// Writing the lower bits of the address runs the command
+const ADDRESS_LOW: u8 = 0x7;
+/// Reads 1 in its lower bit when the chip is ready for a command
+const STATUS: u8 = 0xA;
+const READ_LOW: u8 = 0xC;
+const READ_HIGH: u8 = 0xD;
+
+/// Bandai TAMA5, used by Tamagotchi 3. Everything goes through two registers
+/// at 0xA000 and 0xA001: the second one selects a 4-bit register which the first one
+/// reads or writes. The RAM and the clock are accessed by sending commands.
+pub struct TAMA5 {
+    selected_register: u8,
+    registers: [u8; 16],
+    /// Value returned by the last read command
+    read_value: u8,
+    clock: TAMA5Clock,
+}
+
+impl TAMA5 {
+    pub fn new(_capabilities: &[CartridgeCapability]) -> TAMA5 {
+        let mut registers = [0; 16];
+        registers[ROM_BANK_LOW as usize] = 1;
+        TAMA5 {
+            selected_register: 0,
+            registers,
+            read_value: 0,
+            clock: TAMA5Clock::new(Rc::new(SystemClock)),
+        }
+    }
+
+    /// Returns true if the command changed the RAM or the clock
+    fn run_command(&mut self, ram: &mut [u8]) -> bool {
+        let address_high = self.registers[ADDRESS_HIGH as usize];
+        let index = self.registers[ADDRESS_LOW as usize];
+        let address = usize::from(address_high & 1) << 4 | usize::from(index);
+        let value = self.registers[WRITE_HIGH as usize] << 4 | self.registers[WRITE_LOW as usize];
+
+        match address_high >> 1 {
+            0 => {
+                if let Some(byte) = ram.get_mut(address) {
+                    return mem::replace(byte, value) != value;
+                }
+            }
+            1 => self.read_value = ram.get(address).cloned().unwrap_or(0xFF),
+            2 => return self.clock.set_nibble(index, value & 0xF),
+            3 => self.read_value = self.clock.nibble(index),
+            _ => {}
+        }
+
+        false
+    }
+}
+
+impl Savestate for TAMA5 {
+    fn dump_savestate(&self, buffer: &mut Vec<u8>) {
+        buffer.push(self.selected_register);
+        buffer.extend_from_slice(&self.registers);
+        buffer.push(self.read_value);
+        self.clock.dump_savestate(buffer);
+    }
+
+    fn load_savestate<'a>(
+        &mut self,
+        buffer: &mut SavestateStream<'a>,
+    ) -> Result<(), LoadSavestateError> {
+        self.selected_register = read_savestate_byte(buffer)? & 0xF;
+        for register in self.registers.iter_mut() {
+            *register = read_savestate_byte(buffer)? & 0xF;
+        }
+        self.read_value = read_savestate_byte(buffer)?;
+        self.clock.load_savestate(buffer)
+    }
+}
+
+impl MemoryBankController for TAMA5 {
+    fn rom_bank(&self) -> u16 {
+        u16::from(self.registers[ROM_BANK_HIGH as usize] & 1) << 4
+            | u16::from(self.registers[ROM_BANK_LOW as usize])
+    }
+
+    fn ram_bank(&self) -> u8 {
+        0
+    }
+
+    // the registers are always accessible
+    fn ram_enabled(&self) -> bool {
+        true
+    }
+
+    fn ram_size(&self, _header_ram_size: usize) -> usize {
+        RAM_SIZE
+    }
+
+    fn read_ram(&self, address: usize, _ram: &[u8]) -> u8 {
+        if address & 1 != 0 {
+            return 0xFF;
+        }
+
+        match self.selected_register {
+            READ_LOW => 0xF0 | self.read_value & 0xF,
+            READ_HIGH => 0xF0 | self.read_value >> 4,
+            STATUS => 0xF1,
+            _ => 0xFF,
+        }
+    }
+
+    fn write_ram(&mut self, address: usize, value: u8, ram: &mut [u8]) -> bool {
+        if address & 1 != 0 {
+            self.selected_register = value & 0xF;
+            return false;
+        }
+
+        self.registers[self.selected_register as usize] = value & 0xF;
+        self.selected_register == ADDRESS_LOW && self.run_command(ram)
+    }
+
+    fn set_clock(&mut self, clock: Rc<dyn Clock>) {
+        self.clock.set_clock(clock);
+    }
+
+    fn dump_save_footer(&self, buffer: &mut Vec<u8>) {
+        self.clock.dump_footer(buffer);
+    }
+
+    fn load_save_footer(&mut self, footer: &[u8]) -> bool {
+        self.clock.load_footer(footer)
+    }
+}
+
+/// The TAMA5 clock, a calendar read and written one BCD digit at a time
+pub struct TAMA5Clock {
+    clock: Rc<dyn Clock>,
+    calendar: Calendar,
+    last_update: u64,
+}
+
+impl TAMA5Clock {
+    pub fn new(clock: Rc<dyn Clock>) -> TAMA5Clock {
+        TAMA5Clock {
+            last_update: clock.now(),
+            clock,
+            calendar: Calendar::default(),
+        }
+    }
+
+    /// Replaces the source of time. The calendar keeps its current value.
+    pub fn set_clock(&mut self, clock: Rc<dyn Clock>) {
+        self.update();
+        self.last_update = clock.now();
+        self.clock = clock;
+    }
+
+    pub fn calendar(&self) -> Calendar {
+        let mut calendar = self.calendar;
+        calendar.advance(self.clock.now().saturating_sub(self.last_update));
+        calendar
+    }
+
+    /// Returns a digit of the calendar: the seconds, minutes and hours are at 0-5,
+    /// the day of the week at 6, then the day, month and year at 7-12, ones first
+    fn nibble(&self, index: u8) -> u8 {
+        let calendar = self.calendar();
+        if index == 6 {
+            return calendar.weekday;
+        }
+
+        match digit(index) {
+            Some((field, true)) => calendar.fields()[field] / 10,
+            Some((field, false)) => calendar.fields()[field] % 10,
+            None => 0,
+        }
+    }
+
+    /// Returns true if the calendar changed
+    fn set_nibble(&mut self, index: u8, value: u8) -> bool {
+        self.update();
+        let previous = self.calendar;
+        if index == 6 {
+            self.calendar.weekday = value % 7;
+        } else {
+            let mut fields = self.calendar.fields();
+            match digit(index) {
+                Some((field, true)) => fields[field] = value * 10 + fields[field] % 10,
+                Some((field, false)) => fields[field] = fields[field] / 10 * 10 + value,
+                None => {}
+            }
+            self.calendar.set_fields(fields);
+        }
+        self.calendar != previous
+    }
+
+    /// Dumps the clock in the footer format: the UNIX timestamp of the save as a
+    /// 64-bit little endian value, followed by the calendar fields
+    pub fn dump_footer(&self, buffer: &mut Vec<u8>) {
+        buffer.extend_from_slice(&self.clock.now().to_le_bytes());
+        self.calendar().dump_savestate(buffer);
+    }
+
+    /// Loads a footer made by `dump_footer`, advancing the clock by the time elapsed
+    /// since it was saved. Returns false if the footer has an unknown size.
+    pub fn load_footer(&mut self, footer: &[u8]) -> bool {
+        if footer.len() != FOOTER_SIZE {
+            return false;
+        }
+
+        let mut timestamp = [0; 8];
+        timestamp.copy_from_slice(&footer[..8]);
+        if self
+            .calendar
+            .load_savestate(&mut footer[8..].iter())
+            .is_err()
+        {
+            return false;
+        }
+
+        self.last_update = u64::from_le_bytes(timestamp);
+        self.update();
+        true
+    }
+
+    fn update(&mut self) {
+        self.calendar = self.calendar();
+        self.last_update = self.clock.now();
+    }
+}
+
+impl Savestate for TAMA5Clock {
+    fn dump_savestate(&self, buffer: &mut Vec<u8>) {
+        self.calendar().dump_savestate(buffer);
+    }
+
+    fn load_savestate<'a>(
+        &mut self,
+        buffer: &mut SavestateStream<'a>,
+    ) -> Result<(), LoadSavestateError> {
+        self.calendar.load_savestate(buffer)?;
+        self.last_update = self.clock.now();
+        Ok(())
+    }
+}
+
+/// Returns the calendar field of a digit index, and whether it is the tens digit
+fn digit(index: u8) -> Option<(usize, bool)> {
+    let index = usize::from(index);
+    match index {
+        0..=5 => Some((index / 2, index % 2 == 1)),
+        7..=12 => Some(((index - 7) / 2 + 3, (index - 7) % 2 == 1)),
+        _ => None,
+    }
+}
+
+/// Date and time counted by the TAMA5 clock
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Calendar {
+    pub seconds: u8,
+    pub minutes: u8,
+    pub hours: u8,
+    /// Day of the week, from 0 to 6
+    pub weekday: u8,
+    /// Day of the month, starting at 1
+    pub day: u8,
+    /// Month, starting at 1
+    pub month: u8,
+    /// Years since the last century, which are leap years when they are multiples of 4
+    pub year: u8,
+}
+
+impl Default for Calendar {
+    fn default() -> Calendar {
+        Calendar {
+            seconds: 0,
+            minutes: 0,
+            hours: 0,
+            weekday: 0,
+            day: 1,
+            month: 1,
+            year: 0,
+        }
+    }
+}
+
+impl Calendar {
+    // fields made of two digits, in the order of the digit indices
+    fn fields(&self) -> [u8; 6] {
+        [
+            self.seconds,
+            self.minutes,
+            self.hours,
+            self.day,
+            self.month,
+            self.year,
+        ]
+    }
+
+    fn set_fields(&mut self, fields: [u8; 6]) {
+        self.seconds = fields[0];
+        self.minutes = fields[1];
+        self.hours = fields[2];
+        self.day = fields[3];
+        self.month = fields[4];
+        self.year = fields[5];
+    }
+
+    fn days_in_month(&self) -> u8 {
+        match self.month {
+            2 if self.year & 3 == 0 => 29,
+            2 => 28,
+            4 | 6 | 9 | 11 => 30,
+            _ => 31,
+        }
+    }
+
+    /// Advances the calendar by the given number of seconds
+    pub fn advance(&mut self, seconds: u64) {
+        if seconds == 0 {
+            return;
+        }
+
+        let total = u64::from(self.seconds) + seconds;
+        self.seconds = (total % 60) as u8;
+        let total = u64::from(self.minutes) + total / 60;
+        self.minutes = (total % 60) as u8;
+        let total = u64::from(self.hours) + total / 60;
+        self.hours = (total % 24) as u8;
+
+        let days = total / 24;
+        self.weekday = ((u64::from(self.weekday) + days) % 7) as u8;
+        for _ in 0..days {
+            if self.day >= self.days_in_month() {
+                self.day = 1;
+                if self.month >= 12 {
+                    self.month = 1;
+                    self.year = (self.year + 1) % 100;
+                } else {
+                    self.month += 1;
+                }
+            } else {
+                self.day += 1;
+            }
+        }
+    }
+}
+
+impl Savestate for Calendar {
+    fn dump_savestate(&self, buffer: &mut Vec<u8>) {
+        buffer.extend_from_slice(&self.fields());
+        buffer.push(self.weekday);
+    }
+
+    fn load_savestate<'a>(
+        &mut self,
+        buffer: &mut SavestateStream<'a>,
+    ) -> Result<(), LoadSavestateError> {
+        let mut fields = [0; 6];
+        for field in fields.iter_mut() {
+            *field = read_savestate_byte(buffer)?;
+        }
+        self.set_fields(fields);
+        self.weekday = read_savestate_byte(buffer)? % 7;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::clock::FakeClock;
+
+    fn tama5() -> (Rc<FakeClock>, TAMA5) {
+        let clock = Rc::new(FakeClock::new(1000));
+        let mut mbc = TAMA5::new(&[]);
+        mbc.set_clock(clock.clone());
+        (clock, mbc)
+    }
+
+    fn write_register(mbc: &mut TAMA5, ram: &mut [u8], register: u8, value: u8) {
+        mbc.write_ram(0xA001, register, ram);
+        mbc.write_ram(0xA000, value, ram);
+    }
+
+    fn read_register(mbc: &mut TAMA5, ram: &mut [u8], register: u8) -> u8 {
+        mbc.write_ram(0xA001, register, ram);
+        mbc.read_ram(0xA000, ram)
+    }
+
+    fn command(mbc: &mut TAMA5, ram: &mut [u8], command: u8, address: u8, value: u8) -> bool {
+        write_register(mbc, ram, WRITE_LOW, value & 0xF);
+        write_register(mbc, ram, WRITE_HIGH, value >> 4);
+        write_register(mbc, ram, ADDRESS_HIGH, command << 1 | address >> 4);
+        mbc.write_ram(0xA001, ADDRESS_LOW, ram);
+        mbc.write_ram(0xA000, address & 0xF, ram)
+    }
+
+    fn read_value(mbc: &mut TAMA5, ram: &mut [u8]) -> u8 {
+        let low = read_register(mbc, ram, READ_LOW) & 0xF;
+        let high = read_register(mbc, ram, READ_HIGH) & 0xF;
+        high << 4 | low
+    }
+
+    #[test]
+    fn rom_bank_switching() {
+        let (_, mut mbc) = tama5();
+        assert_eq!(mbc.rom_bank(), 1);
+        write_register(&mut mbc, &mut [], ROM_BANK_LOW, 0x3);
+        write_register(&mut mbc, &mut [], ROM_BANK_HIGH, 0x1);
+        assert_eq!(mbc.rom_bank(), 0x13);
+        assert_eq!(mbc.relative_rom_address(0x4000), 0x13 * 0x4000);
+    }
+
+    #[test]
+    fn ram_commands() {
+        let (_, mut mbc) = tama5();
+        let mut ram = vec![0; RAM_SIZE];
+        command(&mut mbc, &mut ram, 0, 0x13, 0x42);
+        assert_eq!(ram[0x13], 0x42);
+
+        command(&mut mbc, &mut ram, 1, 0x13, 0);
+        assert_eq!(read_value(&mut mbc, &mut ram), 0x42);
+        assert_eq!(read_register(&mut mbc, &mut ram, STATUS) & 1, 1);
+    }
+
+    #[test]
+    fn clock_commands() {
+        let (clock, mut mbc) = tama5();
+        let mut ram = vec![0; RAM_SIZE];
+        // 23:59:59, on February 28th of a leap year
+        for (index, &digit) in [9, 5, 9, 5, 3, 2, 6, 8, 2, 2, 0, 4, 2].iter().enumerate() {
+            command(&mut mbc, &mut ram, 2, index as u8, digit);
+        }
+
+        clock.advance(1);
+        let digits: Vec<u8> = (0..13)
+            .map(|index| {
+                command(&mut mbc, &mut ram, 3, index, 0);
+                read_value(&mut mbc, &mut ram)
+            })
+            .collect();
+        assert_eq!(digits, vec![0, 0, 0, 0, 0, 0, 0, 9, 2, 2, 0, 4, 2]);
+    }
+
+    #[test]
+    fn clock_writes_change_the_save() {
+        let (_, mut mbc) = tama5();
+        let mut ram = vec![0; RAM_SIZE];
+        assert!(command(&mut mbc, &mut ram, 2, 2, 7));
+        assert!(!command(&mut mbc, &mut ram, 2, 2, 7));
+        assert!(!command(&mut mbc, &mut ram, 3, 2, 0));
+        assert!(command(&mut mbc, &mut ram, 0, 3, 1));
+    }
+
+    #[test]
+    fn calendar_rollover() {
+        let mut calendar = Calendar {
+            day: 31,
+            month: 12,
+            year: 99,
+            ..Calendar::default()
+        };
+        calendar.advance(24 * 60 * 60);
+        assert_eq!((calendar.day, calendar.month, calendar.year), (1, 1, 0));
+        assert_eq!(calendar.weekday, 1);
+    }
+
+    #[test]
+    fn footer_advances_by_elapsed_time() {
+        let (clock, mbc) = tama5();
+        let mut footer = Vec::new();
+        mbc.dump_save_footer(&mut footer);
+        assert_eq!(footer.len(), FOOTER_SIZE);
+
+        clock.advance(90);
+        let (_, mut other) = tama5();
+        other.set_clock(clock.clone());
+        assert!(other.load_save_footer(&footer));
+        assert_eq!(other.clock.calendar().minutes, 1);
+        assert_eq!(other.clock.calendar().seconds, 30);
+    }
+}
@@ -0,0 +1,111 @@
+use super::MemoryBankController;
+use crate::cartridge::cartridge_capability::CartridgeCapability;
+use crate::util::savestate::{read_savestate_byte, LoadSavestateError, Savestate, SavestateStream};
+
+/// Names found in the header of Wisdom Tree games, which declare no controller
+const SIGNATURES: [&[u8]; 2] = [b"WISDOM TREE", b"WISDOM\0TREE"];
+
+/// Wisdom Tree mapper, used by their unlicensed games. It switches the whole
+/// 0x0000-0x7FFF area between 32KB banks, selected by the lower byte of the address
+/// written to in 0x0000-0x3FFF.
+pub struct WisdomTree {
+    /// 32KB bank mapped to 0x0000-0x7FFF
+    bank: u8,
+}
+
+impl WisdomTree {
+    pub fn new(_capabilities: &[CartridgeCapability]) -> WisdomTree {
+        WisdomTree { bank: 0 }
+    }
+
+    /// Wisdom Tree games have their name in the header and are bigger than a cartridge
+    /// without a controller can be
+    pub fn is_wisdom_tree(rom: &[u8]) -> bool {
+        if rom.len() <= 0x8000 {
+            return false;
+        }
+
+        let header = &rom[0x0134..0x0150];
+        SIGNATURES.iter().any(|signature| {
+            header
+                .windows(signature.len())
+                .any(|window| window == *signature)
+        })
+    }
+}
+
+impl Savestate for WisdomTree {
+    fn dump_savestate(&self, buffer: &mut Vec<u8>) {
+        buffer.push(self.bank);
+    }
+
+    fn load_savestate<'a>(
+        &mut self,
+        buffer: &mut SavestateStream<'a>,
+    ) -> Result<(), LoadSavestateError> {
+        self.bank = read_savestate_byte(buffer)?;
+        Ok(())
+    }
+}
+
+impl MemoryBankController for WisdomTree {
+    // the upper half of the 32KB bank, in 16KB banks
+    fn rom_bank(&self) -> u16 {
+        u16::from(self.bank) * 2 + 1
+    }
+
+    fn ram_bank(&self) -> u8 {
+        0
+    }
+
+    fn ram_enabled(&self) -> bool {
+        false
+    }
+
+    fn relative_rom_address(&self, address: usize) -> usize {
+        usize::from(self.bank) * 0x8000 + address
+    }
+
+    fn write_rom(&mut self, address: usize, _value: u8) {
+        if address < 0x4000 {
+            self.bank = address as u8;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::tests::test_rom::test_rom;
+
+    #[test]
+    fn bank_switching() {
+        let mut mbc = WisdomTree::new(&[]);
+        assert_eq!(mbc.relative_rom_address(0x4123), 0x4123);
+
+        // the value is ignored, only the address matters
+        mbc.write_rom(0x0003, 0xFF);
+        assert_eq!(mbc.relative_rom_address(0x0123), 3 * 0x8000 + 0x123);
+        assert_eq!(mbc.relative_rom_address(0x4123), 3 * 0x8000 + 0x4123);
+        assert_eq!(mbc.rom_bank(), 7);
+
+        mbc.write_rom(0x4001, 0);
+        assert_eq!(mbc.rom_bank(), 7);
+    }
+
+    #[test]
+    fn detection() {
+        let mut rom = test_rom("WISDOM TREE");
+        assert!(!WisdomTree::is_wisdom_tree(&rom));
+        rom.resize(0x2_0000, 0);
+        assert!(WisdomTree::is_wisdom_tree(&rom));
+
+        let mut rom = test_rom("WISDOM\0TREE");
+        rom.resize(0x2_0000, 0);
+        assert!(WisdomTree::is_wisdom_tree(&rom));
+
+        let mut rom = test_rom("TEST");
+        rom.resize(0x2_0000, 0);
+        assert!(!WisdomTree::is_wisdom_tree(&rom));
+    }
+}
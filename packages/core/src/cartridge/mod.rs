@@ -1,66 +1,353 @@
+pub mod archive;
+pub mod camera;
 mod cartridge_capability;
+pub mod cartridge_error;
 pub mod cartridge_metadata;
-mod mbc;
+pub mod header_validation;
+pub mod licensee;
+pub mod mbc;
+pub mod patch;
+pub mod rom_database;
 
 use crate::bus::{Readable, Writable};
+use crate::cartridge::archive::RomFile;
+use crate::cartridge::camera::CameraSource;
+use crate::cartridge::cartridge_capability::CartridgeCapability;
+use crate::cartridge::cartridge_error::CartridgeError;
 use crate::cartridge::cartridge_metadata::CartridgeMetadata;
-use crate::cartridge::mbc::{MBCFactory, MemoryBankController};
-use crate::util::savestate::{LoadSavestateError, Savestate, SavestateStream};
-use std::error::Error;
+use crate::cartridge::header_validation::HeaderValidation;
+use crate::cartridge::mbc::real_time_clock::RealTimeClock;
+use crate::cartridge::mbc::{mirror, MBCFactory, MBCVariant, MemoryBankController};
+use crate::cartridge::rom_database::{DatMatch, RomDatabase};
+use crate::cheats::game_genie::GameGenieCode;
+use crate::util::clock::{Clock, SystemClock};
+use crate::util::savestate::{read_savestate_byte, LoadSavestateError, Savestate, SavestateStream};
+use std::cmp;
 use std::fs;
 use std::path::Path;
+use std::rc::Rc;
 
 pub struct Cartridge {
     buffer: Vec<u8>,
     metadata: CartridgeMetadata,
     mbc: Option<Box<dyn MemoryBankController>>,
     pub ram: Option<Vec<u8>>,
+    ram_dirty: bool,
+    clock: Rc<dyn Clock>,
+    forced_mbc_variant: Option<MBCVariant>,
+    game_genie_codes: Vec<GameGenieCode>,
+}
+
+/// State of a cartridge decoded from a savestate, which hasn't been applied yet
+pub struct CartridgeSavestate {
+    mbc: Option<Box<dyn MemoryBankController>>,
+    ram: Option<Vec<u8>>,
+}
+
+impl CartridgeSavestate {
+    pub fn real_time_clock_mut(&mut self) -> Option<&mut RealTimeClock> {
+        self.mbc.as_mut()?.real_time_clock_mut()
+    }
 }
 
 impl Cartridge {
-    pub fn from_file(filename: &str) -> Result<Cartridge, Box<dyn Error>> {
-        let mut cartridge = Cartridge::from_buffer(fs::read(filename)?)?;
+    pub fn from_file(filename: &str) -> Result<Cartridge, CartridgeError> {
+        Cartridge::from_patched_file(filename, &[])
+    }
 
-        if cartridge.ram.is_some() {
-            let ram_path = Path::new(filename).with_extension("sav");
-            if let Ok(ram_buffer) = fs::read(ram_path) {
-                cartridge.ram = Some(ram_buffer);
-            }
+    /// Loads a ROM file after applying the given IPS, UPS or BPS patches in order.
+    /// The battery save is the one of the original ROM.
+    pub fn from_patched_file(
+        filename: &str,
+        patches: &[Vec<u8>],
+    ) -> Result<Cartridge, CartridgeError> {
+        Cartridge::from_rom_file(RomFile::read(Path::new(filename), None)?, patches)
+    }
+
+    /// Loads a ROM read from disk or from an archive after applying the given patches.
+    /// The battery save is next to the ROM, or next to the archive it was extracted from.
+    pub fn from_rom_file(
+        rom_file: RomFile,
+        patches: &[Vec<u8>],
+    ) -> Result<Cartridge, CartridgeError> {
+        let mut rom = rom_file.data;
+        for patch in patches {
+            rom = patch::apply_patch(&rom, patch)?;
+        }
+        let mut cartridge = Cartridge::from_buffer(rom)?;
+
+        let save_path = rom_file.path.with_extension("sav");
+        if let Ok(save) = fs::read(save_path) {
+            cartridge.load_battery_save(&save);
         }
 
         Ok(cartridge)
     }
 
-    pub fn from_buffer(buffer: Vec<u8>) -> Result<Cartridge, Box<dyn Error>> {
+    pub fn from_buffer(buffer: Vec<u8>) -> Result<Cartridge, CartridgeError> {
         let metadata = CartridgeMetadata::from_buffer(&buffer)?;
-        let mbc = MBCFactory::from_metadata(&metadata);
-
-        let ram = if metadata.ram_size > 0 {
-            Some(vec![0; metadata.ram_size])
-        } else {
-            None
-        };
+        let mbc = MBCFactory::from_metadata(&metadata, &buffer);
+        let ram = Self::allocate_ram(&metadata, mbc.as_deref());
 
         Ok(Cartridge {
             buffer,
             metadata,
             mbc,
             ram,
+            ram_dirty: false,
+            clock: Rc::new(SystemClock),
+            forced_mbc_variant: None,
+            game_genie_codes: Vec::new(),
         })
     }
 
+    fn allocate_ram(
+        metadata: &CartridgeMetadata,
+        mbc: Option<&dyn MemoryBankController>,
+    ) -> Option<Vec<u8>> {
+        let ram_size = mbc.map_or(metadata.ram_size, |mbc| mbc.ram_size(metadata.ram_size));
+        if ram_size > 0 {
+            Some(vec![0; ram_size])
+        } else {
+            None
+        }
+    }
+
+    fn create_mbc(&self) -> Option<Box<dyn MemoryBankController>> {
+        match &self.forced_mbc_variant {
+            Some(variant) => MBCFactory::from_variant(variant, &self.metadata.capabilities),
+            None => MBCFactory::from_metadata(&self.metadata, &self.buffer),
+        }
+    }
+
+    /// Uses the given memory bank controller instead of the one detected from the header,
+    /// for cartridges that lie about it. The battery save already loaded is kept.
+    pub fn force_mbc_variant(&mut self, variant: MBCVariant) {
+        let save = self.dump_battery_save();
+        self.forced_mbc_variant = Some(variant);
+        self.mbc = self.create_mbc();
+        if let Some(mbc) = &mut self.mbc {
+            mbc.set_clock(self.clock.clone());
+        }
+
+        self.ram = Self::allocate_ram(&self.metadata, self.mbc.as_deref());
+        if let Some(save) = save {
+            self.load_battery_save(&save);
+        }
+    }
+
+    pub fn has_battery(&self) -> bool {
+        self.metadata
+            .capabilities
+            .contains(&CartridgeCapability::Battery)
+    }
+
+    /// Returns the battery backed data of the cartridge, if it has any:
+    /// its RAM followed by the footer of its controller, like the state of its clock,
+    /// in the format used by other emulators
+    pub fn dump_battery_save(&self) -> Option<Vec<u8>> {
+        let mut buffer = if self.has_battery() {
+            self.ram.clone().unwrap_or_default()
+        } else {
+            Vec::new()
+        };
+        if let Some(mbc) = &self.mbc {
+            mbc.dump_save_footer(&mut buffer);
+        }
+
+        if buffer.is_empty() {
+            None
+        } else {
+            Some(buffer)
+        }
+    }
+
+    /// Returns true if the battery backed data changed since the last call to `mark_ram_flushed`,
+    /// either because the game wrote to it or because a savestate was loaded
+    pub fn is_ram_dirty(&self) -> bool {
+        self.ram_dirty
+    }
+
+    /// Marks the battery backed data as saved, after writing `dump_battery_save` to disk
+    pub fn mark_ram_flushed(&mut self) {
+        self.ram_dirty = false;
+    }
+
+    // only the data kept by the battery needs to be saved
+    fn mark_ram_dirty(&mut self) {
+        if self.has_battery() {
+            self.ram_dirty = true;
+        }
+    }
+
+    /// Loads a battery save made by `dump_battery_save` or by another emulator.
+    /// Data after the RAM is loaded as the footer of the controller, and ignored if it isn't recognized.
+    pub fn load_battery_save(&mut self, buffer: &[u8]) {
+        let ram_size = self.ram.as_ref().map_or(0, Vec::len);
+        let (ram, footer) = buffer.split_at(cmp::min(ram_size, buffer.len()));
+        if let Some(cartridge_ram) = &mut self.ram {
+            cartridge_ram[..ram.len()].copy_from_slice(ram);
+        }
+
+        if let (Some(mbc), false) = (&mut self.mbc, footer.is_empty()) {
+            mbc.load_save_footer(footer);
+        }
+    }
+
     pub fn metadata(&self) -> &CartridgeMetadata {
         &self.metadata
     }
 
+    /// Checks the checksums, logo and ROM size declared in the header
+    pub fn header_validation(&self) -> HeaderValidation {
+        HeaderValidation::new(&self.metadata, &self.buffer)
+    }
+
+    /// Sets the Game Genie codes patching the values read from the ROM
+    pub fn set_game_genie_codes(&mut self, codes: Vec<GameGenieCode>) {
+        self.game_genie_codes = codes;
+    }
+
+    /// Writes to a bank of the cartridge RAM, whichever bank is mapped and even if the RAM is disabled.
+    /// Used by cheats, the address is in 0xA000-0xBFFF.
+    pub fn write_ram_bank(&mut self, bank: u8, address: u16, value: u8) {
+        let offset = usize::from(bank) * 0x2000 + (usize::from(address) & 0x1FFF);
+        if let Some(ram) = &mut self.ram {
+            if let Some(offset) = mirror(offset, ram.len()) {
+                if ram[offset] != value {
+                    ram[offset] = value;
+                    self.mark_ram_dirty();
+                }
+            }
+        }
+    }
+
+    fn apply_game_genie_codes(&self, address: u16, value: u8) -> u8 {
+        if self.game_genie_codes.is_empty() {
+            return value;
+        }
+
+        let rom_bank = match (&self.mbc, address) {
+            (_, 0..=0x3FFF) => 0,
+            (Some(mbc), _) => mbc.rom_bank(),
+            (None, _) => 1,
+        };
+        self.game_genie_codes
+            .iter()
+            .fold(value, |value, code| code.apply(address, rom_bank, value))
+    }
+
+    /// Looks the ROM up in a DAT database of known dumps
+    pub fn identify<'a>(&self, database: &'a RomDatabase) -> Option<DatMatch<'a>> {
+        database.find(&self.buffer)
+    }
+
+    /// Resets the memory bank controller.
+    /// The clock is battery powered, so it keeps running through resets.
     pub fn reset(&mut self) {
-        self.mbc = MBCFactory::from_metadata(&self.metadata);
+        let mut footer = Vec::new();
+        let mut camera_source = None;
+        if let Some(mbc) = &mut self.mbc {
+            mbc.dump_save_footer(&mut footer);
+            camera_source = mbc.take_camera_source();
+        }
+
+        self.mbc = self.create_mbc();
+        if let Some(mbc) = &mut self.mbc {
+            mbc.set_clock(self.clock.clone());
+            mbc.load_save_footer(&footer);
+            if let Some(source) = camera_source {
+                mbc.set_camera_source(source);
+            }
+        }
+    }
+
+    /// Decodes the state of the memory bank controller and of the RAM from a savestate,
+    /// without changing the cartridge. See `restore_savestate` to use it.
+    pub fn decode_savestate(
+        &self,
+        buffer: &mut SavestateStream<'_>,
+    ) -> Result<CartridgeSavestate, LoadSavestateError> {
+        // the battery backed data that isn't part of savestates is kept, like on reset
+        let mut footer = Vec::new();
+        if let Some(mbc) = &self.mbc {
+            mbc.dump_save_footer(&mut footer);
+        }
+
+        let mut mbc = self.create_mbc();
+        if let Some(mbc) = &mut mbc {
+            mbc.set_clock(self.clock.clone());
+            mbc.load_save_footer(&footer);
+            mbc.load_savestate(buffer)?;
+        }
+
+        let mut ram = self.ram.as_ref().map(|ram| vec![0; ram.len()]);
+        if let Some(ram) = &mut ram {
+            for value in ram.iter_mut() {
+                *value = read_savestate_byte(buffer)?;
+            }
+        }
+
+        Ok(CartridgeSavestate { mbc, ram })
+    }
+
+    /// Replaces the state of the cartridge with one decoded by `decode_savestate`
+    pub fn restore_savestate(&mut self, state: CartridgeSavestate) {
+        let camera_source = self.mbc.as_mut().and_then(|mbc| mbc.take_camera_source());
+        self.mbc = state.mbc;
+        if let (Some(mbc), Some(source)) = (&mut self.mbc, camera_source) {
+            mbc.set_camera_source(source);
+        }
+
+        if self.ram != state.ram {
+            self.ram = state.ram;
+            self.mark_ram_dirty();
+        }
+    }
+
+    /// Sets the source of time used by the clock of the cartridge, if it has one
+    pub fn set_clock(&mut self, clock: Rc<dyn Clock>) {
+        if let Some(mbc) = &mut self.mbc {
+            mbc.set_clock(clock.clone());
+        }
+        self.clock = clock;
     }
 
+    /// Returns true if the rumble motor of the cartridge is on
+    pub fn rumble(&self) -> bool {
+        match &self.mbc {
+            Some(mbc) => mbc.rumble(),
+            None => false,
+        }
+    }
+
+    /// Sets the tilt measured by the accelerometer of the cartridge, if it has one
+    pub fn set_tilt(&mut self, x: f32, y: f32) {
+        if let Some(mbc) = &mut self.mbc {
+            mbc.set_tilt(x, y);
+        }
+    }
+
+    /// Sets the images seen by the sensor of the Game Boy Camera, if the cartridge is one
+    pub fn set_camera_source(&mut self, source: Box<dyn CameraSource>) {
+        if let Some(mbc) = &mut self.mbc {
+            mbc.set_camera_source(source);
+        }
+    }
+
+    pub fn real_time_clock(&self) -> Option<&RealTimeClock> {
+        self.mbc.as_ref()?.real_time_clock()
+    }
+
+    pub fn real_time_clock_mut(&mut self) -> Option<&mut RealTimeClock> {
+        self.mbc.as_mut()?.real_time_clock_mut()
+    }
+
+    // cartridges without RAM still go through the MBC, which can map other registers there
     fn read_ram(&self, address: usize) -> u8 {
-        if let (Some(mbc), Some(ram)) = (&self.mbc, &self.ram) {
+        if let Some(mbc) = &self.mbc {
             if mbc.ram_enabled() {
-                return mbc.read_ram(address as usize, ram);
+                return mbc.read_ram(address as usize, self.ram.as_deref().unwrap_or(&[]));
             }
         }
 
@@ -68,9 +355,11 @@ impl Cartridge {
     }
 
     fn write_ram(&mut self, address: usize, value: u8) {
-        if let (Some(mbc), Some(ram)) = (&self.mbc, &mut self.ram) {
-            if mbc.ram_enabled() {
-                mbc.write_ram(address as usize, value, ram);
+        if let Some(mbc) = &mut self.mbc {
+            if mbc.ram_enabled()
+                && mbc.write_ram(address, value, self.ram.as_deref_mut().unwrap_or(&mut []))
+            {
+                self.mark_ram_dirty();
             }
         }
     }
@@ -79,15 +368,14 @@ impl Cartridge {
 impl Readable for Cartridge {
     fn read(&self, address: u16) -> u8 {
         match address {
-            0..=0x3FFF => self.buffer[address as usize], // first rom bank
-            0x4000..=0x7FFF => {
-                let address = if let Some(mbc) = &self.mbc {
-                    mbc.relative_rom_address(address as usize)
-                } else {
-                    address as usize
+            0..=0x7FFF => {
+                let value = match &self.mbc {
+                    Some(mbc) => mbc.read_rom(address as usize, &self.buffer),
+                    None => mirror(address as usize, self.buffer.len())
+                        .map_or(0xFF, |address| self.buffer[address]),
                 };
-                self.buffer[address]
-            } // switchable rom bank
+                self.apply_game_genie_codes(address, value)
+            } // rom banks
             0xA000..=0xBFFF => self.read_ram(address as usize), // switchable ram bank
             _ => 0,
         }
@@ -123,14 +411,265 @@ impl Savestate for Cartridge {
         &mut self,
         buffer: &mut SavestateStream<'a>,
     ) -> Result<(), LoadSavestateError> {
-        if let Some(ref mut mbc) = self.mbc {
-            mbc.load_savestate(buffer)?;
-        }
+        let state = self.decode_savestate(buffer)?;
+        self.restore_savestate(state);
+        Ok(())
+    }
+}
 
-        if let Some(ref mut ram) = self.ram {
-            std::mem::replace(ram, buffer.take(ram.len()).cloned().collect());
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::mbc::huc3;
+    use crate::cartridge::mbc::real_time_clock::FOOTER_SIZE;
+    use crate::util::clock::FakeClock;
+    use crate::util::tests::test_rom::{test_rom, test_rom_with_type};
 
-        Ok(())
+    // MBC3+TIMER+RAM+BATTERY with 8KB of RAM
+    fn rtc_cartridge(clock: Rc<FakeClock>) -> Cartridge {
+        let mut cartridge = Cartridge::from_buffer(test_rom_with_type("RTC", 0x10, 2))
+            .ok()
+            .unwrap();
+        cartridge.set_clock(clock);
+        cartridge
+    }
+
+    #[test]
+    fn battery_save_without_ram() {
+        let cartridge = Cartridge::from_buffer(test_rom("TEST")).ok().unwrap();
+        assert_eq!(cartridge.dump_battery_save(), None);
+    }
+
+    #[test]
+    fn battery_save_without_battery() {
+        // MBC1+RAM
+        let cartridge = Cartridge::from_buffer(test_rom_with_type("TEST", 0x02, 2))
+            .ok()
+            .unwrap();
+        assert!(cartridge.ram.is_some());
+        assert_eq!(cartridge.dump_battery_save(), None);
+    }
+
+    #[test]
+    fn write_ram_bank() {
+        // MBC1+RAM+BATTERY with 4 banks of RAM, which is disabled
+        let mut cartridge = Cartridge::from_buffer(test_rom_with_type("TEST", 0x03, 3))
+            .ok()
+            .unwrap();
+        cartridge.write_ram_bank(2, 0xA010, 0x42);
+        cartridge.write_ram_bank(5, 0xBFFF, 0x43);
+        let ram = cartridge.ram.as_ref().unwrap();
+        assert_eq!(ram[0x4010], 0x42);
+        assert_eq!(ram[0x3FFF], 0x43);
+        assert!(cartridge.is_ram_dirty());
+
+        cartridge.mark_ram_flushed();
+        cartridge.write_ram_bank(2, 0xA010, 0x42);
+        assert!(!cartridge.is_ram_dirty());
+    }
+
+    #[test]
+    fn mbc2_battery_save() {
+        // MBC2+BATTERY, which declares no RAM in the header
+        let mut cartridge = Cartridge::from_buffer(test_rom_with_type("TEST", 0x06, 0))
+            .ok()
+            .unwrap();
+        cartridge.write(0, 0x0A);
+        cartridge.write(0xA000, 0x12);
+        assert_eq!(cartridge.read(0xA200), 0xF2);
+
+        let save = cartridge.dump_battery_save().unwrap();
+        assert_eq!(save.len(), 512);
+        assert_eq!(save[0], 2);
+    }
+
+    #[test]
+    fn battery_save_round_trip() {
+        let clock = Rc::new(FakeClock::new(1000));
+        let mut cartridge = rtc_cartridge(clock.clone());
+        cartridge.ram.as_mut().unwrap()[0x10] = 0x42;
+        clock.advance(3600);
+        let save = cartridge.dump_battery_save().unwrap();
+        assert_eq!(save.len(), 0x2000 + FOOTER_SIZE);
+
+        clock.advance(60);
+        let mut loaded = rtc_cartridge(clock.clone());
+        loaded.load_battery_save(&save);
+        assert_eq!(loaded.ram.as_ref().unwrap()[0x10], 0x42);
+
+        let state = loaded.real_time_clock().unwrap().state();
+        assert_eq!((state.hours, state.minutes), (1, 1));
+    }
+
+    #[test]
+    fn reset_keeps_clock() {
+        let clock = Rc::new(FakeClock::new(1000));
+        let mut cartridge = rtc_cartridge(clock.clone());
+        clock.advance(3600);
+        cartridge.reset();
+        clock.advance(60);
+        let state = cartridge.real_time_clock().unwrap().state();
+        assert_eq!((state.hours, state.minutes), (1, 1));
+    }
+
+    #[test]
+    fn huc3_battery_save() {
+        let clock = Rc::new(FakeClock::new(1000));
+        let mut cartridge = Cartridge::from_buffer(test_rom_with_type("TEST", 0xFE, 2))
+            .ok()
+            .unwrap();
+        cartridge.set_clock(clock);
+        cartridge.ram.as_mut().unwrap()[0] = 0x42;
+
+        let save = cartridge.dump_battery_save().unwrap();
+        assert_eq!(save.len(), 0x2000 + huc3::FOOTER_SIZE);
+        assert_eq!(save[0], 0x42);
+        assert_eq!(save[0x2000..0x2008], 1000u64.to_le_bytes());
+    }
+
+    #[test]
+    fn ram_dirty() {
+        let mut cartridge = rtc_cartridge(Rc::new(FakeClock::new(0)));
+        assert!(!cartridge.is_ram_dirty());
+
+        cartridge.write(0xA000, 1);
+        assert!(!cartridge.is_ram_dirty());
+
+        cartridge.write(0, 0x0A);
+        cartridge.write(0xA000, 1);
+        assert!(cartridge.is_ram_dirty());
+
+        cartridge.mark_ram_flushed();
+        assert!(!cartridge.is_ram_dirty());
+
+        // writing the same value doesn't, setting the clock does
+        cartridge.write(0xA000, 1);
+        cartridge.write(0x4000, 8);
+        cartridge.write(0xA000, 0);
+        assert!(!cartridge.is_ram_dirty());
+        cartridge.write(0xA000, 30);
+        assert!(cartridge.is_ram_dirty());
+    }
+
+    #[test]
+    fn ram_without_battery_is_never_dirty() {
+        // MBC1+RAM
+        let mut cartridge = Cartridge::from_buffer(test_rom_with_type("TEST", 0x02, 2))
+            .ok()
+            .unwrap();
+        cartridge.write(0, 0x0A);
+        cartridge.write(0xA000, 1);
+        assert_eq!(cartridge.ram.as_ref().unwrap()[0], 1);
+        assert!(!cartridge.is_ram_dirty());
+    }
+
+    #[test]
+    fn rom_bank_mirroring() {
+        // MBC1 with a 32KB ROM, which only has 2 banks
+        let mut rom = test_rom_with_type("TEST", 0x01, 0);
+        rom[0x4000] = 0x42;
+        let mut cartridge = Cartridge::from_buffer(rom).ok().unwrap();
+
+        cartridge.write(0x2000, 5);
+        assert_eq!(cartridge.read(0x4000), 0x42);
+        cartridge.write(0x2000, 0x1E);
+        assert_eq!(cartridge.read(0x4000), cartridge.read(0));
+    }
+
+    #[test]
+    fn rom_with_ram() {
+        let mut cartridge = Cartridge::from_buffer(test_rom_with_type("TEST", 0x09, 2))
+            .ok()
+            .unwrap();
+        cartridge.write(0xA010, 0x42);
+        assert_eq!(cartridge.read(0xA010), 0x42);
+        assert_eq!(cartridge.dump_battery_save().unwrap()[0x10], 0x42);
+    }
+
+    #[test]
+    fn ram_bank_mirroring() {
+        // MBC5+RAM+BATTERY with a single 8KB bank
+        let mut cartridge = Cartridge::from_buffer(test_rom_with_type("TEST", 0x1B, 2))
+            .ok()
+            .unwrap();
+        cartridge.write(0, 0x0A);
+        cartridge.write(0xA000, 0x42);
+        cartridge.write(0x4000, 3);
+        assert_eq!(cartridge.read(0xA000), 0x42);
+    }
+
+    #[test]
+    fn forced_mbc_variant() {
+        // a bootleg declaring no controller with a 64KB ROM, detected as an MBC1
+        let mut rom = test_rom_with_type("TEST", 0x00, 2);
+        rom.resize(0x1_0000, 0);
+        rom[0x8000] = 0x42;
+        let mut cartridge = Cartridge::from_buffer(rom).ok().unwrap();
+        cartridge.write(0x2000, 2);
+        assert_eq!(cartridge.read(0x4000), 0x42);
+        cartridge.write(0xA000, 0x12);
+        assert_eq!(cartridge.read(0xA000), 0xFF);
+
+        cartridge.force_mbc_variant(MBCVariant::NoMBC);
+        cartridge.write(0x2000, 2);
+        assert_eq!(cartridge.read(0x4000), 0);
+        cartridge.write(0xA000, 0x12);
+        assert_eq!(cartridge.read(0xA000), 0x12);
+
+        // resets keep the forced controller
+        cartridge.reset();
+        cartridge.write(0x2000, 2);
+        assert_eq!(cartridge.read(0x4000), 0);
+    }
+
+    #[test]
+    fn loading_errors() {
+        assert!(matches!(
+            Cartridge::from_buffer(vec![0; 0x100]),
+            Err(CartridgeError::TooShort(0x100))
+        ));
+
+        // an unknown ROM size is only a bad header
+        let mut rom = test_rom("TEST");
+        rom[0x0148] = 0x20;
+        assert!(Cartridge::from_buffer(rom).is_ok());
+
+        assert!(matches!(
+            Cartridge::from_buffer(test_rom_with_type("TEST", 0x42, 0)),
+            Err(CartridgeError::InvalidCartridgeType(0x42))
+        ));
+        assert!(matches!(
+            Cartridge::from_file("missing.gb"),
+            Err(CartridgeError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn patched_file() {
+        let path = std::env::temp_dir().join("rustyboy_patched_file.gb");
+        fs::write(&path, test_rom("TEST")).unwrap();
+        let mut patch = b"PATCH".to_vec();
+        patch.extend_from_slice(&[0, 0x01, 0x34, 0, 5]);
+        patch.extend_from_slice(b"OTHER");
+        patch.extend_from_slice(b"EOF");
+
+        let filename = path.to_str().unwrap();
+        let cartridge = Cartridge::from_patched_file(filename, &[patch])
+            .ok()
+            .unwrap();
+        assert_eq!(cartridge.metadata().title, "OTHER");
+        assert!(matches!(
+            Cartridge::from_patched_file(filename, &[b"UPS1".to_vec()]),
+            Err(CartridgeError::Patch(_))
+        ));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn battery_save_without_footer() {
+        let clock = Rc::new(FakeClock::new(1000));
+        let mut cartridge = rtc_cartridge(clock);
+        cartridge.load_battery_save(&[1, 2, 3]);
+        assert_eq!(cartridge.ram.as_ref().unwrap()[..4], [1, 2, 3, 0]);
     }
 }
@@ -0,0 +1,425 @@
+use crate::util::crc32::crc32;
+use std::error::Error;
+use std::fmt;
+
+const IPS_MAGIC: &[u8] = b"PATCH";
+const IPS_END: &[u8] = b"EOF";
+const UPS_MAGIC: &[u8] = b"UPS1";
+const BPS_MAGIC: &[u8] = b"BPS1";
+/// UPS and BPS patches end with the CRC32 of the source, the target and the patch itself
+const FOOTER_SIZE: usize = 12;
+/// Bigger than any Game Boy ROM, to reject corrupted sizes before allocating the ROM
+const MAX_TARGET_SIZE: usize = 0x200_0000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PatchFormat {
+    IPS,
+    UPS,
+    BPS,
+}
+
+impl PatchFormat {
+    /// Finds the format of a patch from its first bytes
+    pub fn detect(patch: &[u8]) -> Option<PatchFormat> {
+        if patch.starts_with(IPS_MAGIC) {
+            Some(PatchFormat::IPS)
+        } else if patch.starts_with(UPS_MAGIC) {
+            Some(PatchFormat::UPS)
+        } else if patch.starts_with(BPS_MAGIC) {
+            Some(PatchFormat::BPS)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PatchError {
+    /// The patch isn't an IPS, UPS or BPS patch
+    UnknownFormat,
+    /// The patch ended in the middle of a record
+    Truncated,
+    /// The patch reads or writes outside of the ROM
+    OutOfBounds,
+    /// The patch was made for another ROM: the CRC32 of the ROM doesn't match
+    WrongSource { expected: u32, actual: u32 },
+    /// The patched ROM doesn't have the CRC32 given by the patch
+    WrongTarget { expected: u32, actual: u32 },
+    /// The patch is corrupted: its CRC32 doesn't match
+    CorruptedPatch,
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PatchError::UnknownFormat => write!(f, "not an IPS, UPS or BPS patch"),
+            PatchError::Truncated => write!(f, "the patch is truncated"),
+            PatchError::OutOfBounds => write!(f, "the patch goes past the end of the ROM"),
+            PatchError::WrongSource { expected, actual } => write!(
+                f,
+                "the patch is for another ROM (CRC32 {:08X} instead of {:08X})",
+                actual, expected
+            ),
+            PatchError::WrongTarget { expected, actual } => write!(
+                f,
+                "the patched ROM has the wrong CRC32 ({:08X} instead of {:08X})",
+                actual, expected
+            ),
+            PatchError::CorruptedPatch => write!(f, "the patch is corrupted"),
+        }
+    }
+}
+
+impl Error for PatchError {}
+
+/// Applies an IPS, UPS or BPS patch to a ROM, returning the patched ROM.
+/// The checksums of UPS and BPS patches are verified.
+pub fn apply_patch(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    match PatchFormat::detect(patch).ok_or(PatchError::UnknownFormat)? {
+        PatchFormat::IPS => apply_ips(rom, patch),
+        PatchFormat::UPS => apply_ups(rom, patch),
+        PatchFormat::BPS => apply_bps(rom, patch),
+    }
+}
+
+/// Reads the parts of a patch in order
+struct PatchReader<'a> {
+    patch: &'a [u8],
+    position: usize,
+}
+
+impl<'a> PatchReader<'a> {
+    fn new(patch: &'a [u8], position: usize) -> PatchReader<'a> {
+        PatchReader { patch, position }
+    }
+
+    fn bytes(&mut self, length: usize) -> Result<&'a [u8], PatchError> {
+        let bytes = self
+            .patch
+            .get(self.position..self.position.saturating_add(length))
+            .ok_or(PatchError::Truncated)?;
+        self.position += length;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8, PatchError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn big_endian(&mut self, length: usize) -> Result<usize, PatchError> {
+        Ok(self
+            .bytes(length)?
+            .iter()
+            .fold(0, |value, &byte| value << 8 | usize::from(byte)))
+    }
+
+    // UPS and BPS numbers: 7 bits per byte, the last one has its upper bit set
+    fn number(&mut self) -> Result<usize, PatchError> {
+        let mut value: usize = 0;
+        let mut shift: usize = 1;
+        loop {
+            let byte = self.byte()?;
+            value = usize::from(byte & 0x7F)
+                .checked_mul(shift)
+                .and_then(|data| value.checked_add(data))
+                .ok_or(PatchError::CorruptedPatch)?;
+            if byte & 0x80 != 0 {
+                return Ok(value);
+            }
+            shift = shift.checked_shl(7).ok_or(PatchError::CorruptedPatch)?;
+            value = value.checked_add(shift).ok_or(PatchError::CorruptedPatch)?;
+        }
+    }
+}
+
+fn apply_ips(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let mut target = rom.to_vec();
+    let mut reader = PatchReader::new(patch, IPS_MAGIC.len());
+    loop {
+        if reader.bytes(IPS_END.len())? == IPS_END {
+            break;
+        }
+        reader.position -= IPS_END.len();
+
+        let offset = reader.big_endian(3)?;
+        let (length, value) = match reader.big_endian(2)? {
+            // run of the same byte
+            0 => (reader.big_endian(2)?, None),
+            length => (length, Some(reader.bytes(length)?)),
+        };
+        if target.len() < offset + length {
+            target.resize(offset + length, 0);
+        }
+        match value {
+            Some(bytes) => target[offset..offset + length].copy_from_slice(bytes),
+            None => {
+                let byte = reader.byte()?;
+                target[offset..offset + length]
+                    .iter_mut()
+                    .for_each(|b| *b = byte);
+            }
+        }
+    }
+
+    // some patches end with the size to truncate the ROM to
+    if let Ok(size) = reader.big_endian(3) {
+        target.truncate(size);
+    }
+    Ok(target)
+}
+
+/// Checks the CRC32 footer of UPS and BPS patches against the patch and its source
+fn check_footer(rom: &[u8], patch: &[u8]) -> Result<u32, PatchError> {
+    if patch.len() < FOOTER_SIZE {
+        return Err(PatchError::Truncated);
+    }
+
+    let footer = &patch[patch.len() - FOOTER_SIZE..];
+    let checksum = |index: usize| {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(&footer[index * 4..index * 4 + 4]);
+        u32::from_le_bytes(bytes)
+    };
+    if crc32(&patch[..patch.len() - 4]) != checksum(2) {
+        return Err(PatchError::CorruptedPatch);
+    }
+
+    let actual = crc32(rom);
+    if actual != checksum(0) {
+        return Err(PatchError::WrongSource {
+            expected: checksum(0),
+            actual,
+        });
+    }
+    Ok(checksum(1))
+}
+
+fn check_target(target: Vec<u8>, expected: u32) -> Result<Vec<u8>, PatchError> {
+    let actual = crc32(&target);
+    if actual == expected {
+        Ok(target)
+    } else {
+        Err(PatchError::WrongTarget { expected, actual })
+    }
+}
+
+fn apply_ups(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let target_checksum = check_footer(rom, patch)?;
+    let end = patch.len() - FOOTER_SIZE;
+    let mut reader = PatchReader::new(&patch[..end], UPS_MAGIC.len());
+    let _source_size = reader.number()?;
+    let target_size = read_target_size(&mut reader)?;
+
+    let mut target = rom.to_vec();
+    target.resize(target_size, 0);
+    let mut offset: usize = 0;
+    while reader.position < end {
+        // each hunk skips unchanged bytes, then XORs bytes until a 0
+        offset = offset
+            .checked_add(reader.number()?)
+            .ok_or(PatchError::OutOfBounds)?;
+        loop {
+            let byte = reader.byte()?;
+            if let Some(value) = target.get_mut(offset) {
+                *value = rom.get(offset).cloned().unwrap_or(0) ^ byte;
+            }
+            offset += 1;
+            if byte == 0 {
+                break;
+            }
+        }
+    }
+    check_target(target, target_checksum)
+}
+
+fn apply_bps(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let target_checksum = check_footer(rom, patch)?;
+    let end = patch.len() - FOOTER_SIZE;
+    let mut reader = PatchReader::new(&patch[..end], BPS_MAGIC.len());
+    let _source_size = reader.number()?;
+    let target_size = read_target_size(&mut reader)?;
+    let metadata_size = reader.number()?;
+    reader.bytes(metadata_size)?;
+
+    let mut target = Vec::with_capacity(target_size);
+    let mut source_offset: usize = 0;
+    let mut target_offset: usize = 0;
+    while reader.position < end {
+        let action = reader.number()?;
+        let length = (action >> 2) + 1;
+        if target_size - target.len() < length {
+            return Err(PatchError::OutOfBounds);
+        }
+
+        match action & 3 {
+            // copy from the same position in the source
+            0 => target.extend_from_slice(slice(rom, target.len(), length)?),
+            // copy from the patch
+            1 => target.extend_from_slice(reader.bytes(length)?),
+            // copy from a position in the source relative to the last copy
+            2 => {
+                source_offset = relative_offset(source_offset, reader.number()?)?;
+                target.extend_from_slice(slice(rom, source_offset, length)?);
+                source_offset += length;
+            }
+            // copy from the target itself, the copy can overlap what it writes
+            _ => {
+                target_offset = relative_offset(target_offset, reader.number()?)?;
+                for _ in 0..length {
+                    let byte = *target.get(target_offset).ok_or(PatchError::OutOfBounds)?;
+                    target.push(byte);
+                    target_offset += 1;
+                }
+            }
+        }
+    }
+    check_target(target, target_checksum)
+}
+
+fn read_target_size(reader: &mut PatchReader<'_>) -> Result<usize, PatchError> {
+    let size = reader.number()?;
+    if size > MAX_TARGET_SIZE {
+        return Err(PatchError::OutOfBounds);
+    }
+    Ok(size)
+}
+
+fn slice(data: &[u8], start: usize, length: usize) -> Result<&[u8], PatchError> {
+    start
+        .checked_add(length)
+        .and_then(|end| data.get(start..end))
+        .ok_or(PatchError::OutOfBounds)
+}
+
+// BPS offsets are stored as a sign bit followed by the distance
+fn relative_offset(offset: usize, data: usize) -> Result<usize, PatchError> {
+    let distance = data >> 1;
+    let offset = if data & 1 != 0 {
+        offset.checked_sub(distance)
+    } else {
+        offset.checked_add(distance)
+    };
+    offset.ok_or(PatchError::OutOfBounds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &[u8] = b"ABCDEFGH";
+
+    fn push_number(patch: &mut Vec<u8>, mut value: usize) {
+        loop {
+            let byte = (value & 0x7F) as u8;
+            value >>= 7;
+            if value == 0 {
+                patch.push(byte | 0x80);
+                return;
+            }
+            patch.push(byte);
+            value -= 1;
+        }
+    }
+
+    fn push_footer(patch: &mut Vec<u8>, source: &[u8], target: &[u8]) {
+        patch.extend_from_slice(&crc32(source).to_le_bytes());
+        patch.extend_from_slice(&crc32(target).to_le_bytes());
+        let checksum = crc32(patch);
+        patch.extend_from_slice(&checksum.to_le_bytes());
+    }
+
+    #[test]
+    fn numbers() {
+        for &value in [0, 1, 0x7F, 0x80, 0x4000, 0x12_3456].iter() {
+            let mut patch = Vec::new();
+            push_number(&mut patch, value);
+            assert_eq!(PatchReader::new(&patch, 0).number(), Ok(value));
+        }
+    }
+
+    #[test]
+    fn detect_format() {
+        assert_eq!(PatchFormat::detect(b"PATCHEOF"), Some(PatchFormat::IPS));
+        assert_eq!(PatchFormat::detect(b"UPS1"), Some(PatchFormat::UPS));
+        assert_eq!(PatchFormat::detect(b"BPS1"), Some(PatchFormat::BPS));
+        assert_eq!(apply_patch(SOURCE, b"NOPE"), Err(PatchError::UnknownFormat));
+    }
+
+    #[test]
+    fn ips() {
+        let mut patch = b"PATCH".to_vec();
+        // 2 bytes at offset 1
+        patch.extend_from_slice(&[0, 0, 1, 0, 2, b'x', b'y']);
+        // a run of 3 bytes at offset 7, growing the ROM
+        patch.extend_from_slice(&[0, 0, 7, 0, 0, 0, 3, b'z']);
+        patch.extend_from_slice(b"EOF");
+        assert_eq!(apply_patch(SOURCE, &patch).unwrap(), b"AxyDEFGzzz");
+
+        // truncation after the end marker
+        patch.extend_from_slice(&[0, 0, 4]);
+        assert_eq!(apply_patch(SOURCE, &patch).unwrap(), b"AxyD");
+
+        assert_eq!(
+            apply_patch(SOURCE, b"PATCH\0\0\x01\0\x05ab"),
+            Err(PatchError::Truncated)
+        );
+    }
+
+    #[test]
+    fn ups() {
+        let target = b"ABxDEFGHIJ";
+        let mut patch = b"UPS1".to_vec();
+        push_number(&mut patch, SOURCE.len());
+        push_number(&mut patch, target.len());
+        push_number(&mut patch, 2);
+        patch.extend_from_slice(&[b'C' ^ b'x', 0]);
+        push_number(&mut patch, 4);
+        patch.extend_from_slice(&[b'I', b'J', 0]);
+        push_footer(&mut patch, SOURCE, target);
+        assert_eq!(apply_patch(SOURCE, &patch).unwrap(), target);
+
+        assert!(matches!(
+            apply_patch(b"ABCDEFGX", &patch),
+            Err(PatchError::WrongSource { .. })
+        ));
+        let last = patch.len() - 1;
+        patch[last] ^= 1;
+        assert_eq!(apply_patch(SOURCE, &patch), Err(PatchError::CorruptedPatch));
+    }
+
+    #[test]
+    fn bps() {
+        let target = b"ABCDxyzEFGHABCD";
+        let mut patch = b"BPS1".to_vec();
+        push_number(&mut patch, SOURCE.len());
+        push_number(&mut patch, target.len());
+        push_number(&mut patch, 0);
+        // source read of ABCD
+        push_number(&mut patch, (4 - 1) << 2);
+        // target read of xyz
+        push_number(&mut patch, (3 - 1) << 2 | 1);
+        patch.extend_from_slice(b"xyz");
+        // source copy of EFGH, 4 bytes forward
+        push_number(&mut patch, (4 - 1) << 2 | 2);
+        push_number(&mut patch, 4 << 1);
+        // target copy of ABCD, from the start
+        push_number(&mut patch, (4 - 1) << 2 | 3);
+        push_number(&mut patch, 0);
+        push_footer(&mut patch, SOURCE, target);
+        assert_eq!(apply_patch(SOURCE, &patch).unwrap(), target);
+    }
+
+    #[test]
+    fn bps_wrong_target() {
+        let mut patch = b"BPS1".to_vec();
+        push_number(&mut patch, SOURCE.len());
+        push_number(&mut patch, 4);
+        push_number(&mut patch, 0);
+        push_number(&mut patch, (4 - 1) << 2);
+        push_footer(&mut patch, SOURCE, b"ABCE");
+        assert!(matches!(
+            apply_patch(SOURCE, &patch),
+            Err(PatchError::WrongTarget { .. })
+        ));
+    }
+}
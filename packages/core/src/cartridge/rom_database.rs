@@ -0,0 +1,531 @@
+use crate::util::crc32::crc32;
+use crate::util::sha1::sha1;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// DAT files, such as the No-Intro ones, list the known dumps of each game with their checksums.
+// Both the Logiqx XML format and the older ClrMamePro one can be read.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DumpStatus {
+    /// The dump was checked against several cartridges
+    Verified,
+    Unverified,
+    /// The dump is known to be incorrect
+    BadDump,
+}
+
+impl DumpStatus {
+    pub fn name(self) -> &'static str {
+        match self {
+            DumpStatus::Verified => "verified",
+            DumpStatus::Unverified => "unverified",
+            DumpStatus::BadDump => "bad dump",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DatEntry {
+    /// Name of the game in the database, e.g. "Tetris (World) (Rev 1)"
+    pub name: String,
+    pub size: usize,
+    pub crc32: u32,
+    pub sha1: Option<[u8; 20]>,
+    pub status: DumpStatus,
+}
+
+impl DatEntry {
+    /// Name without the tags following it
+    pub fn title(&self) -> &str {
+        let title = self.name.split(" (").next().unwrap_or(&self.name);
+        title.split(" [").next().unwrap_or(title)
+    }
+
+    /// The first tag of the name, e.g. "USA, Europe"
+    pub fn region(&self) -> Option<&str> {
+        self.tags().next()
+    }
+
+    /// The revision of the game, from the "(Rev 1)" tag
+    pub fn revision(&self) -> Option<&str> {
+        self.tags().find_map(|tag| tag.strip_prefix("Rev "))
+    }
+
+    fn tags(&self) -> impl Iterator<Item = &str> {
+        self.name
+            .split('(')
+            .skip(1)
+            .filter_map(|tag| tag.split(')').next())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MatchKind {
+    /// The ROM is the one of the entry
+    Exact,
+    /// The ROM has the CRC32 and size of the entry but not its SHA-1
+    ChecksumMismatch,
+    /// The ROM starts with the one of the entry but has extra data after it
+    Overdump,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DatMatch<'a> {
+    pub entry: &'a DatEntry,
+    pub kind: MatchKind,
+}
+
+#[derive(Debug)]
+pub enum DatError {
+    Io(io::Error),
+    /// The file isn't a valid XML or ClrMamePro DAT
+    Syntax,
+    /// A ROM has an invalid value, the name of the field is given
+    InvalidValue(&'static str),
+}
+
+impl fmt::Display for DatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DatError::Io(error) => write!(f, "couldn't read the DAT file: {}", error),
+            DatError::Syntax => write!(f, "the DAT file is malformed"),
+            DatError::InvalidValue(field) => write!(f, "invalid {} in the DAT file", field),
+        }
+    }
+}
+
+impl Error for DatError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            DatError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for DatError {
+    fn from(error: io::Error) -> Self {
+        DatError::Io(error)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct RomDatabase {
+    entries: Vec<DatEntry>,
+}
+
+impl RomDatabase {
+    pub fn from_file(path: &Path) -> Result<RomDatabase, DatError> {
+        let text = fs::read_to_string(path)?;
+        RomDatabase::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<RomDatabase, DatError> {
+        let entries = if text.trim_start().starts_with('<') {
+            parse_xml(text)?
+        } else {
+            parse_clrmamepro(text)?
+        };
+        Ok(RomDatabase { entries })
+    }
+
+    pub fn entries(&self) -> &[DatEntry] {
+        &self.entries
+    }
+
+    /// Finds the entry of a ROM, or the one it's an incorrect dump of
+    pub fn find(&self, rom: &[u8]) -> Option<DatMatch<'_>> {
+        let checksum = crc32(rom);
+        let mut rom_sha1 = None;
+        let mut mismatch = None;
+        for entry in self.entries.iter() {
+            if entry.size != rom.len() || entry.crc32 != checksum {
+                continue;
+            }
+            let rom_sha1 = *rom_sha1.get_or_insert_with(|| sha1(rom));
+            let sha1_matches = match entry.sha1 {
+                Some(sha1) => sha1 == rom_sha1,
+                None => true,
+            };
+            if sha1_matches {
+                return Some(DatMatch {
+                    entry,
+                    kind: MatchKind::Exact,
+                });
+            }
+            mismatch = Some(entry);
+        }
+        if let Some(entry) = mismatch {
+            return Some(DatMatch {
+                entry,
+                kind: MatchKind::ChecksumMismatch,
+            });
+        }
+
+        // overdumps have the data of the game followed by padding or garbage
+        let mut prefix_checksums = HashMap::new();
+        self.entries
+            .iter()
+            .find(|entry| {
+                entry.size > 0
+                    && entry.size < rom.len()
+                    && *prefix_checksums
+                        .entry(entry.size)
+                        .or_insert_with(|| crc32(&rom[..entry.size]))
+                        == entry.crc32
+            })
+            .map(|entry| DatMatch {
+                entry,
+                kind: MatchKind::Overdump,
+            })
+    }
+}
+
+/// Creates the entry of a ROM from its fields, ROMs that were never dumped are skipped
+fn rom_entry(
+    game: Option<&str>,
+    rom: &HashMap<String, String>,
+) -> Result<Option<DatEntry>, DatError> {
+    let crc = match rom.get("crc") {
+        Some(crc) => crc,
+        None => return Ok(None),
+    };
+    let crc32 = u32::from_str_radix(crc, 16).map_err(|_| DatError::InvalidValue("crc"))?;
+    let size = rom
+        .get("size")
+        .and_then(|size| size.parse().ok())
+        .ok_or(DatError::InvalidValue("size"))?;
+    let sha1 = match rom.get("sha1") {
+        Some(sha1) => Some(parse_sha1(sha1).ok_or(DatError::InvalidValue("sha1"))?),
+        None => None,
+    };
+    let name = match game {
+        Some(game) => game.to_string(),
+        None => {
+            let name = rom.get("name").ok_or(DatError::InvalidValue("name"))?;
+            Path::new(name)
+                .file_stem()
+                .map_or_else(|| name.clone(), |stem| stem.to_string_lossy().into_owned())
+        }
+    };
+    // XML files have a status attribute, ClrMamePro ones flags
+    let status = match rom.get("status").or_else(|| rom.get("flags")) {
+        Some(status) if status == "verified" => DumpStatus::Verified,
+        Some(status) if status == "baddump" => DumpStatus::BadDump,
+        _ if name.contains("[b]") => DumpStatus::BadDump,
+        _ => DumpStatus::Unverified,
+    };
+
+    Ok(Some(DatEntry {
+        name,
+        size,
+        crc32,
+        sha1,
+        status,
+    }))
+}
+
+fn parse_sha1(value: &str) -> Option<[u8; 20]> {
+    if value.len() != 40 || !value.is_ascii() {
+        return None;
+    }
+    let mut sha1 = [0; 20];
+    for (i, byte) in sha1.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&value[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(sha1)
+}
+
+fn parse_xml(text: &str) -> Result<Vec<DatEntry>, DatError> {
+    let mut entries = Vec::new();
+    let mut game = None;
+    let mut rest = text;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start..];
+        if rest.starts_with("<!--") {
+            let end = rest.find("-->").ok_or(DatError::Syntax)?;
+            rest = &rest[end + 3..];
+            continue;
+        }
+
+        let end = tag_end(rest).ok_or(DatError::Syntax)?;
+        let tag = &rest[1..end];
+        rest = &rest[end + 1..];
+        let name_end = tag
+            .find(|c: char| c.is_whitespace() || c == '/')
+            .unwrap_or(tag.len());
+        match &tag[..name_end] {
+            "game" | "machine" => {
+                let attributes = parse_attributes(&tag[name_end..])?;
+                game = attributes.get("name").cloned();
+            }
+            "/game" | "/machine" => game = None,
+            "rom" => {
+                let attributes = parse_attributes(&tag[name_end..])?;
+                entries.extend(rom_entry(game.as_deref(), &attributes)?);
+            }
+            _ => {}
+        }
+    }
+    Ok(entries)
+}
+
+/// Position of the end of the tag at the start of the text, skipping quoted attributes
+fn tag_end(text: &str) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in text.char_indices() {
+        match (quote, c) {
+            (None, '>') => return Some(i),
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (Some(q), _) if q == c => quote = None,
+            _ => {}
+        }
+    }
+    None
+}
+
+fn parse_attributes(text: &str) -> Result<HashMap<String, String>, DatError> {
+    let mut attributes = HashMap::new();
+    let mut rest = text.trim_start();
+    while !rest.is_empty() && !rest.starts_with('/') && !rest.starts_with('?') {
+        let equals = rest.find('=').ok_or(DatError::Syntax)?;
+        let key = rest[..equals].trim();
+        let value = rest[equals + 1..].trim_start();
+        let quote = value
+            .chars()
+            .next()
+            .filter(|&c| c == '"' || c == '\'')
+            .ok_or(DatError::Syntax)?;
+        let length = value[1..].find(quote).ok_or(DatError::Syntax)?;
+        attributes.insert(key.to_string(), unescape_xml(&value[1..=length]));
+        rest = value[length + 2..].trim_start();
+    }
+    Ok(attributes)
+}
+
+fn unescape_xml(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    Text(String),
+}
+
+enum Node {
+    Value(String),
+    Block(Vec<(String, Node)>),
+}
+
+fn parse_clrmamepro(text: &str) -> Result<Vec<DatEntry>, DatError> {
+    let mut tokens = tokenize(text)?.into_iter();
+    let mut entries = Vec::new();
+    for (key, node) in parse_block(&mut tokens, false)? {
+        let fields = match (key.as_str(), node) {
+            ("game", Node::Block(fields)) | ("machine", Node::Block(fields)) => fields,
+            _ => continue,
+        };
+        let game = fields
+            .iter()
+            .find_map(|(key, node)| match (key.as_str(), node) {
+                ("name", Node::Value(name)) => Some(name.as_str()),
+                _ => None,
+            });
+        for (key, node) in fields.iter() {
+            if let ("rom", Node::Block(rom)) = (key.as_str(), node) {
+                let rom = rom
+                    .iter()
+                    .filter_map(|(key, node)| match node {
+                        Node::Value(value) => Some((key.clone(), value.clone())),
+                        Node::Block(_) => None,
+                    })
+                    .collect();
+                entries.extend(rom_entry(game, &rom)?);
+            }
+        }
+    }
+    Ok(entries)
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, DatError> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '(' => tokens.push(Token::Open),
+            ')' => tokens.push(Token::Close),
+            '"' => {
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => value.push(c),
+                        None => return Err(DatError::Syntax),
+                    }
+                }
+                tokens.push(Token::Text(value));
+            }
+            c if c.is_whitespace() => {}
+            c => {
+                let mut value = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || c == '(' || c == ')' || c == '"' {
+                        break;
+                    }
+                    value.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Text(value));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// Parses key value pairs until the end of the block, or of the file at the top level
+fn parse_block(
+    tokens: &mut impl Iterator<Item = Token>,
+    nested: bool,
+) -> Result<Vec<(String, Node)>, DatError> {
+    let mut fields = Vec::new();
+    loop {
+        let key = match tokens.next() {
+            Some(Token::Text(key)) => key,
+            Some(Token::Close) if nested => return Ok(fields),
+            None if !nested => return Ok(fields),
+            _ => return Err(DatError::Syntax),
+        };
+        let node = match tokens.next() {
+            Some(Token::Open) => Node::Block(parse_block(tokens, true)?),
+            Some(Token::Text(value)) => Node::Value(value),
+            _ => return Err(DatError::Syntax),
+        };
+        fields.push((key, node));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROM: &[u8] = b"rustyboy test rom";
+
+    fn xml_database(sha1: &str) -> RomDatabase {
+        RomDatabase::parse(&format!(
+            r#"<?xml version="1.0"?>
+<!DOCTYPE datafile PUBLIC "-//Logiqx//DTD ROM Management Datafile//EN" "http://www.logiqx.com/Dats/datafile.dtd">
+<datafile>
+	<header>
+		<name>Nintendo - Game Boy</name>
+	</header>
+	<!-- <game name="commented out"> -->
+	<game name="Never Dumped (Japan)">
+		<rom name="Never Dumped (Japan).gb" size="32768" status="nodump"/>
+	</game>
+	<game name="Rusty &amp; Boy (USA, Europe) (Rev 2) (SGB Enhanced)">
+		<description>Rusty &amp; Boy (USA, Europe) (Rev 2) (SGB Enhanced)</description>
+		<rom name="Rusty &amp; Boy (USA, Europe) (Rev 2) (SGB Enhanced).gb" size="{}" crc="{:08x}" sha1="{}" status="verified"/>
+	</game>
+</datafile>"#,
+            ROM.len(),
+            crc32(ROM),
+            sha1
+        ))
+        .unwrap()
+    }
+
+    fn hex(digest: [u8; 20]) -> String {
+        digest.iter().map(|byte| format!("{:02X}", byte)).collect()
+    }
+
+    #[test]
+    fn xml() {
+        let database = xml_database(&hex(sha1(ROM)));
+        assert_eq!(database.entries().len(), 1);
+
+        let entry = &database.entries()[0];
+        assert_eq!(
+            entry.name,
+            "Rusty & Boy (USA, Europe) (Rev 2) (SGB Enhanced)"
+        );
+        assert_eq!(entry.title(), "Rusty & Boy");
+        assert_eq!(entry.region(), Some("USA, Europe"));
+        assert_eq!(entry.revision(), Some("2"));
+        assert_eq!(entry.size, ROM.len());
+        assert_eq!(entry.status, DumpStatus::Verified);
+    }
+
+    #[test]
+    fn clrmamepro() {
+        let database = RomDatabase::parse(&format!(
+            "clrmamepro (\n\tname \"Nintendo - Game Boy\"\n)\n\n\
+             game (\n\tname \"Rusty Boy (Japan) [b]\"\n\t\
+             rom ( name \"Rusty Boy (Japan) [b].gb\" size {} crc {:08X} md5 0123 )\n)\n",
+            ROM.len(),
+            crc32(ROM)
+        ))
+        .unwrap();
+        let entry = &database.entries()[0];
+        assert_eq!(entry.title(), "Rusty Boy");
+        assert_eq!(entry.region(), Some("Japan"));
+        assert_eq!(entry.revision(), None);
+        assert_eq!(entry.sha1, None);
+        assert_eq!(entry.status, DumpStatus::BadDump);
+        assert_eq!(
+            database.find(ROM).map(|found| found.kind),
+            Some(MatchKind::Exact)
+        );
+    }
+
+    #[test]
+    fn matches() {
+        let database = xml_database(&hex(sha1(ROM)));
+        assert_eq!(
+            database.find(ROM).map(|found| found.kind),
+            Some(MatchKind::Exact)
+        );
+
+        let mut overdump = ROM.to_vec();
+        overdump.extend_from_slice(&[0xFF; 15]);
+        assert_eq!(
+            database.find(&overdump).map(|found| found.kind),
+            Some(MatchKind::Overdump)
+        );
+        assert_eq!(database.find(b"another rom"), None);
+
+        let database = xml_database(&hex(sha1(b"another rom")));
+        assert_eq!(
+            database.find(ROM).map(|found| found.kind),
+            Some(MatchKind::ChecksumMismatch)
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(matches!(
+            RomDatabase::parse(
+                "<game name=\"Game\"><rom name=\"Game.gb\" size=\"1\" crc=\"xyz\"/>"
+            ),
+            Err(DatError::InvalidValue("crc"))
+        ));
+        assert!(matches!(
+            RomDatabase::parse("<game name=\"Game\"><rom name=\"Game.gb\""),
+            Err(DatError::Syntax)
+        ));
+        assert!(matches!(
+            RomDatabase::parse("game ( name \"Game\" rom ( size 1 )"),
+            Err(DatError::Syntax)
+        ));
+    }
+}
@@ -0,0 +1,132 @@
+use crate::cheats::{parse_hex_digits, CheatError};
+
+const ADDRESS_XOR: u16 = 0xF000;
+const COMPARE_XOR: u8 = 0xBA;
+
+/// Game Genie code, replacing a byte of the ROM when it's read.
+/// Codes have 6 digits ("ABC-DEF"), or 9 digits ("ABC-DEF-GHI") for codes
+/// that only apply when the original byte is the compare value.
+/// They can be followed by "@" and a hexadecimal ROM bank, to only apply to that bank.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GameGenieCode {
+    pub address: u16,
+    pub value: u8,
+    pub compare: Option<u8>,
+    pub bank: Option<u16>,
+}
+
+impl GameGenieCode {
+    pub fn parse(code: &str) -> Result<GameGenieCode, CheatError> {
+        let (code, bank) = match code.find('@') {
+            Some(separator) => {
+                let bank = u16::from_str_radix(code[separator + 1..].trim(), 16)
+                    .map_err(|_| CheatError::InvalidBank)?;
+                (&code[..separator], Some(bank))
+            }
+            None => (code, None),
+        };
+        let digits = parse_hex_digits(&code.replace('-', ""))?;
+        if digits.len() != 6 && digits.len() != 9 {
+            return Err(CheatError::InvalidLength(digits.len()));
+        }
+
+        // the digits of the address are shuffled, and the first one is inverted
+        let address = (u16::from(digits[5]) << 12
+            | u16::from(digits[2]) << 8
+            | u16::from(digits[3]) << 4
+            | u16::from(digits[4]))
+            ^ ADDRESS_XOR;
+        if address > 0x7FFF {
+            return Err(CheatError::InvalidAddress(address));
+        }
+
+        // the 8th digit isn't used
+        let compare = if digits.len() == 9 {
+            Some((digits[6] << 4 | digits[8]).rotate_right(2) ^ COMPARE_XOR)
+        } else {
+            None
+        };
+
+        Ok(GameGenieCode {
+            address,
+            value: digits[0] << 4 | digits[1],
+            compare,
+            bank,
+        })
+    }
+
+    /// Returns the value read from the ROM with the code applied.
+    /// `rom_bank` is the bank mapped at the address.
+    pub fn apply(&self, address: u16, rom_bank: u16, value: u8) -> u8 {
+        let bank_matches = match self.bank {
+            Some(bank) => bank == rom_bank,
+            None => true,
+        };
+        let compare_matches = match self.compare {
+            Some(compare) => compare == value,
+            None => true,
+        };
+        if address == self.address && bank_matches && compare_matches {
+            self.value
+        } else {
+            value
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            GameGenieCode::parse("3E9-A8B"),
+            Ok(GameGenieCode {
+                address: 0x49A8,
+                value: 0x3E,
+                compare: None,
+                bank: None,
+            })
+        );
+        assert_eq!(
+            GameGenieCode::parse("3e9a8be6e@1F"),
+            Ok(GameGenieCode {
+                address: 0x49A8,
+                value: 0x3E,
+                compare: Some(0x01),
+                bank: Some(0x1F),
+            })
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        assert_eq!(
+            GameGenieCode::parse("3E9-A8"),
+            Err(CheatError::InvalidLength(5))
+        );
+        assert_eq!(
+            GameGenieCode::parse("3E9-A8G"),
+            Err(CheatError::InvalidCharacter('G'))
+        );
+        assert_eq!(
+            GameGenieCode::parse("3E9-A87"),
+            Err(CheatError::InvalidAddress(0x89A8))
+        );
+        assert_eq!(
+            GameGenieCode::parse("3E9-A8B@X"),
+            Err(CheatError::InvalidBank)
+        );
+    }
+
+    #[test]
+    fn apply() {
+        let code = GameGenieCode::parse("3E9-A8B-E6E@2").unwrap();
+        assert_eq!(code.apply(0x49A8, 2, 0x01), 0x3E);
+        // other value, bank or address
+        assert_eq!(code.apply(0x49A8, 2, 0x02), 0x02);
+        assert_eq!(code.apply(0x49A8, 3, 0x01), 0x01);
+        assert_eq!(code.apply(0x49A9, 2, 0x01), 0x01);
+    }
+}
@@ -0,0 +1,211 @@
+use crate::bus::Writable;
+use crate::cheats::{parse_hex_digits, CheatError};
+use crate::hardware::Hardware;
+use std::fmt;
+
+/// GameShark code, writing a value to memory every frame.
+/// Codes have 8 digits, "TTVVAAAA": a type, the value and the address with its bytes swapped.
+/// Codebreaker codes ("AAAA-VV") are the same writes, to whatever is mapped at the address.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GameSharkCode {
+    pub address: u16,
+    pub value: u8,
+    pub target: GameSharkTarget,
+}
+
+/// Memory a code writes to, given by the type of GameShark codes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GameSharkTarget {
+    /// Whatever is mapped at the address, type 0X (usually 01)
+    Mapped,
+    /// A bank of the cartridge RAM, type 8X, so only banks 0 to F have a code
+    CartridgeRam(u8),
+    /// A bank of the work RAM in 0xD000-0xDFFF, type 9X. Only the CGB has more than one.
+    WorkRam(u8),
+}
+
+impl GameSharkCode {
+    pub fn parse(code: &str) -> Result<GameSharkCode, CheatError> {
+        let digits = parse_hex_digits(code)?;
+        if digits.len() != 8 {
+            return Err(CheatError::InvalidLength(digits.len()));
+        }
+
+        let byte = |i: usize| digits[i] << 4 | digits[i + 1];
+        let target = match digits[0] {
+            0x0 => GameSharkTarget::Mapped,
+            0x8 => GameSharkTarget::CartridgeRam(digits[1]),
+            0x9 => GameSharkTarget::WorkRam(digits[1]),
+            _ => return Err(CheatError::InvalidType(byte(0))),
+        };
+        GameSharkCode::new(u16::from_le_bytes([byte(4), byte(6)]), byte(2), target)
+    }
+
+    pub fn parse_codebreaker(code: &str) -> Result<GameSharkCode, CheatError> {
+        let digits = parse_hex_digits(&code.replace(&['-', ':'][..], ""))?;
+        if digits.len() != 6 {
+            return Err(CheatError::InvalidLength(digits.len()));
+        }
+
+        let address = digits[..4]
+            .iter()
+            .fold(0, |address, &digit| address << 4 | u16::from(digit));
+        GameSharkCode::new(address, digits[4] << 4 | digits[5], GameSharkTarget::Mapped)
+    }
+
+    fn new(address: u16, value: u8, target: GameSharkTarget) -> Result<GameSharkCode, CheatError> {
+        let valid = match target {
+            // writes to the ROM would switch banks instead of changing a value
+            GameSharkTarget::Mapped => address >= 0x8000,
+            GameSharkTarget::CartridgeRam(_) => (0xA000..=0xBFFF).contains(&address),
+            GameSharkTarget::WorkRam(_) => (0xD000..=0xDFFF).contains(&address),
+        };
+        if !valid {
+            return Err(CheatError::InvalidAddress(address));
+        }
+
+        Ok(GameSharkCode {
+            address,
+            value,
+            target,
+        })
+    }
+
+    /// Writes the value, should be called once per frame
+    pub fn apply(&self, hardware: &mut Hardware) {
+        match self.target {
+            GameSharkTarget::CartridgeRam(bank) => {
+                hardware
+                    .cartridge
+                    .write_ram_bank(bank, self.address, self.value)
+            }
+            // like on the CGB, bank 0 selects bank 1, the only one of the DMG
+            GameSharkTarget::WorkRam(bank) if bank > 1 => {}
+            _ => hardware.write(self.address, self.value),
+        }
+    }
+}
+
+impl fmt::Display for GameSharkCode {
+    /// Formats the code as a GameShark one, writes to whatever is mapped have the usual type 01
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let code_type = match self.target {
+            GameSharkTarget::Mapped => 0x01,
+            GameSharkTarget::CartridgeRam(bank) => 0x80 | bank,
+            GameSharkTarget::WorkRam(bank) => 0x90 | bank,
+        };
+        let [low, high] = self.address.to_le_bytes();
+        write!(
+            f,
+            "{:02X}{:02X}{:02X}{:02X}",
+            code_type, self.value, low, high
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            GameSharkCode::parse("0163D2C0"),
+            Ok(GameSharkCode {
+                address: 0xC0D2,
+                value: 0x63,
+                target: GameSharkTarget::Mapped,
+            })
+        );
+        assert_eq!(
+            GameSharkCode::parse("830512a0"),
+            Ok(GameSharkCode {
+                address: 0xA012,
+                value: 0x05,
+                target: GameSharkTarget::CartridgeRam(3),
+            })
+        );
+        assert_eq!(
+            GameSharkCode::parse("01631240"),
+            Err(CheatError::InvalidAddress(0x4012))
+        );
+        assert_eq!(
+            GameSharkCode::parse("0163D2C"),
+            Err(CheatError::InvalidLength(7))
+        );
+    }
+
+    #[test]
+    fn parse_types() {
+        let target = |code| GameSharkCode::parse(code).map(|code| code.target);
+        assert_eq!(target("000512A0"), Ok(GameSharkTarget::Mapped));
+        assert_eq!(target("850512A0"), Ok(GameSharkTarget::CartridgeRam(5)));
+        assert_eq!(target("910512D0"), Ok(GameSharkTarget::WorkRam(1)));
+        assert_eq!(target("8163D2C0"), Err(CheatError::InvalidAddress(0xC0D2)));
+        assert_eq!(target("910512A0"), Err(CheatError::InvalidAddress(0xA012)));
+        for code in ["1163D2C0", "4163D2C0", "7F63D2C0", "A163D2C0", "FF63D2C0"].iter() {
+            let code_type = u8::from_str_radix(&code[..2], 16).unwrap();
+            assert_eq!(
+                GameSharkCode::parse(code),
+                Err(CheatError::InvalidType(code_type))
+            );
+        }
+    }
+
+    #[test]
+    fn format() {
+        for code in ["0163D2C0", "810512A0", "8F0512BF", "920512D0"].iter() {
+            assert_eq!(GameSharkCode::parse(code).unwrap().to_string(), *code);
+        }
+        assert_eq!(
+            GameSharkCode::parse_codebreaker("A012-05")
+                .unwrap()
+                .to_string(),
+            "010512A0"
+        );
+    }
+
+    #[test]
+    fn parse_formatted() {
+        let codes = [
+            ("C0D2", GameSharkTarget::Mapped),
+            ("A012", GameSharkTarget::Mapped),
+            ("A012", GameSharkTarget::CartridgeRam(0)),
+            ("A012", GameSharkTarget::CartridgeRam(1)),
+            ("BFFF", GameSharkTarget::CartridgeRam(0xF)),
+            ("D000", GameSharkTarget::WorkRam(1)),
+        ];
+        for &(address, target) in codes.iter() {
+            let code = GameSharkCode {
+                address: u16::from_str_radix(address, 16).unwrap(),
+                value: 0x63,
+                target,
+            };
+            assert_eq!(GameSharkCode::parse(&code.to_string()), Ok(code));
+        }
+    }
+
+    #[test]
+    fn parse_codebreaker() {
+        assert_eq!(
+            GameSharkCode::parse_codebreaker("C0D2-63"),
+            Ok(GameSharkCode {
+                address: 0xC0D2,
+                value: 0x63,
+                target: GameSharkTarget::Mapped,
+            })
+        );
+        assert_eq!(
+            GameSharkCode::parse_codebreaker("a012:05"),
+            Ok(GameSharkCode {
+                address: 0xA012,
+                value: 0x05,
+                target: GameSharkTarget::Mapped,
+            })
+        );
+        assert_eq!(
+            GameSharkCode::parse_codebreaker("C0D2-6"),
+            Err(CheatError::InvalidLength(5))
+        );
+    }
+}
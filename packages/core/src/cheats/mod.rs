@@ -0,0 +1,189 @@
+use crate::cheats::game_genie::GameGenieCode;
+use crate::cheats::game_shark::GameSharkCode;
+use crate::hardware::Hardware;
+use std::error::Error;
+use std::fmt;
+
+pub mod game_genie;
+pub mod game_shark;
+pub mod search;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CheatError {
+    /// The code doesn't have the number of digits of any format
+    InvalidLength(usize),
+    InvalidCharacter(char),
+    /// Game Genie codes can only patch the ROM, in 0x0000-0x7FFF
+    InvalidAddress(u16),
+    InvalidBank,
+    /// GameShark codes can only have the types 0X, 8X and 9X
+    InvalidType(u8),
+}
+
+impl fmt::Display for CheatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheatError::InvalidLength(length) => {
+                write!(f, "codes can't have {} digits", length)
+            }
+            CheatError::InvalidCharacter(c) => write!(f, "invalid character '{}'", c),
+            CheatError::InvalidAddress(address) => {
+                write!(f, "invalid address {:#06X}", address)
+            }
+            CheatError::InvalidBank => write!(f, "invalid ROM bank"),
+            CheatError::InvalidType(code_type) => {
+                write!(f, "invalid GameShark code type {:02X}", code_type)
+            }
+        }
+    }
+}
+
+impl Error for CheatError {}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CheatCode {
+    GameGenie(GameGenieCode),
+    /// GameShark or Codebreaker code
+    GameShark(GameSharkCode),
+}
+
+impl CheatCode {
+    /// Parses a code, its format is detected from its number of digits and separators
+    pub fn parse(code: &str) -> Result<CheatCode, CheatError> {
+        let digit_count = code.chars().filter(char::is_ascii_hexdigit).count();
+        if code.contains('@') {
+            GameGenieCode::parse(code).map(CheatCode::GameGenie)
+        } else if digit_count == 8 && !code.contains('-') {
+            GameSharkCode::parse(code).map(CheatCode::GameShark)
+        } else if digit_count == 6 && code.find(&['-', ':'][..]) == Some(4) {
+            // Game Genie codes are split every 3 digits, Codebreaker ones after the address
+            GameSharkCode::parse_codebreaker(code).map(CheatCode::GameShark)
+        } else {
+            GameGenieCode::parse(code).map(CheatCode::GameGenie)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cheat {
+    /// The code as it was entered
+    pub code: String,
+    pub enabled: bool,
+    pub kind: CheatCode,
+}
+
+/// Cheat codes of a game, in the order they were added
+#[derive(Debug, Default)]
+pub struct Cheats {
+    cheats: Vec<Cheat>,
+}
+
+impl Cheats {
+    /// Adds an enabled code and returns its index
+    pub fn add(&mut self, code: &str) -> Result<usize, CheatError> {
+        let code = code.trim();
+        self.cheats.push(Cheat {
+            code: code.to_string(),
+            enabled: true,
+            kind: CheatCode::parse(code)?,
+        });
+        Ok(self.cheats.len() - 1)
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<Cheat> {
+        if index < self.cheats.len() {
+            Some(self.cheats.remove(index))
+        } else {
+            None
+        }
+    }
+
+    pub fn set_enabled(&mut self, index: usize, enabled: bool) {
+        if let Some(cheat) = self.cheats.get_mut(index) {
+            cheat.enabled = enabled;
+        }
+    }
+
+    pub fn list(&self) -> &[Cheat] {
+        &self.cheats
+    }
+
+    pub fn game_genie_codes(&self) -> Vec<GameGenieCode> {
+        self.enabled_codes()
+            .filter_map(|code| match code {
+                CheatCode::GameGenie(code) => Some(code),
+                CheatCode::GameShark(_) => None,
+            })
+            .collect()
+    }
+
+    /// Writes the values of the GameShark codes, should be called at every VBlank
+    pub fn apply_game_shark_codes(&self, hardware: &mut Hardware) {
+        for code in self.enabled_codes() {
+            if let CheatCode::GameShark(code) = code {
+                code.apply(hardware);
+            }
+        }
+    }
+
+    fn enabled_codes(&self) -> impl Iterator<Item = CheatCode> + '_ {
+        self.cheats
+            .iter()
+            .filter(|cheat| cheat.enabled)
+            .map(|cheat| cheat.kind)
+    }
+}
+
+/// Parses a string of hexadecimal digits, ignoring whitespace
+fn parse_hex_digits(code: &str) -> Result<Vec<u8>, CheatError> {
+    code.chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| {
+            c.to_digit(16)
+                .map(|digit| digit as u8)
+                .ok_or(CheatError::InvalidCharacter(c))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cheats::game_shark::GameSharkTarget;
+
+    #[test]
+    fn add_and_toggle() {
+        let mut cheats = Cheats::default();
+        assert_eq!(cheats.add(" 3E9-A8B "), Ok(0));
+        assert_eq!(cheats.add("00A-17B-C49"), Ok(1));
+        assert_eq!(cheats.add("nope"), Err(CheatError::InvalidCharacter('n')));
+        assert_eq!(cheats.list().len(), 2);
+        assert_eq!(cheats.list()[0].code, "3E9-A8B");
+
+        cheats.set_enabled(0, false);
+        assert_eq!(cheats.game_genie_codes().len(), 1);
+        assert_eq!(cheats.game_genie_codes()[0].address, 0x4A17);
+
+        assert_eq!(
+            cheats
+                .add("0163D2C0")
+                .map(|index| cheats.list()[index].kind),
+            Ok(CheatCode::GameShark(GameSharkCode {
+                address: 0xC0D2,
+                value: 0x63,
+                target: GameSharkTarget::Mapped,
+            }))
+        );
+        assert!(matches!(
+            cheats.add("C0D2-63").map(|index| cheats.list()[index].kind),
+            Ok(CheatCode::GameShark(_))
+        ));
+        assert_eq!(cheats.game_genie_codes().len(), 1);
+        cheats.remove(3);
+        cheats.remove(2);
+
+        assert!(cheats.remove(1).is_some());
+        assert!(cheats.remove(1).is_none());
+        assert!(cheats.game_genie_codes().is_empty());
+    }
+}
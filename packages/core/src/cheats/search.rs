@@ -0,0 +1,317 @@
+use crate::cheats::game_shark::{GameSharkCode, GameSharkTarget};
+use crate::hardware::Hardware;
+
+const WORK_RAM_START: u16 = 0xC000;
+const HIGH_RAM_START: u16 = 0xFF80;
+const CARTRIDGE_RAM_START: u16 = 0xA000;
+const CARTRIDGE_RAM_BANK_SIZE: usize = 0x2000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ValueSize {
+    Byte,
+    /// Little endian 16 bit value
+    Word,
+}
+
+impl ValueSize {
+    fn bytes(self) -> usize {
+        match self {
+            ValueSize::Byte => 1,
+            ValueSize::Word => 2,
+        }
+    }
+
+    /// Returns true if the value can be stored in this size, as a signed or an unsigned value
+    pub fn fits(self, value: i32) -> bool {
+        let bits = self.bytes() as u32 * 8;
+        (-(1 << (bits - 1))..1 << bits).contains(&value)
+    }
+}
+
+/// Condition the values must meet to remain candidates,
+/// compared to their value when the previous snapshot was taken
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SearchFilter {
+    Unchanged,
+    Changed,
+    Increased,
+    Decreased,
+    EqualTo(i32),
+}
+
+impl SearchFilter {
+    fn matches(self, previous: i32, value: i32) -> bool {
+        match self {
+            SearchFilter::Unchanged => value == previous,
+            SearchFilter::Changed => value != previous,
+            SearchFilter::Increased => value > previous,
+            SearchFilter::Decreased => value < previous,
+            SearchFilter::EqualTo(expected) => value == expected,
+        }
+    }
+}
+
+/// Contiguous memory, values spanning two blocks aren't searched
+#[derive(Debug, Clone)]
+struct MemoryBlock {
+    address: u16,
+    ram_bank: Option<u8>,
+    data: Vec<u8>,
+}
+
+/// Copy of the RAM a game can keep its state in: the internal RAM, the high RAM
+/// and every bank of the cartridge RAM
+#[derive(Debug, Clone)]
+pub struct MemorySnapshot {
+    blocks: Vec<MemoryBlock>,
+}
+
+impl MemorySnapshot {
+    pub fn new(hardware: &Hardware) -> MemorySnapshot {
+        let mut blocks = vec![
+            MemoryBlock {
+                address: WORK_RAM_START,
+                ram_bank: None,
+                data: hardware.internal_ram().to_vec(),
+            },
+            MemoryBlock {
+                address: HIGH_RAM_START,
+                ram_bank: None,
+                data: hardware.high_ram().to_vec(),
+            },
+        ];
+        if let Some(ram) = &hardware.cartridge.ram {
+            blocks.extend(
+                ram.chunks(CARTRIDGE_RAM_BANK_SIZE)
+                    .enumerate()
+                    .map(|(bank, data)| MemoryBlock {
+                        address: CARTRIDGE_RAM_START,
+                        ram_bank: Some(bank as u8),
+                        data: data.to_vec(),
+                    }),
+            );
+        }
+        MemorySnapshot { blocks }
+    }
+
+    fn value(&self, location: Location, size: ValueSize, signed: bool) -> i32 {
+        let data = &self.blocks[location.block].data[location.offset..];
+        match (size, signed) {
+            (ValueSize::Byte, false) => i32::from(data[0]),
+            (ValueSize::Byte, true) => i32::from(data[0] as i8),
+            (ValueSize::Word, false) => i32::from(u16::from_le_bytes([data[0], data[1]])),
+            (ValueSize::Word, true) => i32::from(i16::from_le_bytes([data[0], data[1]])),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Location {
+    block: usize,
+    offset: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SearchResult {
+    pub address: u16,
+    /// Bank of the cartridge RAM, for addresses in 0xA000-0xBFFF
+    pub ram_bank: Option<u8>,
+    pub size: ValueSize,
+    pub value: i32,
+}
+
+impl SearchResult {
+    /// Creates the GameShark codes keeping the value at the address, one per byte.
+    /// Returns None if the value doesn't fit in the size of the result,
+    /// or if GameShark codes can't select its RAM bank.
+    pub fn game_shark_codes(&self, value: i32) -> Option<Vec<GameSharkCode>> {
+        let target = match self.ram_bank {
+            Some(bank) if bank > 0xF => return None,
+            Some(bank) => GameSharkTarget::CartridgeRam(bank),
+            None => GameSharkTarget::Mapped,
+        };
+        if !self.size.fits(value) {
+            return None;
+        }
+
+        let codes = value.to_le_bytes()[..self.size.bytes()]
+            .iter()
+            .enumerate()
+            .map(|(i, &byte)| GameSharkCode {
+                address: self.address.wrapping_add(i as u16),
+                value: byte,
+                target,
+            })
+            .collect();
+        Some(codes)
+    }
+}
+
+/// Finds where a game keeps a value by narrowing down the memory locations
+/// whose changes match what happens in the game
+pub struct RamSearch {
+    size: ValueSize,
+    signed: bool,
+    snapshot: MemorySnapshot,
+    candidates: Vec<Location>,
+}
+
+impl RamSearch {
+    /// Starts a search with every value of the memory as a candidate
+    pub fn new(snapshot: MemorySnapshot, size: ValueSize, signed: bool) -> RamSearch {
+        let candidates = snapshot
+            .blocks
+            .iter()
+            .enumerate()
+            .flat_map(|(block, memory)| {
+                let count = (memory.data.len() + 1).saturating_sub(size.bytes());
+                (0..count).map(move |offset| Location { block, offset })
+            })
+            .collect();
+
+        RamSearch {
+            size,
+            signed,
+            snapshot,
+            candidates,
+        }
+    }
+
+    /// Keeps the candidates matching the filter, and makes the snapshot the new reference
+    pub fn filter(&mut self, snapshot: MemorySnapshot, filter: SearchFilter) {
+        let (size, signed) = (self.size, self.signed);
+        let previous = &self.snapshot;
+        self.candidates.retain(|&location| {
+            // the cartridge RAM can only change size when another game is loaded
+            location.block < snapshot.blocks.len()
+                && filter.matches(
+                    previous.value(location, size, signed),
+                    snapshot.value(location, size, signed),
+                )
+        });
+        self.snapshot = snapshot;
+    }
+
+    pub fn len(&self) -> usize {
+        self.candidates.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.candidates.is_empty()
+    }
+
+    pub fn results(&self) -> impl Iterator<Item = SearchResult> + '_ {
+        self.candidates.iter().map(move |&location| {
+            let block = &self.snapshot.blocks[location.block];
+            SearchResult {
+                address: block.address + location.offset as u16,
+                ram_bank: block.ram_bank,
+                size: self.size,
+                value: self.snapshot.value(location, self.size, self.signed),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::Writable;
+    use crate::cartridge::Cartridge;
+    use crate::util::tests::test_rom::test_rom_with_type;
+
+    // MBC1+RAM+BATTERY with 4 banks of RAM
+    fn hardware() -> Hardware {
+        let cartridge = Cartridge::from_buffer(test_rom_with_type("TEST", 0x03, 3))
+            .ok()
+            .unwrap();
+        Hardware::new(cartridge)
+    }
+
+    fn addresses(search: &RamSearch) -> Vec<(u16, Option<u8>)> {
+        search
+            .results()
+            .map(|result| (result.address, result.ram_bank))
+            .collect()
+    }
+
+    #[test]
+    fn candidates() {
+        let hardware = hardware();
+        let bytes = RamSearch::new(MemorySnapshot::new(&hardware), ValueSize::Byte, false);
+        assert_eq!(bytes.len(), 0x2000 + 127 + 4 * 0x2000);
+        // values can't span two banks
+        let words = RamSearch::new(MemorySnapshot::new(&hardware), ValueSize::Word, false);
+        assert_eq!(words.len(), 0x1FFF + 126 + 4 * 0x1FFF);
+    }
+
+    #[test]
+    fn narrow_down() {
+        let mut hardware = hardware();
+        hardware.write(0xC123, 10);
+        hardware.write(0xFF90, 10);
+        hardware.cartridge.write_ram_bank(2, 0xA456, 10);
+        let mut search = RamSearch::new(MemorySnapshot::new(&hardware), ValueSize::Byte, true);
+        search.filter(MemorySnapshot::new(&hardware), SearchFilter::EqualTo(10));
+        assert_eq!(search.len(), 3);
+
+        hardware.write(0xC123, 9);
+        hardware.write(0xFF90, 11);
+        search.filter(MemorySnapshot::new(&hardware), SearchFilter::Changed);
+        assert_eq!(addresses(&search), vec![(0xC123, None), (0xFF90, None)]);
+
+        hardware.write(0xC123, 0xFF);
+        hardware.write(0xFF90, 0xFF);
+        // -1 for signed values
+        search.filter(MemorySnapshot::new(&hardware), SearchFilter::Decreased);
+        assert_eq!(addresses(&search), vec![(0xC123, None), (0xFF90, None)]);
+        search.filter(MemorySnapshot::new(&hardware), SearchFilter::Unchanged);
+        assert_eq!(search.results().next().unwrap().value, -1);
+    }
+
+    #[test]
+    fn words() {
+        let mut hardware = hardware();
+        hardware.cartridge.write_ram_bank(3, 0xA010, 0x34);
+        hardware.cartridge.write_ram_bank(3, 0xA011, 0x12);
+        let mut search = RamSearch::new(MemorySnapshot::new(&hardware), ValueSize::Word, false);
+        search.filter(
+            MemorySnapshot::new(&hardware),
+            SearchFilter::EqualTo(0x1234),
+        );
+        assert_eq!(addresses(&search), vec![(0xA010, Some(3))]);
+
+        hardware.cartridge.write_ram_bank(3, 0xA011, 0x13);
+        search.filter(MemorySnapshot::new(&hardware), SearchFilter::Increased);
+        let result = search.results().next().unwrap();
+        assert_eq!(result.value, 0x1334);
+        assert_eq!(
+            result.game_shark_codes(0x0500),
+            Some(vec![
+                GameSharkCode {
+                    address: 0xA010,
+                    value: 0x00,
+                    target: GameSharkTarget::CartridgeRam(3),
+                },
+                GameSharkCode {
+                    address: 0xA011,
+                    value: 0x05,
+                    target: GameSharkTarget::CartridgeRam(3),
+                },
+            ])
+        );
+        assert_eq!(result.game_shark_codes(0x10000), None);
+    }
+
+    #[test]
+    fn value_sizes() {
+        assert!(ValueSize::Byte.fits(255));
+        assert!(ValueSize::Byte.fits(-128));
+        assert!(!ValueSize::Byte.fits(256));
+        assert!(!ValueSize::Byte.fits(-129));
+        assert!(ValueSize::Word.fits(0xFFFF));
+        assert!(ValueSize::Word.fits(-0x8000));
+        assert!(!ValueSize::Word.fits(0x10000));
+        assert!(!ValueSize::Word.fits(-0x8001));
+    }
+}
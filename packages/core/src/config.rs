@@ -1,9 +1,16 @@
+use crate::cartridge::mbc::MBCVariant;
 use crate::debugger::Debugger;
 use crate::gameboy::DeviceType;
+use crate::util::clock::{Clock, SystemClock};
+use std::rc::Rc;
 
 pub struct Config {
     pub device_type: DeviceType,
     pub debugger: Option<Debugger>,
+    /// Source of time for the cartridge's real-time clock
+    pub clock: Rc<dyn Clock>,
+    /// Memory bank controller to use instead of the one detected from the cartridge
+    pub mbc_variant: Option<MBCVariant>,
 }
 
 impl Default for Config {
@@ -11,6 +18,8 @@ impl Default for Config {
         Self {
             device_type: DeviceType::GameBoy,
             debugger: None,
+            clock: Rc::new(SystemClock),
+            mbc_variant: None,
         }
     }
 }
@@ -1,12 +1,18 @@
 use crate::bus::Readable;
+use crate::cartridge::camera::CameraSource;
 use crate::cartridge::Cartridge;
+use crate::cheats::search::MemorySnapshot;
+use crate::cheats::{Cheat, CheatError, Cheats};
 use crate::config::Config;
 use crate::debugger::debug_info::DebugInfo;
 use crate::debugger::processor_debug_info::ProcessorDebugInfo;
 use crate::debugger::Debugger;
 use crate::hardware::{joypad::Input, Hardware};
 use crate::processor::{Processor, ProcessorStepResult};
-use crate::util::savestate::{LoadSavestateError, Savestate};
+use crate::util::savestate::{
+    write_savestate_section, LoadSavestateError, Savestate, SavestateHeader, SavestateSection,
+    SavestateSections,
+};
 use crate::video::screen::BUFFER_SIZE;
 use crate::video::status_register::StatusMode;
 
@@ -14,13 +20,19 @@ use crate::video::status_register::StatusMode;
 pub struct Gameboy {
     processor: Processor,
     hardware: Hardware,
+    cheats: Cheats,
 }
 
 impl Gameboy {
-    pub fn new(cartridge: Cartridge, _config: &Config) -> Gameboy {
+    pub fn new(mut cartridge: Cartridge, config: &Config) -> Gameboy {
+        if let Some(variant) = config.mbc_variant {
+            cartridge.force_mbc_variant(variant);
+        }
+        cartridge.set_clock(config.clock.clone());
         Gameboy {
             processor: Processor::new(),
             hardware: Hardware::new(cartridge),
+            cheats: Cheats::default(),
         }
     }
 
@@ -35,6 +47,7 @@ impl Gameboy {
     pub fn run_to_vblank(&mut self) {
         loop {
             if let GameboyStepResult(_, Some(StatusMode::VBlank)) = self.step() {
+                self.cheats.apply_game_shark_codes(&mut self.hardware);
                 break;
             }
         }
@@ -46,6 +59,7 @@ impl Gameboy {
         loop {
             let GameboyStepResult(cpu_step_result, status_mode) = self.step();
             if let Some(StatusMode::VBlank) = status_mode {
+                self.cheats.apply_game_shark_codes(&mut self.hardware);
                 return GameboyEvent::VBlank;
             } else if let (Some(debugger), ProcessorStepResult::InstructionCompleted) =
                 (debugger.as_mut(), cpu_step_result)
@@ -79,22 +93,109 @@ impl Gameboy {
         &self.hardware
     }
 
+    /// Copies the RAM the game can keep its state in, including every bank of the cartridge RAM,
+    /// to search it for cheats
+    pub fn memory_snapshot(&self) -> MemorySnapshot {
+        MemorySnapshot::new(&self.hardware)
+    }
+
+    /// Returns true if the rumble motor of the cartridge is on.
+    /// Games turn it on and off quickly to change its strength,
+    /// so this should be polled every frame.
+    pub fn rumble(&self) -> bool {
+        self.hardware.cartridge.rumble()
+    }
+
+    /// Tilts the cartridge for games with an accelerometer.
+    /// The values are in g, from about -1 to 1: x is positive when tilted right
+    /// and y is positive when tilted towards the player.
+    pub fn set_tilt(&mut self, x: f32, y: f32) {
+        self.hardware.cartridge.set_tilt(x, y);
+    }
+
+    /// Sets the images seen by the Game Boy Camera when taking pictures.
+    /// See `cartridge::camera` for sources reading images from files.
+    pub fn set_camera_source(&mut self, source: Box<dyn CameraSource>) {
+        self.hardware.cartridge.set_camera_source(source);
+    }
+
+    /// Adds an enabled Game Genie, GameShark or Codebreaker code, returns its index in `cheats`.
+    /// GameShark and Codebreaker codes are applied at every VBlank.
+    pub fn add_cheat(&mut self, code: &str) -> Result<usize, CheatError> {
+        let index = self.cheats.add(code)?;
+        self.update_cheats();
+        Ok(index)
+    }
+
+    pub fn remove_cheat(&mut self, index: usize) -> Option<Cheat> {
+        let cheat = self.cheats.remove(index);
+        self.update_cheats();
+        cheat
+    }
+
+    pub fn set_cheat_enabled(&mut self, index: usize, enabled: bool) {
+        self.cheats.set_enabled(index, enabled);
+        self.update_cheats();
+    }
+
+    pub fn cheats(&self) -> &[Cheat] {
+        self.cheats.list()
+    }
+
+    fn update_cheats(&mut self) {
+        let codes = self.cheats.game_genie_codes();
+        self.hardware.cartridge.set_game_genie_codes(codes);
+    }
+
+    /// Returns true if the battery backed data of the cartridge changed since
+    /// the last call to `mark_ram_flushed`, so it needs to be saved
+    pub fn is_ram_dirty(&self) -> bool {
+        self.hardware.cartridge.is_ram_dirty()
+    }
+
+    /// Marks the cartridge RAM as saved
+    pub fn mark_ram_flushed(&mut self) {
+        self.hardware.cartridge.mark_ram_flushed();
+    }
+
     /// Sends an button event to the GameBoy
     pub fn send_input(&mut self, input: Input) {
         self.hardware.send_input(input);
     }
 
+    /// Dumps the state of the GameBoy.
+    /// The savestate starts with a header identifying the ROM it was made with,
+    /// followed by a length-prefixed section for each component.
     pub fn dump_savestate(&self) -> Vec<u8> {
         let mut buffer = Vec::new();
-        self.processor.dump_savestate(&mut buffer);
-        self.hardware.dump_savestate(&mut buffer);
+        SavestateHeader::new(self.hardware.cartridge.metadata()).dump(&mut buffer);
+        write_savestate_section(&mut buffer, SavestateSection::Processor, |buffer| {
+            self.processor.dump_savestate(buffer)
+        });
+        self.hardware.dump_savestate_sections(&mut buffer);
         buffer
     }
 
+    /// Loads a savestate made by `dump_savestate`.
+    /// Savestates made with an older version of the format are migrated to the current one.
+    /// If the savestate can't be loaded, the emulator is left in the state it was before the call.
     pub fn load_savestate(&mut self, buffer: Vec<u8>) -> Result<(), LoadSavestateError> {
         let mut iter = buffer.iter();
-        self.processor.load_savestate(&mut iter)?;
-        self.hardware.load_savestate(&mut iter)?;
+        let header = SavestateHeader::load(&mut iter)?;
+        if !header.matches(self.hardware.cartridge.metadata()) {
+            return Err(LoadSavestateError::WrongRom(header.rom_title));
+        }
+
+        let mut sections = SavestateSections::read(&mut iter)?;
+        sections.migrate(header.version)?;
+
+        // nothing is replaced until every section has been decoded
+        let mut processor = Processor::new();
+        sections.load(SavestateSection::Processor, |buffer| {
+            processor.load_savestate(buffer)
+        })?;
+        self.hardware.load_savestate_sections(&sections)?;
+        self.processor = processor;
         Ok(())
     }
 }
@@ -122,3 +223,235 @@ impl Iterator for Gameboy {
         Some(self.hardware().video.screen().buffer.rgb())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::{Readable, Writable};
+    use crate::hardware::joypad::{Button, InputType};
+    use crate::processor::registers::RegisterType;
+    use crate::util::clock::FakeClock;
+    use crate::util::tests::test_rom::{test_rom, test_rom_with_program, test_rom_with_type};
+    use std::rc::Rc;
+
+    // writes an incrementing value to every byte of VRAM in a loop
+    const VRAM_FILL_PROGRAM: &[u8] = &[
+        0x21, 0x00, 0x80, // LD HL,0x8000
+        0x3C, // INC A
+        0x22, // LD (HL+),A
+        0xCB, 0xAC, // RES 5,H
+        0x18, 0xFA, // JR -6
+    ];
+
+    fn gameboy(title: &str) -> Gameboy {
+        let cartridge = Cartridge::from_buffer(test_rom(title)).ok().unwrap();
+        Gameboy::new(cartridge, &Config::default())
+    }
+
+    fn frames(gameboy: &mut Gameboy, count: usize) -> Vec<Vec<u8>> {
+        gameboy.take(count).map(|frame| frame.to_vec()).collect()
+    }
+
+    #[test]
+    fn savestate_round_trip() {
+        let mut gameboy = gameboy("TEST");
+        gameboy.run_to_vblank();
+        let savestate = gameboy.dump_savestate();
+
+        let mut loaded = self::gameboy("TEST");
+        assert!(loaded.load_savestate(savestate.clone()).is_ok());
+        assert_eq!(loaded.dump_savestate(), savestate);
+    }
+
+    #[test]
+    fn savestate_is_deterministic() {
+        let rom = test_rom_with_program("TEST", VRAM_FILL_PROGRAM);
+        let mut gameboy = Gameboy::new(
+            Cartridge::from_buffer(rom.clone()).ok().unwrap(),
+            &Config::default(),
+        );
+        gameboy.send_input(Input {
+            input_type: InputType::Down,
+            button: Button::Start,
+        });
+        frames(&mut gameboy, 10);
+        let savestate = gameboy.dump_savestate();
+        let expected_frames = frames(&mut gameboy, 20);
+
+        let mut loaded = Gameboy::new(
+            Cartridge::from_buffer(rom).ok().unwrap(),
+            &Config::default(),
+        );
+        assert!(loaded.load_savestate(savestate.clone()).is_ok());
+        assert_eq!(loaded.dump_savestate(), savestate);
+        assert!(frames(&mut loaded, 20) == expected_frames);
+    }
+
+    #[test]
+    fn savestate_wrong_rom() {
+        let savestate = gameboy("TEST").dump_savestate();
+        assert_eq!(
+            gameboy("OTHER").load_savestate(savestate),
+            Err(LoadSavestateError::WrongRom("TEST".to_string()))
+        );
+    }
+
+    #[test]
+    fn savestate_without_header() {
+        assert_eq!(
+            gameboy("TEST").load_savestate(vec![0; 64]),
+            Err(LoadSavestateError::InvalidHeader)
+        );
+    }
+
+    #[test]
+    fn failed_load_keeps_state() {
+        let mut other = gameboy("TEST");
+        other.run_to_vblank();
+        let mut savestate = other.dump_savestate();
+        // the clock section is the last one: claim the savestate contains a clock
+        *savestate.last_mut().unwrap() = 1;
+
+        let mut gameboy = gameboy("TEST");
+        let before = gameboy.dump_savestate();
+        assert_eq!(
+            gameboy.load_savestate(savestate),
+            Err(LoadSavestateError::Section(
+                SavestateSection::Clock,
+                Box::new(LoadSavestateError::MissingComponent("real-time clock"))
+            ))
+        );
+        assert_eq!(gameboy.dump_savestate(), before);
+    }
+
+    #[test]
+    fn failed_savestate_keeps_the_cartridge_ram() {
+        // MBC1+RAM+BATTERY
+        let cartridge = Cartridge::from_buffer(test_rom_with_type("TEST", 0x03, 2))
+            .ok()
+            .unwrap();
+        let mut gameboy = Gameboy::new(cartridge, &Config::default());
+        let mut savestate = gameboy.dump_savestate();
+        gameboy.hardware.cartridge.write_ram_bank(0, 0xA000, 0x42);
+        gameboy.mark_ram_flushed();
+
+        // the clock section is the last one, and the cartridge section comes before it
+        *savestate.last_mut().unwrap() = 1;
+        let before = gameboy.dump_savestate();
+        assert!(gameboy.load_savestate(savestate).is_err());
+        assert_eq!(gameboy.dump_savestate(), before);
+        assert!(!gameboy.is_ram_dirty());
+    }
+
+    #[test]
+    fn setting_the_clock_dirties_the_ram() {
+        // MBC3+TIMER+RAM+BATTERY
+        let cartridge = Cartridge::from_buffer(test_rom_with_type("TEST", 0x10, 2))
+            .ok()
+            .unwrap();
+        let config = Config {
+            clock: Rc::new(FakeClock::new(1000)),
+            ..Config::default()
+        };
+        let mut gameboy = Gameboy::new(cartridge, &config);
+        gameboy.hardware.write(0x0000, 0x0A); // enable RAM and clock
+        gameboy.hardware.write(0x4000, 0x08); // select the seconds
+        assert!(!gameboy.is_ram_dirty());
+
+        gameboy.hardware.write(0xA000, 30);
+        assert!(gameboy.is_ram_dirty());
+        gameboy.mark_ram_flushed();
+        gameboy.hardware.write(0xA000, 30);
+        assert!(!gameboy.is_ram_dirty());
+
+        // halting the clock
+        gameboy.hardware.write(0x4000, 0x0C);
+        gameboy.hardware.write(0xA000, 0x40);
+        assert!(gameboy.is_ram_dirty());
+    }
+
+    #[test]
+    fn game_genie_cheats() {
+        let mut gameboy = gameboy("TEST");
+        // patch the first letter of the title, if it's still a 'T'
+        assert_eq!(gameboy.add_cheat("991-34F-BAB"), Ok(0));
+        assert_eq!(gameboy.hardware().read(0x0134), 0x99);
+        assert_eq!(
+            gameboy.add_cheat("991-34F-BA"),
+            Err(CheatError::InvalidLength(8))
+        );
+
+        gameboy.set_cheat_enabled(0, false);
+        assert_eq!(gameboy.hardware().read(0x0134), b'T');
+        gameboy.set_cheat_enabled(0, true);
+        assert_eq!(gameboy.cheats()[0].code, "991-34F-BAB");
+        assert!(gameboy.remove_cheat(0).is_some());
+        assert_eq!(gameboy.hardware().read(0x0134), b'T');
+    }
+
+    #[test]
+    fn game_shark_cheats() {
+        let mut gameboy = gameboy("TEST");
+        assert_eq!(gameboy.add_cheat("014200C0"), Ok(0));
+        assert_eq!(gameboy.add_cheat("C001-43"), Ok(1));
+        gameboy.set_cheat_enabled(1, false);
+        gameboy.run_to_vblank();
+        assert_eq!(gameboy.hardware().read(0xC000), 0x42);
+        assert_ne!(gameboy.hardware().read(0xC001), 0x43);
+
+        gameboy.set_cheat_enabled(1, true);
+        assert!(matches!(gameboy.run_to_event(None), GameboyEvent::VBlank));
+        assert_eq!(gameboy.hardware().read(0xC001), 0x43);
+    }
+
+    // fills OAM with the low byte of its addresses, then runs the first instruction of
+    // `program` with HL in OAM, `delay` cycles after the PPU started reading OAM for a line
+    fn oam_after_instruction(program: &[u8], delay: usize) -> Vec<u8> {
+        let rom = test_rom_with_program("TEST", program);
+        let mut gameboy = Gameboy::new(
+            Cartridge::from_buffer(rom).ok().unwrap(),
+            &Config::default(),
+        );
+        let hardware = &mut gameboy.hardware;
+        for address in 0xFE00..=0xFE9F {
+            hardware.write(address, address as u8);
+        }
+        hardware.write(0xFF40, 0x91);
+        while hardware.clock() != Some(StatusMode::ReadingOAM) {}
+        for _ in 0..delay {
+            hardware.clock();
+        }
+
+        let registers = &mut gameboy.processor.registers;
+        registers.set_reg(RegisterType::PC, 0x100);
+        registers.set_reg(RegisterType::HL, 0xFE10);
+        gameboy.processor.step(&mut gameboy.hardware);
+        (0xFE00..=0xFE9F)
+            .map(|address| gameboy.hardware.read(address))
+            .collect()
+    }
+
+    fn corrupted_rows(oam: &[u8]) -> Vec<usize> {
+        (0..20)
+            .filter(|row| (0..8).any(|i| oam[row * 8 + i] != (row * 8 + i) as u8))
+            .collect()
+    }
+
+    #[test]
+    fn oam_bug_in_the_cycle_of_the_access() {
+        // the opcode is fetched in the first M-cycle, HL is on the bus in the second
+        assert_eq!(corrupted_rows(&oam_after_instruction(&[0x23], 0)), [1]); // INC HL
+        assert_eq!(corrupted_rows(&oam_after_instruction(&[0x23], 8)), [3]);
+        assert_eq!(corrupted_rows(&oam_after_instruction(&[0x7E], 8)), [3]); // LD A,(HL)
+        assert_eq!(
+            corrupted_rows(&oam_after_instruction(&[0x2A], 16)), // LD A,(HL+)
+            [3, 4, 5]
+        );
+    }
+
+    #[test]
+    fn no_oam_bug_outside_oam_scan() {
+        assert_eq!(corrupted_rows(&oam_after_instruction(&[0x23], 100)), []);
+        assert_eq!(corrupted_rows(&oam_after_instruction(&[0x2A], 100)), []);
+    }
+}
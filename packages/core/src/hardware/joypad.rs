@@ -1,8 +1,23 @@
 use crate::bus::{Readable, Writable};
+use crate::processor::interrupt::{Interrupt, InterruptHandler};
 use crate::util::bitflags::Bitflags;
+use crate::util::bits::get_bit;
+use crate::util::savestate::{read_savestate_byte, LoadSavestateError, Savestate, SavestateStream};
 
+/// Bits 4 and 5 of the P1 register, used to select which key groups are read
+const SELECTION_MASK: u8 = 0b11_0000;
+
+/// Bits 6 and 7 of the P1 register, which are unused and always read as 1
+const UNUSED_BITS: u8 = 0b1100_0000;
+
+/// Represents the P1 register (0xFF00).
+///
+/// The buttons are laid out as a 2x4 matrix: writing a 0 to bit 4 selects the directional keys
+/// and writing a 0 to bit 5 selects the button keys. The lower 4 bits then read as 0 for
+/// every pressed key of the selected groups. When both groups are selected their lines are
+/// combined, and when none is selected every line reads as 1.
 pub struct Joypad {
-    mode: Mode,
+    selection: u8,
     pushed_keys: u8,
 }
 
@@ -12,18 +27,49 @@ impl Joypad {
     }
 
     pub fn set_mode(&mut self, mode: Mode) {
-        self.mode = mode;
+        self.selection = mode as u8;
+    }
+
+    /// Sends a button event to the joypad, requesting a keypad interrupt
+    /// if it made one of the selected lines go from high to low
+    pub fn send_input(&mut self, input: Input, interrupt_handler: &mut InterruptHandler) {
+        let lines = self.lines();
+        self.set_flag(input.button, input.input_type == InputType::Down);
+        self.check_interrupt(lines, interrupt_handler);
+    }
+
+    /// Changes the selected key groups, requesting a keypad interrupt
+    /// if it made one of the lines go from high to low
+    pub fn select(&mut self, value: u8, interrupt_handler: &mut InterruptHandler) {
+        let lines = self.lines();
+        self.write(0xFF00, value);
+        self.check_interrupt(lines, interrupt_handler);
+    }
+
+    /// Returns the state of the 4 input lines, where a 0 means that
+    /// a key of one of the selected groups is pressed
+    fn lines(&self) -> u8 {
+        let mut lines = 0xF;
+        if !get_bit(self.selection, 4) {
+            lines &= !self.pushed_keys >> 4;
+        }
+        if !get_bit(self.selection, 5) {
+            lines &= !self.pushed_keys & 0xF;
+        }
+        lines
     }
 
-    pub fn send_input(&mut self, input: Input) {
-        self.set_flag(input.button, input.input_type == InputType::Down)
+    fn check_interrupt(&self, previous_lines: u8, interrupt_handler: &mut InterruptHandler) {
+        if previous_lines & !self.lines() != 0 {
+            interrupt_handler.request_interrupt(Interrupt::Keypad);
+        }
     }
 }
 
 impl Default for Joypad {
     fn default() -> Joypad {
         Joypad {
-            mode: Mode::DirectionalKeys,
+            selection: Mode::DirectionalKeys as u8,
             pushed_keys: 0,
         }
     }
@@ -31,26 +77,29 @@ impl Default for Joypad {
 
 impl Readable for Joypad {
     fn read(&self, _: u16) -> u8 {
-        (self.mode as u8)
-            | if let Mode::DirectionalKeys = self.mode {
-                !self.pushed_keys >> 4
-            } else {
-                !self.pushed_keys & 0xF
-            }
+        UNUSED_BITS | self.selection | self.lines()
     }
 }
 
 impl Writable for Joypad {
     fn write(&mut self, _: u16, value: u8) {
-        match value {
-            0x20 => {
-                self.mode = Mode::DirectionalKeys;
-            }
-            0x10 => {
-                self.mode = Mode::ButtonKeys;
-            }
-            _ => {}
-        };
+        self.selection = value & SELECTION_MASK;
+    }
+}
+
+impl Savestate for Joypad {
+    fn dump_savestate(&self, buffer: &mut Vec<u8>) {
+        buffer.push(self.selection);
+        buffer.push(self.pushed_keys);
+    }
+
+    fn load_savestate<'a>(
+        &mut self,
+        buffer: &mut SavestateStream<'a>,
+    ) -> Result<(), LoadSavestateError> {
+        self.selection = read_savestate_byte(buffer)? & SELECTION_MASK;
+        self.pushed_keys = read_savestate_byte(buffer)?;
+        Ok(())
     }
 }
 
@@ -92,6 +141,8 @@ impl Into<u8> for Button {
     }
 }
 
+/// Represents the value written to the P1 register to select a single group of keys.
+/// Bit 4 low selects the directional keys, bit 5 low selects the button keys.
 #[derive(Copy, Clone)]
 pub enum Mode {
     DirectionalKeys = 0x20,
@@ -115,14 +166,14 @@ mod tests {
     #[test]
     fn fetch_joypad_info_none() {
         let joypad = Joypad::new();
-        assert_eq!(joypad.read(0), 0x2F);
+        assert_eq!(joypad.read(0), 0xEF);
     }
 
     #[test]
     fn fetch_joypad_info_some_wrong_mode() {
         let mut joypad = Joypad::new();
         joypad.set_flag(Button::A, true);
-        assert_eq!(joypad.read(0), 0x2F);
+        assert_eq!(joypad.read(0), 0xEF);
     }
 
     #[test]
@@ -130,6 +181,61 @@ mod tests {
         let mut joypad = Joypad::new();
         joypad.set_mode(Mode::ButtonKeys);
         joypad.set_flag(Button::A, true);
-        assert_eq!(joypad.read(0), 0x1E);
+        assert_eq!(joypad.read(0), 0xDE);
+    }
+
+    #[test]
+    fn fetch_joypad_info_both_groups() {
+        let mut joypad = Joypad::new();
+        joypad.write(0, 0);
+        joypad.set_flag(Button::A, true);
+        joypad.set_flag(Button::Left, true);
+        assert_eq!(joypad.read(0), 0xCC);
+    }
+
+    #[test]
+    fn fetch_joypad_info_no_group() {
+        let mut joypad = Joypad::new();
+        joypad.write(0, 0x30);
+        joypad.set_flag(Button::A, true);
+        joypad.set_flag(Button::Left, true);
+        assert_eq!(joypad.read(0), 0xFF);
+    }
+
+    #[test]
+    fn input_requests_interrupt() {
+        let mut joypad = Joypad::new();
+        let mut interrupt_handler = InterruptHandler::new();
+        joypad.send_input(
+            Input {
+                input_type: InputType::Down,
+                button: Button::Up,
+            },
+            &mut interrupt_handler,
+        );
+        assert_eq!(interrupt_handler.fetch_interrupt(), Some(Interrupt::Keypad));
+    }
+
+    #[test]
+    fn unselected_input_does_not_request_interrupt() {
+        let mut joypad = Joypad::new();
+        let mut interrupt_handler = InterruptHandler::new();
+        joypad.send_input(
+            Input {
+                input_type: InputType::Down,
+                button: Button::A,
+            },
+            &mut interrupt_handler,
+        );
+        assert!(interrupt_handler.fetch_interrupt().is_none());
+    }
+
+    #[test]
+    fn selection_requests_interrupt() {
+        let mut joypad = Joypad::new();
+        let mut interrupt_handler = InterruptHandler::new();
+        joypad.set_flag(Button::Start, true);
+        joypad.select(Mode::ButtonKeys as u8, &mut interrupt_handler);
+        assert_eq!(interrupt_handler.fetch_interrupt(), Some(Interrupt::Keypad));
     }
 }
@@ -1,12 +1,16 @@
 use crate::bus::{Bus, Readable, Writable};
 use crate::cartridge::Cartridge;
 use crate::processor::interrupt::{Interrupt, InterruptHandler};
-use crate::video::Video;
+use crate::video::{OAMCorruption, Video};
 
 use self::joypad::{Input, Joypad};
 use self::timer::Timer;
-use crate::util::savestate::{read_savestate_byte, LoadSavestateError, Savestate, SavestateStream};
+use crate::util::savestate::{
+    read_savestate_bool, read_savestate_byte, write_savestate_section, LoadSavestateError,
+    Savestate, SavestateSection, SavestateSections,
+};
 use crate::video::status_register::StatusMode;
+use std::cell::{Cell, RefCell};
 
 pub mod joypad;
 mod timer;
@@ -19,6 +23,10 @@ pub struct Hardware {
     pub video: Video,
     internal_ram: [u8; 8192],
     high_ram: [u8; 127],
+    /// M-cycles taken so far by the accesses of the CPU, see `Bus::start_cpu_cycles`
+    cpu_cycle: Cell<Option<u16>>,
+    /// OAM corruptions triggered by the CPU, with the M-cycle they happened in
+    oam_bugs: RefCell<Vec<(u16, OAMCorruption)>>,
 }
 
 impl Hardware {
@@ -31,6 +39,8 @@ impl Hardware {
             video: Video::default(),
             internal_ram: [0; 8192],
             high_ram: [0; 127],
+            cpu_cycle: Cell::new(None),
+            oam_bugs: RefCell::new(Vec::new()),
         }
     }
 
@@ -56,15 +66,47 @@ impl Hardware {
         &self.interrupt_handler
     }
 
+    pub fn internal_ram(&self) -> &[u8] {
+        &self.internal_ram
+    }
+
+    pub fn high_ram(&self) -> &[u8] {
+        &self.high_ram
+    }
+
     fn audio_unimplemented(&self) {}
 
+    // counts an M-cycle of the CPU, putting the address on the bus if it accesses one
+    fn cpu_cycle(&self, access: Option<(u16, OAMCorruption)>) {
+        if let Some(cycle) = self.cpu_cycle.get() {
+            if let Some((0xFE00..=0xFEFF, corruption)) = access {
+                self.oam_bugs.borrow_mut().push((cycle, corruption));
+            }
+            self.cpu_cycle.set(Some(cycle + 1));
+        }
+    }
+
     pub fn send_input(&mut self, input: Input) {
-        self.joypad.send_input(input);
+        self.joypad.send_input(input, &mut self.interrupt_handler);
     }
 }
 
 impl Readable for Hardware {
     fn read(&self, address: u16) -> u8 {
+        self.cpu_cycle(Some((address, OAMCorruption::Read)));
+        self.read_memory(address)
+    }
+}
+
+impl Writable for Hardware {
+    fn write(&mut self, address: u16, value: u8) {
+        self.cpu_cycle(Some((address, OAMCorruption::Write)));
+        self.write_memory(address, value);
+    }
+}
+
+impl Hardware {
+    fn read_memory(&self, address: u16) -> u8 {
         match address {
             0..=0x7FFF | 0xA000..=0xBFFF => self.cartridge.read(address), // cartridge
 
@@ -140,10 +182,8 @@ impl Readable for Hardware {
             } // empty
         }
     }
-}
 
-impl Writable for Hardware {
-    fn write(&mut self, address: u16, value: u8) {
+    fn write_memory(&mut self, address: u16, value: u8) {
         match address {
             0..=0x7FFF | 0xA000..=0xBFFF => self.cartridge.write(address, value), // cartridge
 
@@ -169,7 +209,7 @@ impl Writable for Hardware {
 
             0xFF4C..=0xFF7F | 0xFEA0..=0xFEFF => {} // empty but unusable for i/o
 
-            0xFF00 => self.joypad.write(address, value), // joypad
+            0xFF00 => self.joypad.select(value, &mut self.interrupt_handler), // joypad
 
             0xFF01 => {
                 // TODO serial transfer data
@@ -236,44 +276,129 @@ impl Bus for Hardware {
     }
 
     fn dma_transfer(&mut self, from: u16, to: u16, size: u16) {
+        // the CPU isn't the one accessing memory
         for i in 0..=size {
-            let value = self.read(from + i);
-            self.write(to + i, value);
+            let value = self.read_memory(from + i);
+            self.write_memory(to + i, value);
         }
     }
 
     fn master_interrupt_enable(&self) -> bool {
         self.interrupt_handler.master_interrupt_enable()
     }
-}
 
-impl Savestate for Hardware {
-    fn dump_savestate(&self, buffer: &mut Vec<u8>) {
-        self.cartridge.dump_savestate(buffer);
-        self.interrupt_handler.dump_savestate(buffer);
-        self.timer.dump_savestate(buffer);
-        self.video.dump_savestate(buffer);
-        buffer.append(&mut self.internal_ram.to_vec());
-        buffer.append(&mut self.high_ram.to_vec());
+    fn start_cpu_cycles(&mut self) {
+        self.cpu_cycle.set(Some(0));
     }
 
-    fn load_savestate<'a>(
-        &mut self,
-        buffer: &mut SavestateStream<'a>,
-    ) -> Result<(), LoadSavestateError> {
-        self.cartridge.load_savestate(buffer)?;
-        self.interrupt_handler.load_savestate(buffer)?;
-        self.timer.load_savestate(buffer)?;
-        self.video.load_savestate(buffer)?;
+    fn increment_cycle(&self, address: u16) {
+        self.cpu_cycle(Some((address, OAMCorruption::Write)));
+    }
 
-        for i in 0..self.internal_ram.len() {
-            self.internal_ram[i] = read_savestate_byte(buffer)?;
-        }
+    fn idle_cycle(&self) {
+        self.cpu_cycle(None);
+    }
 
-        for i in 0..self.high_ram.len() {
-            self.high_ram[i] = read_savestate_byte(buffer)?;
+    fn read_increment(&self, address: u16) -> u8 {
+        self.cpu_cycle(Some((address, OAMCorruption::ReadDuringIncrease)));
+        self.read_memory(address)
+    }
+
+    fn end_cpu_cycles(&mut self) {
+        self.cpu_cycle.set(None);
+        for (cycle, corruption) in self.oam_bugs.get_mut().drain(..) {
+            self.video.trigger_oam_bug(corruption, cycle * 4);
         }
+    }
+}
+
+impl Hardware {
+    /// Writes the state of every component of the hardware, each in its own savestate section
+    pub fn dump_savestate_sections(&self, buffer: &mut Vec<u8>) {
+        write_savestate_section(buffer, SavestateSection::Cartridge, |buffer| {
+            self.cartridge.dump_savestate(buffer)
+        });
+        write_savestate_section(buffer, SavestateSection::Interrupts, |buffer| {
+            self.interrupt_handler.dump_savestate(buffer)
+        });
+        write_savestate_section(buffer, SavestateSection::Timer, |buffer| {
+            self.timer.dump_savestate(buffer)
+        });
+        write_savestate_section(buffer, SavestateSection::Video, |buffer| {
+            self.video.dump_savestate(buffer)
+        });
+        write_savestate_section(buffer, SavestateSection::Memory, |buffer| {
+            buffer.extend_from_slice(&self.internal_ram);
+            buffer.extend_from_slice(&self.high_ram);
+        });
+        write_savestate_section(buffer, SavestateSection::Joypad, |buffer| {
+            self.joypad.dump_savestate(buffer)
+        });
+        write_savestate_section(buffer, SavestateSection::Clock, |buffer| {
+            if let Some(clock) = self.cartridge.real_time_clock() {
+                buffer.push(1);
+                clock.dump_savestate(buffer);
+            } else {
+                buffer.push(0);
+            }
+        });
+    }
 
+    /// Loads the sections written by `dump_savestate_sections`.
+    /// Every section is decoded before any component is replaced,
+    /// so the hardware is left untouched if one of them is invalid.
+    pub fn load_savestate_sections(
+        &mut self,
+        sections: &SavestateSections,
+    ) -> Result<(), LoadSavestateError> {
+        let mut cartridge = sections.load(SavestateSection::Cartridge, |buffer| {
+            self.cartridge.decode_savestate(buffer)
+        })?;
+
+        let mut interrupt_handler = InterruptHandler::new();
+        sections.load(SavestateSection::Interrupts, |buffer| {
+            interrupt_handler.load_savestate(buffer)
+        })?;
+        let mut timer = Timer::new();
+        sections.load(SavestateSection::Timer, |buffer| {
+            timer.load_savestate(buffer)
+        })?;
+        let mut video = Video::default();
+        sections.load(SavestateSection::Video, |buffer| {
+            video.load_savestate(buffer)
+        })?;
+
+        let mut internal_ram = [0; 8192];
+        let mut high_ram = [0; 127];
+        sections.load(SavestateSection::Memory, |buffer| {
+            for value in internal_ram.iter_mut().chain(high_ram.iter_mut()) {
+                *value = read_savestate_byte(buffer)?;
+            }
+
+            Ok(())
+        })?;
+        let mut joypad = Joypad::new();
+        sections.load(SavestateSection::Joypad, |buffer| {
+            joypad.load_savestate(buffer)
+        })?;
+        sections.load(SavestateSection::Clock, |buffer| {
+            if read_savestate_bool(buffer)? {
+                cartridge
+                    .real_time_clock_mut()
+                    .ok_or(LoadSavestateError::MissingComponent("real-time clock"))?
+                    .load_savestate(buffer)?;
+            }
+
+            Ok(())
+        })?;
+
+        self.cartridge.restore_savestate(cartridge);
+        self.interrupt_handler = interrupt_handler;
+        self.timer = timer;
+        self.video = video;
+        self.internal_ram = internal_ram;
+        self.high_ram = high_ram;
+        self.joypad = joypad;
         Ok(())
     }
 }
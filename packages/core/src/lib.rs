@@ -1,5 +1,6 @@
 pub mod bus;
 pub mod cartridge;
+pub mod cheats;
 pub mod config;
 pub mod debugger;
 pub mod gameboy;
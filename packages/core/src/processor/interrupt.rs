@@ -194,7 +194,7 @@ impl Savestate for InterruptHandler {
         buffer: &mut SavestateStream<'a>,
     ) -> Result<(), LoadSavestateError> {
         self.interrupt_request.load_savestate(buffer)?;
-        self.interrupt_request.load_savestate(buffer)?;
+        self.interrupt_enable.load_savestate(buffer)?;
         self.interrupt_master_enable = read_savestate_bool(buffer)?;
         Ok(())
     }
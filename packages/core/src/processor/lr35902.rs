@@ -130,6 +130,8 @@ pub trait LR35902: OperandParser {
             }
             Mnemonic::RST(value) => self.rst(bus, value),
             Mnemonic::RET(Some(condition)) => {
+                // the condition is checked in its own M-cycle
+                bus.idle_cycle();
                 if self.operand_condition(condition) {
                     self.ret(bus);
                 }
@@ -160,15 +162,25 @@ pub trait LR35902: OperandParser {
     }
 
     fn ldd<H: Bus>(&mut self, bus: &mut H, reg: Reference, value: ValueType) {
-        let value = self.operand_value(bus, value);
+        let hl = self.reg(RegisterType::HL);
+        let value = self.hl_operand_value(bus, value);
         self.ld(bus, reg, value);
-        self.dec(bus, Reference::Register(RegisterType::HL));
+        self.set_reg(RegisterType::HL, hl.wrapping_sub(1));
     }
 
     fn ldi<H: Bus>(&mut self, bus: &mut H, reg: Reference, value: ValueType) {
-        let value = self.operand_value(bus, value);
+        let hl = self.reg(RegisterType::HL);
+        let value = self.hl_operand_value(bus, value);
         self.ld(bus, reg, value);
-        self.inc(bus, Reference::Register(RegisterType::HL));
+        self.set_reg(RegisterType::HL, hl.wrapping_add(1));
+    }
+
+    // (HL) is read while HL is incremented or decremented
+    fn hl_operand_value<H: Bus>(&mut self, bus: &H, value: ValueType) -> u16 {
+        match value {
+            ValueType::Address(_) => u16::from(bus.read_increment(self.reg(RegisterType::HL))),
+            _ => self.operand_value(bus, value),
+        }
     }
 
     // writes SP + n to HL
@@ -326,6 +338,7 @@ pub trait LR35902: OperandParser {
 
     fn inc16<H: Bus>(&mut self, bus: &mut H, reference: Reference) {
         let value = self.reference(bus, reference) as u16;
+        bus.increment_cycle(value);
         self.set_reference(bus, reference, value.wrapping_add(1));
     }
 
@@ -349,6 +362,7 @@ pub trait LR35902: OperandParser {
 
     fn dec16<H: Bus>(&mut self, bus: &mut H, reference: Reference) {
         let value = self.reference(bus, reference) as u16;
+        bus.increment_cycle(value);
         self.set_reference(bus, reference, value.wrapping_sub(1));
     }
 
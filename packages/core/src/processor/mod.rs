@@ -18,7 +18,8 @@ use crate::processor::operand_parser::OperandParser;
 use crate::processor::registers::program_counter::ProgramCounter;
 use crate::util::bitflags::Bitflags;
 use crate::util::savestate::{
-    read_savestate_bool, read_savestate_byte, LoadSavestateError, Savestate, SavestateStream,
+    read_savestate_bool, read_savestate_byte, read_savestate_enum, LoadSavestateError, Savestate,
+    SavestateStream,
 };
 
 /// This struct contains the logic for the GameBoy's processor
@@ -49,13 +50,23 @@ impl Processor {
 
     /// This method performs a single CPU step and returns the result
     pub fn step<H: Bus>(&mut self, bus: &mut H) -> ProcessorStepResult {
+        bus.start_cpu_cycles();
+        let result = self.run_cycles(bus);
+        bus.end_cpu_cycles();
+        result
+    }
+
+    fn run_cycles<H: Bus>(&mut self, bus: &mut H) -> ProcessorStepResult {
         // check for interrupts
         if let Some(interrupt) = bus.fetch_interrupt() {
             self.halt_mode = HaltMode::None;
             if bus.master_interrupt_enable() {
                 bus.service_interrupt(interrupt);
+                // the dispatch decrements PC, pushes it, then jumps to the handler
                 let pc = self.registers.program_counter.get();
+                bus.increment_cycle(pc);
                 self.push_stack(bus, pc);
+                bus.idle_cycle();
                 self.jp(interrupt.address());
             }
         }
@@ -202,11 +213,7 @@ impl Savestate for Processor {
         buffer: &mut SavestateStream<'a>,
     ) -> Result<(), LoadSavestateError> {
         self.registers.load_savestate(buffer)?;
-        self.halt_mode = buffer
-            .next()
-            .cloned()
-            .and_then(HaltMode::from)
-            .ok_or(LoadSavestateError::InvalidSavestate)?;
+        self.halt_mode = read_savestate_enum(buffer, "HaltMode", HaltMode::from)?;
         self.cycles_left = read_savestate_byte(buffer)?;
         self.pending_ei = read_savestate_bool(buffer)?;
         Ok(())
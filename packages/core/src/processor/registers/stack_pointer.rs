@@ -15,7 +15,9 @@ impl StackPointer {
         bus.read(self.value)
     }
 
+    /// SP is decremented once before the writes, then once for each of them
     pub fn push<H: Bus>(&mut self, bus: &mut H, value: u16) {
+        bus.increment_cycle(self.value);
         self.decrement();
         bus.write(self.value, (value >> 8) as u8);
         self.decrement();
@@ -23,7 +25,8 @@ impl StackPointer {
     }
 
     pub fn pop<H: Bus>(&mut self, bus: &H) -> u16 {
-        let low = u16::from(bus.read(self.value));
+        // only the first read happens while SP is incremented
+        let low = u16::from(bus.read_increment(self.value));
         self.increment();
         let high = u16::from(bus.read(self.value));
         self.increment();
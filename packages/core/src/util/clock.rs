@@ -0,0 +1,63 @@
+use std::cell::Cell;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A source of wall-clock time, used by the cartridge components that keep counting
+/// while the GameBoy is turned off, like the MBC3 real-time clock
+pub trait Clock {
+    /// Returns the number of seconds elapsed since the UNIX epoch
+    fn now(&self) -> u64;
+}
+
+/// Clock returning the time of the host system
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0)
+    }
+}
+
+/// Clock that only moves when told to, making time-dependent behavior deterministic
+#[derive(Default)]
+pub struct FakeClock {
+    time: Cell<u64>,
+}
+
+impl FakeClock {
+    pub fn new(time: u64) -> FakeClock {
+        FakeClock {
+            time: Cell::new(time),
+        }
+    }
+
+    pub fn set(&self, time: u64) {
+        self.time.set(time);
+    }
+
+    pub fn advance(&self, seconds: u64) {
+        self.time.set(self.time.get() + seconds);
+    }
+}
+
+impl Clock for FakeClock {
+    fn now(&self) -> u64 {
+        self.time.get()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fake_clock_advances() {
+        let clock = FakeClock::new(10);
+        clock.advance(5);
+        assert_eq!(clock.now(), 15);
+        clock.set(3);
+        assert_eq!(clock.now(), 3);
+    }
+}
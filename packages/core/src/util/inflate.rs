@@ -0,0 +1,263 @@
+/// Decompresses raw DEFLATE data (RFC 1951), as found in zip and gzip files.
+/// Returns None if the data is corrupted.
+pub fn inflate(data: &[u8]) -> Option<Vec<u8>> {
+    let mut reader = BitReader { data, position: 0 };
+    let mut output = Vec::new();
+    loop {
+        let last_block = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => stored_block(&mut reader, &mut output)?,
+            1 => {
+                let (literals, distances) = fixed_codes();
+                compressed_block(&mut reader, &mut output, &literals, &distances)?
+            }
+            2 => {
+                let (literals, distances) = dynamic_codes(&mut reader)?;
+                compressed_block(&mut reader, &mut output, &literals, &distances)?
+            }
+            _ => return None,
+        }
+
+        if last_block {
+            return Some(output);
+        }
+    }
+}
+
+const LENGTH_BASES: [usize; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA_BITS: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASES: [usize; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA_BITS: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+/// Order in which the lengths of the code length code are stored in dynamic blocks
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+const END_OF_BLOCK: u16 = 256;
+const MAX_CODE_LENGTH: usize = 15;
+
+/// Reads the bits of the data from the least significant one
+struct BitReader<'a> {
+    data: &'a [u8],
+    /// Position in bits
+    position: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn bits(&mut self, count: u8) -> Option<usize> {
+        let mut value = 0;
+        for i in 0..count {
+            let byte = self.data.get(self.position / 8)?;
+            let bit = (byte >> (self.position % 8)) & 1;
+            value |= usize::from(bit) << i;
+            self.position += 1;
+        }
+        Some(value)
+    }
+
+    fn align_to_byte(&mut self) {
+        self.position = (self.position + 7) & !7;
+    }
+}
+
+/// Canonical Huffman code, decoded by counting the codes of each length
+struct Huffman {
+    /// Number of codes of each length
+    counts: [u16; MAX_CODE_LENGTH + 1],
+    /// Symbols ordered by code
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    /// Builds the code from the length of the code of each symbol, 0 for unused symbols.
+    /// Returns None if there are more codes than the lengths allow.
+    fn new(lengths: &[u8]) -> Option<Huffman> {
+        let mut counts = [0; MAX_CODE_LENGTH + 1];
+        for &length in lengths {
+            counts[usize::from(length)] += 1;
+        }
+        counts[0] = 0;
+
+        let mut left: i32 = 1;
+        for &count in counts[1..].iter() {
+            left = left * 2 - i32::from(count);
+            if left < 0 {
+                return None;
+            }
+        }
+
+        let mut offsets = [0; MAX_CODE_LENGTH + 1];
+        for length in 1..MAX_CODE_LENGTH {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                let offset = &mut offsets[usize::from(length)];
+                symbols[usize::from(*offset)] = symbol as u16;
+                *offset += 1;
+            }
+        }
+
+        Some(Huffman { counts, symbols })
+    }
+
+    fn decode(&self, reader: &mut BitReader<'_>) -> Option<u16> {
+        // first code and index of the symbols of the current length
+        let mut code: i32 = 0;
+        let mut first: i32 = 0;
+        let mut index: i32 = 0;
+        for &count in self.counts[1..].iter() {
+            code |= reader.bits(1)? as i32;
+            let count = i32::from(count);
+            if code - first < count {
+                return self.symbols.get((index + code - first) as usize).cloned();
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        None
+    }
+}
+
+fn stored_block(reader: &mut BitReader<'_>, output: &mut Vec<u8>) -> Option<()> {
+    reader.align_to_byte();
+    let length = reader.bits(16)?;
+    let complement = reader.bits(16)?;
+    if length != !complement & 0xFFFF {
+        return None;
+    }
+
+    let start = reader.position / 8;
+    output.extend_from_slice(reader.data.get(start..start + length)?);
+    reader.position += length * 8;
+    Some(())
+}
+
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [0; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    let literals = Huffman::new(&lengths).unwrap();
+    let distances = Huffman::new(&[5; 30]).unwrap();
+    (literals, distances)
+}
+
+fn dynamic_codes(reader: &mut BitReader<'_>) -> Option<(Huffman, Huffman)> {
+    let literal_count = reader.bits(5)? + 257;
+    let distance_count = reader.bits(5)? + 1;
+    let code_length_count = reader.bits(4)? + 4;
+
+    let mut code_length_lengths = [0; 19];
+    for &symbol in CODE_LENGTH_ORDER[..code_length_count].iter() {
+        code_length_lengths[symbol] = reader.bits(3)? as u8;
+    }
+    let code_lengths = Huffman::new(&code_length_lengths)?;
+
+    // the lengths of both codes follow each other, repeats can cross from one to the other
+    let mut lengths = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let (length, repeat) = match code_lengths.decode(reader)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => (*lengths.last()?, reader.bits(2)? + 3),
+            17 => (0, reader.bits(3)? + 3),
+            _ => (0, reader.bits(7)? + 11),
+        };
+        if lengths.len() + repeat > literal_count + distance_count {
+            return None;
+        }
+        lengths.resize(lengths.len() + repeat, length);
+    }
+
+    let literals = Huffman::new(&lengths[..literal_count])?;
+    let distances = Huffman::new(&lengths[literal_count..])?;
+    Some((literals, distances))
+}
+
+fn compressed_block(
+    reader: &mut BitReader<'_>,
+    output: &mut Vec<u8>,
+    literals: &Huffman,
+    distances: &Huffman,
+) -> Option<()> {
+    loop {
+        let symbol = literals.decode(reader)?;
+        if symbol < END_OF_BLOCK {
+            output.push(symbol as u8);
+            continue;
+        } else if symbol == END_OF_BLOCK {
+            return Some(());
+        }
+
+        // copy of previous output
+        let index = usize::from(symbol - END_OF_BLOCK - 1);
+        let length = LENGTH_BASES.get(index)? + reader.bits(LENGTH_EXTRA_BITS[index])?;
+        let index = usize::from(distances.decode(reader)?);
+        let distance = DISTANCE_BASES.get(index)? + reader.bits(DISTANCE_EXTRA_BITS[index])?;
+        let start = output.len().checked_sub(distance)?;
+        for i in start..start + length {
+            output.push(output[i]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stored() {
+        assert_eq!(
+            inflate(&[0x01, 0x03, 0x00, 0xFC, 0xFF, b'a', b'b', b'c']),
+            Some(b"abc".to_vec())
+        );
+        assert_eq!(inflate(&[0x01, 0x03, 0x00, 0xFC, 0xFE, b'a']), None);
+    }
+
+    #[test]
+    fn fixed_huffman() {
+        let data = [
+            0x2B, 0x2A, 0x2D, 0x2E, 0xA9, 0x4C, 0xCA, 0xAF, 0x54, 0x28, 0x42, 0x67, 0x28, 0x02,
+            0x00,
+        ];
+        assert_eq!(
+            inflate(&data),
+            Some(b"rustyboy rustyboy rustyboy!".to_vec())
+        );
+    }
+
+    #[test]
+    fn dynamic_huffman() {
+        let data = [
+            0x05, 0xC1, 0x81, 0x0D, 0x00, 0x10, 0x0C, 0x00, 0xC1, 0xD9, 0x1E, 0x55, 0x55, 0x8D,
+            0x88, 0x88, 0xD8, 0x7F, 0x11, 0x77, 0xB4, 0x4C, 0xDE, 0xB8, 0x51, 0x1F, 0x4C, 0x4C,
+            0x28, 0x87, 0xE1, 0x28, 0xA4, 0x45, 0x57, 0xE4, 0x12, 0xC1, 0x07,
+        ];
+        let expected: Vec<u8> = (0..40)
+            .map(|i| ((i * 7 % 13) * (i % 3) + 0x41) as u8)
+            .collect();
+        assert_eq!(inflate(&data), Some(expected));
+    }
+
+    #[test]
+    fn corrupted() {
+        assert_eq!(inflate(&[]), None);
+        // reserved block type
+        assert_eq!(inflate(&[0x07]), None);
+        // copy from before the start of the output
+        assert_eq!(inflate(&[0x03, 0x02]), None);
+    }
+}
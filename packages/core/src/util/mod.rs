@@ -3,9 +3,13 @@ use std::string::FromUtf8Error;
 pub mod bitflags;
 pub mod bits;
 pub mod bytes_convert;
+pub mod clock;
+pub mod crc32;
 pub mod drawer;
+pub mod inflate;
 pub mod parse_hex;
 pub mod savestate;
+pub mod sha1;
 #[cfg(test)]
 pub mod tests;
 
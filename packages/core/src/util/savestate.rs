@@ -1,9 +1,74 @@
+use crate::cartridge::cartridge_metadata::CartridgeMetadata;
+use crate::hardware::joypad::Joypad;
+use crate::video::screen::BUFFER_SIZE;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
 pub type SavestateStream<'a> = std::slice::Iter<'a, u8>;
 
+#[derive(Debug, Clone, PartialEq)]
 pub enum LoadSavestateError {
-    InvalidSavestate,
+    /// The data doesn't start with a rustyboy savestate header.
+    /// Savestates made before the format had a header can't be loaded anymore.
+    InvalidHeader,
+    /// The savestate was made with a newer version of the format, or an old one which
+    /// can't be migrated
+    UnsupportedVersion(u16),
+    /// The savestate was made with another ROM, whose title is given
+    WrongRom(String),
+    /// A section needed to load the savestate is missing
+    MissingSection(SavestateSection),
+    /// The data ended before everything could be loaded
+    Truncated,
+    /// A value doesn't correspond to any variant of the given enum
+    InvalidValue(&'static str, u8),
+    /// The savestate contains the state of a component that the cartridge doesn't have
+    MissingComponent(&'static str),
+    /// An error occurred while loading the section of a component
+    Section(SavestateSection, Box<LoadSavestateError>),
+}
+
+impl fmt::Display for LoadSavestateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadSavestateError::InvalidHeader => write!(
+                f,
+                "not a rustyboy savestate, or made by a version of rustyboy too old to be loaded"
+            ),
+            LoadSavestateError::UnsupportedVersion(0) => {
+                write!(
+                    f,
+                    "savestates made before version 1 are no longer supported"
+                )
+            }
+            LoadSavestateError::UnsupportedVersion(version) => write!(
+                f,
+                "savestate version {} is newer than the supported version {}",
+                version, SAVESTATE_VERSION
+            ),
+            LoadSavestateError::WrongRom(title) => {
+                write!(f, "savestate was made with another ROM ({:?})", title)
+            }
+            LoadSavestateError::MissingSection(section) => {
+                write!(f, "missing {:?} section", section)
+            }
+            LoadSavestateError::Truncated => write!(f, "data is truncated"),
+            LoadSavestateError::InvalidValue(name, value) => {
+                write!(f, "invalid {} value {}", name, value)
+            }
+            LoadSavestateError::MissingComponent(name) => {
+                write!(f, "the cartridge has no {}", name)
+            }
+            LoadSavestateError::Section(section, error) => {
+                write!(f, "{:?} section: {}", section, error)
+            }
+        }
+    }
 }
 
+impl Error for LoadSavestateError {}
+
 pub trait Savestate {
     fn dump_savestate(&self, buffer: &mut Vec<u8>);
     fn load_savestate<'a>(
@@ -15,10 +80,18 @@ pub trait Savestate {
 pub fn read_savestate_byte<'a>(
     buffer: &mut impl Iterator<Item = &'a u8>,
 ) -> Result<u8, LoadSavestateError> {
-    buffer
-        .next()
-        .cloned()
-        .ok_or(LoadSavestateError::InvalidSavestate)
+    buffer.next().cloned().ok_or(LoadSavestateError::Truncated)
+}
+
+/// Reads a byte and converts it to an enum variant with the given function,
+/// failing with the name of the enum if it doesn't correspond to any variant
+pub fn read_savestate_enum<'a, T>(
+    buffer: &mut impl Iterator<Item = &'a u8>,
+    name: &'static str,
+    from: impl FnOnce(u8) -> Option<T>,
+) -> Result<T, LoadSavestateError> {
+    let value = read_savestate_byte(buffer)?;
+    from(value).ok_or(LoadSavestateError::InvalidValue(name, value))
 }
 
 pub fn read_savestate_bool<'a>(
@@ -37,3 +110,371 @@ pub fn write_savestate_u16(buffer: &mut Vec<u8>, value: u16) {
     buffer.push(value as u8);
     buffer.push((value >> 8) as u8);
 }
+
+pub fn read_savestate_u32<'a>(
+    buffer: &mut impl Iterator<Item = &'a u8>,
+) -> Result<u32, LoadSavestateError> {
+    let low = u32::from(read_savestate_u16(buffer)?);
+    let high = u32::from(read_savestate_u16(buffer)?);
+    Ok(low | (high << 16))
+}
+
+pub fn write_savestate_u32(buffer: &mut Vec<u8>, value: u32) {
+    write_savestate_u16(buffer, value as u16);
+    write_savestate_u16(buffer, (value >> 16) as u16);
+}
+
+/// Identifies a rustyboy savestate, written at the very beginning of the file
+pub const SAVESTATE_MAGIC: &[u8; 4] = b"RBSS";
+
+/// The current version of the savestate format.
+/// It should be incremented, and a migration added to `MIGRATIONS`,
+/// every time the content of a section changes.
+pub const SAVESTATE_VERSION: u16 = 3;
+
+/// Functions that upgrade the sections of a savestate made with an older version of the format.
+/// The migration at index `i` upgrades a savestate from version `i + 1` to version `i + 2`.
+const MIGRATIONS: &[fn(&mut SavestateSections)] = &[migrate_v1, migrate_v2];
+
+/// Version 2 added the joypad and real-time clock sections, the current state of
+/// the position registers and the screen buffer
+fn migrate_v1(sections: &mut SavestateSections) {
+    let mut joypad = Vec::new();
+    Joypad::default().dump_savestate(&mut joypad);
+    sections.insert(SavestateSection::Joypad, joypad);
+    sections.insert(SavestateSection::Clock, vec![0]);
+
+    if let Some(video) = sections.section_mut(SavestateSection::Video) {
+        // control, status and mode registers followed by the position registers
+        if video.len() >= 9 {
+            let position_registers: Vec<u8> = video[3..9].to_vec();
+            video.splice(9..9, position_registers);
+        }
+        video.extend_from_slice(&[0; BUFFER_SIZE]);
+    }
+}
+
+/// Version 3 stores the real-time clock counters as their register values,
+/// and adds the running counters after the latched ones
+fn migrate_v2(sections: &mut SavestateSections) {
+    if let Some(clock) = sections.section_mut(SavestateSection::Clock) {
+        // presence flag, active register and prelatch followed by the latched counters,
+        // with the day counter stored as a u16
+        if clock.len() == 8 && clock[0] == 1 {
+            clock[7] &= 1;
+            let counters: Vec<u8> = clock[3..8].to_vec();
+            clock.extend(counters);
+        }
+    }
+}
+
+/// The header of a savestate, used to make sure that it was made
+/// with a compatible version of the format and with the same ROM.
+#[derive(Debug, PartialEq)]
+pub struct SavestateHeader {
+    pub version: u16,
+    pub rom_title: String,
+    pub rom_checksum: u8,
+}
+
+impl SavestateHeader {
+    pub fn new(metadata: &CartridgeMetadata) -> Self {
+        Self {
+            version: SAVESTATE_VERSION,
+            rom_title: metadata.title.clone(),
+            rom_checksum: metadata.header_checksum,
+        }
+    }
+
+    /// Returns whether the savestate was made with the given ROM
+    pub fn matches(&self, metadata: &CartridgeMetadata) -> bool {
+        self.rom_title == metadata.title && self.rom_checksum == metadata.header_checksum
+    }
+
+    pub fn dump(&self, buffer: &mut Vec<u8>) {
+        buffer.extend_from_slice(SAVESTATE_MAGIC);
+        write_savestate_u16(buffer, self.version);
+        buffer.push(self.rom_checksum);
+        buffer.push(self.rom_title.len() as u8);
+        buffer.extend_from_slice(self.rom_title.as_bytes());
+    }
+
+    pub fn load(buffer: &mut SavestateStream<'_>) -> Result<Self, LoadSavestateError> {
+        let magic: Vec<u8> = buffer.take(SAVESTATE_MAGIC.len()).cloned().collect();
+        if magic != SAVESTATE_MAGIC {
+            return Err(LoadSavestateError::InvalidHeader);
+        }
+
+        let version = read_savestate_u16(buffer)?;
+        let rom_checksum = read_savestate_byte(buffer)?;
+        let title_length = read_savestate_byte(buffer)? as usize;
+        let title: Vec<u8> = buffer.take(title_length).cloned().collect();
+        if title.len() != title_length {
+            return Err(LoadSavestateError::Truncated);
+        }
+
+        Ok(Self {
+            version,
+            rom_title: String::from_utf8(title).map_err(|_| LoadSavestateError::InvalidHeader)?,
+            rom_checksum,
+        })
+    }
+}
+
+/// Represents a section of a savestate, each containing the state of a single component
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum SavestateSection {
+    Processor = 0,
+    Cartridge = 1,
+    Interrupts = 2,
+    Timer = 3,
+    Video = 4,
+    Memory = 5,
+    Joypad = 6,
+    Clock = 7,
+}
+
+impl SavestateSection {
+    pub fn from(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(SavestateSection::Processor),
+            1 => Some(SavestateSection::Cartridge),
+            2 => Some(SavestateSection::Interrupts),
+            3 => Some(SavestateSection::Timer),
+            4 => Some(SavestateSection::Video),
+            5 => Some(SavestateSection::Memory),
+            6 => Some(SavestateSection::Joypad),
+            7 => Some(SavestateSection::Clock),
+            _ => None,
+        }
+    }
+}
+
+/// Writes a section to the savestate, prefixed by its identifier and its length
+pub fn write_savestate_section(
+    buffer: &mut Vec<u8>,
+    section: SavestateSection,
+    dump: impl FnOnce(&mut Vec<u8>),
+) {
+    let mut content = Vec::new();
+    dump(&mut content);
+    buffer.push(section as u8);
+    write_savestate_u32(buffer, content.len() as u32);
+    buffer.append(&mut content);
+}
+
+/// Contains the sections of a savestate, indexed by the component they belong to.
+/// Sections unknown to this version of the format are ignored.
+#[derive(Default)]
+pub struct SavestateSections {
+    sections: HashMap<SavestateSection, Vec<u8>>,
+}
+
+impl SavestateSections {
+    pub fn read(buffer: &mut SavestateStream<'_>) -> Result<Self, LoadSavestateError> {
+        let mut sections = HashMap::new();
+        while let Some(&id) = buffer.next() {
+            let length = read_savestate_u32(buffer)? as usize;
+            let content: Vec<u8> = buffer.take(length).cloned().collect();
+            if content.len() != length {
+                return Err(LoadSavestateError::Truncated);
+            }
+
+            if let Some(section) = SavestateSection::from(id) {
+                sections.insert(section, content);
+            }
+        }
+
+        Ok(Self { sections })
+    }
+
+    /// Upgrades sections written with an older version of the format to the current one
+    pub fn migrate(&mut self, version: u16) -> Result<(), LoadSavestateError> {
+        if version == 0 || version > SAVESTATE_VERSION {
+            return Err(LoadSavestateError::UnsupportedVersion(version));
+        }
+
+        for migration in MIGRATIONS.iter().skip(version as usize - 1) {
+            migration(self);
+        }
+
+        Ok(())
+    }
+
+    pub fn section(&self, section: SavestateSection) -> Option<&Vec<u8>> {
+        self.sections.get(&section)
+    }
+
+    pub fn section_mut(&mut self, section: SavestateSection) -> Option<&mut Vec<u8>> {
+        self.sections.get_mut(&section)
+    }
+
+    pub fn insert(&mut self, section: SavestateSection, content: Vec<u8>) {
+        self.sections.insert(section, content);
+    }
+
+    /// Loads a section using the given function.
+    /// Errors are wrapped with the section they occurred in.
+    pub fn load<T>(
+        &self,
+        section: SavestateSection,
+        load: impl FnOnce(&mut SavestateStream<'_>) -> Result<T, LoadSavestateError>,
+    ) -> Result<T, LoadSavestateError> {
+        let content = self
+            .section(section)
+            .ok_or(LoadSavestateError::MissingSection(section))?;
+        load(&mut content.iter())
+            .map_err(|error| LoadSavestateError::Section(section, Box::new(error)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sections_round_trip() {
+        let mut buffer = Vec::new();
+        write_savestate_section(&mut buffer, SavestateSection::Timer, |buffer| {
+            buffer.extend_from_slice(&[1, 2, 3])
+        });
+        write_savestate_section(&mut buffer, SavestateSection::Video, |buffer| {
+            buffer.push(4)
+        });
+
+        let sections = SavestateSections::read(&mut buffer.iter()).ok().unwrap();
+        assert_eq!(
+            sections.section(SavestateSection::Timer),
+            Some(&vec![1, 2, 3])
+        );
+        assert_eq!(sections.section(SavestateSection::Video), Some(&vec![4]));
+        assert_eq!(sections.section(SavestateSection::Processor), None);
+    }
+
+    #[test]
+    fn unknown_sections_are_ignored() {
+        let mut buffer = vec![0xFF];
+        write_savestate_u32(&mut buffer, 2);
+        buffer.extend_from_slice(&[1, 2]);
+        write_savestate_section(&mut buffer, SavestateSection::Memory, |buffer| {
+            buffer.push(3)
+        });
+
+        let sections = SavestateSections::read(&mut buffer.iter()).ok().unwrap();
+        assert_eq!(sections.section(SavestateSection::Memory), Some(&vec![3]));
+    }
+
+    #[test]
+    fn truncated_section() {
+        let mut buffer = Vec::new();
+        write_savestate_section(&mut buffer, SavestateSection::Timer, |buffer| {
+            buffer.extend_from_slice(&[1, 2, 3])
+        });
+        buffer.pop();
+
+        assert_eq!(
+            SavestateSections::read(&mut buffer.iter()).err(),
+            Some(LoadSavestateError::Truncated)
+        );
+    }
+
+    #[test]
+    fn section_errors_are_wrapped() {
+        let mut sections = SavestateSections::default();
+        sections.insert(SavestateSection::Timer, vec![]);
+
+        assert_eq!(
+            sections.load(SavestateSection::Timer, |buffer| {
+                read_savestate_byte(buffer).map(|_| ())
+            }),
+            Err(LoadSavestateError::Section(
+                SavestateSection::Timer,
+                Box::new(LoadSavestateError::Truncated)
+            ))
+        );
+        assert_eq!(
+            sections.load(SavestateSection::Video, |_| Ok(())),
+            Err(LoadSavestateError::MissingSection(SavestateSection::Video))
+        );
+    }
+
+    #[test]
+    fn invalid_enum_value() {
+        let buffer = [7];
+        assert_eq!(
+            read_savestate_enum(&mut buffer.iter(), "Test", |_| None::<u8>),
+            Err(LoadSavestateError::InvalidValue("Test", 7))
+        );
+    }
+
+    #[test]
+    fn header_round_trip() {
+        let header = SavestateHeader {
+            version: SAVESTATE_VERSION,
+            rom_title: String::from("TETRIS"),
+            rom_checksum: 0x0A,
+        };
+        let mut buffer = Vec::new();
+        header.dump(&mut buffer);
+
+        let loaded = SavestateHeader::load(&mut buffer.iter()).ok().unwrap();
+        assert_eq!(loaded, header);
+    }
+
+    #[test]
+    fn header_invalid_magic() {
+        let buffer = [0, 1, 2, 3, 1, 0];
+        assert_eq!(
+            SavestateHeader::load(&mut buffer.iter()).err(),
+            Some(LoadSavestateError::InvalidHeader)
+        );
+    }
+
+    #[test]
+    fn migrate_v1_adds_sections() {
+        let mut sections = SavestateSections::default();
+        sections.insert(SavestateSection::Video, (0..12).collect());
+        assert!(sections.migrate(1).is_ok());
+
+        assert!(sections.section(SavestateSection::Joypad).is_some());
+        assert_eq!(sections.section(SavestateSection::Clock), Some(&vec![0]));
+
+        let video = sections.section(SavestateSection::Video).unwrap();
+        assert_eq!(video.len(), 18 + BUFFER_SIZE);
+        assert_eq!(video[3..9], video[9..15]);
+    }
+
+    #[test]
+    fn migrate_v2_adds_running_clock() {
+        let mut sections = SavestateSections::default();
+        sections.insert(SavestateSection::Clock, vec![1, 8, 0, 1, 2, 3, 4, 1]);
+        assert!(sections.migrate(2).is_ok());
+        assert_eq!(
+            sections.section(SavestateSection::Clock),
+            Some(&vec![1, 8, 0, 1, 2, 3, 4, 1, 1, 2, 3, 4, 1])
+        );
+    }
+
+    #[test]
+    fn migrate_unsupported_version() {
+        let mut sections = SavestateSections::default();
+        let error = sections.migrate(0).err().unwrap();
+        assert_eq!(error, LoadSavestateError::UnsupportedVersion(0));
+        assert_eq!(
+            error.to_string(),
+            "savestates made before version 1 are no longer supported"
+        );
+    }
+
+    #[test]
+    fn migrate_future_version() {
+        let mut sections = SavestateSections::default();
+        assert_eq!(
+            sections.migrate(SAVESTATE_VERSION + 1),
+            Err(LoadSavestateError::UnsupportedVersion(
+                SAVESTATE_VERSION + 1
+            ))
+        );
+        assert!(sections.migrate(SAVESTATE_VERSION).is_ok());
+    }
+}
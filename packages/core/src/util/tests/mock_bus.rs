@@ -28,6 +28,13 @@ impl Bus for MockBus {
     fn master_interrupt_enable(&self) -> bool {
         true
     }
+    fn start_cpu_cycles(&mut self) {}
+    fn increment_cycle(&self, _: u16) {}
+    fn idle_cycle(&self) {}
+    fn read_increment(&self, address: u16) -> u8 {
+        self.read(address)
+    }
+    fn end_cpu_cycles(&mut self) {}
 }
 
 impl Readable for MockBus {
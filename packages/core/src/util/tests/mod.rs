@@ -1 +1,2 @@
 pub mod mock_bus;
+pub mod test_rom;
@@ -0,0 +1,29 @@
+/// Builds a 32KB ROM-only cartridge with the given title and a valid header checksum.
+/// The rest of the ROM is filled with NOPs.
+pub fn test_rom(title: &str) -> Vec<u8> {
+    test_rom_with_program(title, &[])
+}
+
+/// Builds a test ROM that runs the given program from the entry point (0x100)
+pub fn test_rom_with_program(title: &str, program: &[u8]) -> Vec<u8> {
+    let mut buffer = vec![0; 0x8000];
+    buffer[0x0100..0x0100 + program.len()].copy_from_slice(program);
+    buffer[0x0134..0x0134 + title.len()].copy_from_slice(title.as_bytes());
+    update_header_checksum(&mut buffer);
+    buffer
+}
+
+/// Builds a test ROM with the given cartridge type and RAM size header values
+pub fn test_rom_with_type(title: &str, cartridge_type: u8, ram_size: u8) -> Vec<u8> {
+    let mut buffer = test_rom(title);
+    buffer[0x0147] = cartridge_type;
+    buffer[0x0149] = ram_size;
+    update_header_checksum(&mut buffer);
+    buffer
+}
+
+fn update_header_checksum(buffer: &mut [u8]) {
+    buffer[0x014D] = buffer[0x0134..=0x014C]
+        .iter()
+        .fold(0u8, |x, &value| x.wrapping_sub(value).wrapping_sub(1));
+}
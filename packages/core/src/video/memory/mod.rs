@@ -5,6 +5,7 @@ use self::sprite_attribute_table::SpriteAttributeTable;
 use crate::bus::{Readable, Writable};
 use crate::util::savestate::{LoadSavestateError, Savestate};
 use crate::video::tile::Tile;
+use crate::video::OAMCorruption;
 
 #[derive(Clone)]
 pub struct VideoMemory {
@@ -32,6 +33,10 @@ impl VideoMemory {
         &self.background_tile_maps
     }
 
+    pub fn corrupt_oam(&mut self, row: usize, corruption: OAMCorruption) {
+        self.oam.corrupt(row, corruption);
+    }
+
     fn tile_idx_at(&self, address: u16) -> (u16, u16, u8, u8) {
         let tile_address = address.saturating_sub(0x8000);
         let tile_base_address = (tile_address - tile_address % 16) / 16;
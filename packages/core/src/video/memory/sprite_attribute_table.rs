@@ -1,6 +1,10 @@
 use crate::bus::{Readable, Writable};
 use crate::util::bits::get_bit;
 use crate::util::savestate::{read_savestate_byte, LoadSavestateError, Savestate};
+use crate::video::OAMCorruption;
+
+/// OAM is accessed by the PPU in rows of 8 bytes (2 entries)
+const OAM_ROW_COUNT: usize = 20;
 
 #[derive(Clone)]
 pub struct SpriteAttributeTable {
@@ -22,6 +26,60 @@ impl SpriteAttributeTable {
     pub fn entries(&self) -> &[OAMEntry; 40] {
         &self.table
     }
+
+    /// Corrupts the given row following the patterns of the DMG's OAM corruption bug.
+    /// The first row is never affected.
+    pub fn corrupt(&mut self, row: usize, corruption: OAMCorruption) {
+        if row == 0 || row >= OAM_ROW_COUNT {
+            return;
+        }
+
+        if corruption == OAMCorruption::ReadDuringIncrease {
+            if (4..OAM_ROW_COUNT - 1).contains(&row) {
+                let a = self.word(row - 2, 0);
+                let b = self.word(row - 1, 0);
+                let c = self.word(row, 0);
+                let d = self.word(row - 2, 2);
+                self.set_word(row - 1, 0, (b & (a | c | d)) | (a & c & d));
+                self.copy_row(row - 1, row);
+                self.copy_row(row - 1, row - 2);
+            }
+
+            self.corrupt(row, OAMCorruption::Read);
+            return;
+        }
+
+        let a = self.word(row, 0);
+        let b = self.word(row - 1, 0);
+        let c = self.word(row - 1, 2);
+        let value = match corruption {
+            OAMCorruption::Read => b | (a & c),
+            _ => ((a ^ c) & (b ^ c)) ^ c,
+        };
+        self.set_word(row, 0, value);
+        for word in 1..4 {
+            let value = self.word(row - 1, word);
+            self.set_word(row, word, value);
+        }
+    }
+
+    fn word(&self, row: usize, word: usize) -> u16 {
+        let address = (0xFE00 + row * 8 + word * 2) as u16;
+        u16::from(self.read(address)) | (u16::from(self.read(address + 1)) << 8)
+    }
+
+    fn set_word(&mut self, row: usize, word: usize, value: u16) {
+        let address = (0xFE00 + row * 8 + word * 2) as u16;
+        self.write(address, value as u8);
+        self.write(address + 1, (value >> 8) as u8);
+    }
+
+    fn copy_row(&mut self, from: usize, to: usize) {
+        for word in 0..4 {
+            let value = self.word(from, word);
+            self.set_word(to, word, value);
+        }
+    }
 }
 
 impl Default for SpriteAttributeTable {
@@ -147,3 +205,61 @@ impl Savestate for OAMEntry {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table_with_rows() -> SpriteAttributeTable {
+        let mut table = SpriteAttributeTable::new();
+        for address in 0xFE00..0xFEA0u16 {
+            table.write(address, (address - 0xFE00) as u8);
+        }
+        table
+    }
+
+    #[test]
+    fn write_corruption() {
+        let mut table = table_with_rows();
+        table.corrupt(2, OAMCorruption::Write);
+
+        let (a, b, c) = (0x1110, 0x0908, 0x0D0C);
+        assert_eq!(table.word(2, 0), ((a ^ c) & (b ^ c)) ^ c);
+        for word in 1..4 {
+            assert_eq!(table.word(2, word), table.word(1, word));
+        }
+    }
+
+    #[test]
+    fn read_corruption() {
+        let mut table = table_with_rows();
+        table.corrupt(2, OAMCorruption::Read);
+
+        let (a, b, c) = (0x1110, 0x0908, 0x0D0C);
+        assert_eq!(table.word(2, 0), b | (a & c));
+        for word in 1..4 {
+            assert_eq!(table.word(2, word), table.word(1, word));
+        }
+    }
+
+    #[test]
+    fn first_row_is_not_corrupted() {
+        let mut table = table_with_rows();
+        table.corrupt(0, OAMCorruption::Write);
+        assert_eq!(table.word(0, 0), 0x0100);
+        assert_eq!(table.word(0, 3), 0x0706);
+    }
+
+    #[test]
+    fn read_during_increase_corruption() {
+        let mut table = table_with_rows();
+        table.corrupt(5, OAMCorruption::ReadDuringIncrease);
+
+        let (a, b, c, d) = (0x1918, 0x2120, 0x2928, 0x1D1C);
+        let corrupted = (b & (a | c | d)) | (a & c & d);
+        assert_eq!(table.word(4, 0), corrupted);
+        assert_eq!(table.word(3, 0), corrupted);
+        assert_eq!(table.word(5, 0), corrupted | (corrupted & table.word(4, 2)));
+        assert_eq!(table.word(5, 2), table.word(4, 2));
+    }
+}
@@ -15,12 +15,32 @@ use self::status_register::{StatusMode, StatusRegister};
 use crate::bus::{Readable, Writable};
 use crate::processor::interrupt::{Interrupt, InterruptHandler};
 use crate::util::savestate::{
-    read_savestate_byte, read_savestate_u16, write_savestate_u16, LoadSavestateError, Savestate,
+    read_savestate_byte, read_savestate_enum, read_savestate_u16, write_savestate_u16,
+    LoadSavestateError, Savestate,
 };
 use crate::video::debugging::VideoDebugInformation;
 use crate::video::palette::Palette;
 use crate::video::screen::{Screen, VideoInformation};
 
+/// Represents the kind of access that triggered the OAM corruption bug.
+///
+/// On the DMG, when the CPU puts an address in the 0xFE00..=0xFEFF range on the bus
+/// while the PPU is reading OAM (mode 2), the row of OAM being read by the PPU gets corrupted.
+/// This happens with reads, writes and 16-bit increments and decrements
+/// (`INC rr`, `DEC rr`, `PUSH`, `POP`, etc.), in the M-cycle where the address is on the bus.
+///
+/// https://gbdev.io/pandocs/OAM_Corruption_Bug.html
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum OAMCorruption {
+    /// Triggered by a write or a 16-bit increment/decrement
+    Write,
+    /// Triggered by a read
+    Read,
+    /// Triggered by a read and an increment/decrement of the same register in the same cycle,
+    /// like `LD A,(HL+)` or `POP`
+    ReadDuringIncrease,
+}
+
 pub struct Video {
     control: ControlRegister,
     status: StatusRegister,
@@ -118,6 +138,35 @@ impl Video {
         }
     }
 
+    /// Corrupts the row of OAM read by the PPU `delay` cycles from now, if it is reading OAM then.
+    /// The delay times the accesses made later in the instruction the CPU is executing.
+    pub fn trigger_oam_bug(&mut self, corruption: OAMCorruption, delay: u16) {
+        if !self.control.lcd_enabled() {
+            return;
+        }
+
+        let cycle = match self.mode {
+            StatusMode::ReadingOAM => {
+                self.mode_cycle_length().saturating_sub(self.cycles_left) + delay
+            }
+            // a line starts by reading OAM, unless it's the first line of VBlank
+            StatusMode::HBlank if self.position_registers.ly() < 143 => {
+                match delay.checked_sub(self.cycles_left) {
+                    Some(cycle) => cycle,
+                    None => return,
+                }
+            }
+            StatusMode::VBlank => match delay.checked_sub(self.cycles_left) {
+                Some(cycle) => cycle,
+                None => return,
+            },
+            _ => return,
+        };
+
+        // the PPU reads a row of 8 bytes every 4 cycles, rows past the end aren't corrupted
+        self.vram.corrupt_oam(usize::from(cycle / 4), corruption);
+    }
+
     fn set_mode(&mut self, mode: StatusMode, interrupt_handler: &mut InterruptHandler) {
         self.mode = mode;
         match mode {
@@ -262,6 +311,7 @@ impl Savestate for Video {
         self.obj_palette1.dump_savestate(buffer);
         self.vram.dump_savestate(buffer);
         write_savestate_u16(buffer, self.cycles_left);
+        self.screen.buffer.dump_savestate(buffer);
     }
 
     fn load_savestate<'a>(
@@ -270,17 +320,14 @@ impl Savestate for Video {
     ) -> Result<(), LoadSavestateError> {
         self.control.register = read_savestate_byte(buffer)?;
         self.status.register = read_savestate_byte(buffer)?;
-        self.mode = buffer
-            .next()
-            .cloned()
-            .and_then(StatusMode::from)
-            .ok_or(LoadSavestateError::InvalidSavestate)?;
+        self.mode = read_savestate_enum(buffer, "StatusMode", StatusMode::from)?;
         self.position_registers.load_savestate(buffer)?;
         self.bg_palette.load_savestate(buffer)?;
         self.obj_palette0.load_savestate(buffer)?;
         self.obj_palette1.load_savestate(buffer)?;
         self.vram.load_savestate(buffer)?;
         self.cycles_left = read_savestate_u16(buffer)?;
+        self.screen.buffer.load_savestate(buffer)?;
 
         Ok(())
     }
@@ -104,6 +104,7 @@ impl PositionRegisters {
 impl Savestate for PositionRegisters {
     fn dump_savestate(&self, buffer: &mut Vec<u8>) {
         self.state.next_state.dump_savestate(buffer);
+        self.state.state.dump_savestate(buffer);
     }
 
     fn load_savestate<'a>(
@@ -111,7 +112,7 @@ impl Savestate for PositionRegisters {
         buffer: &mut std::slice::Iter<'a, u8>,
     ) -> Result<(), LoadSavestateError> {
         self.state.next_state.load_savestate(buffer)?;
-        self.state.apply_next_state();
+        self.state.state.load_savestate(buffer)?;
         Ok(())
     }
 }
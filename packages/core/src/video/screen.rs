@@ -1,4 +1,5 @@
 use crate::util::drawer::{apply_option_buffer, DrawnColor};
+use crate::util::savestate::{read_savestate_byte, LoadSavestateError, Savestate};
 use crate::util::wrap_value;
 use crate::video::color::Color;
 use crate::video::control_register::ControlRegister;
@@ -275,6 +276,27 @@ impl Default for ScreenBuffer {
     }
 }
 
+impl Savestate for ScreenBuffer {
+    fn dump_savestate(&self, buffer: &mut Vec<u8>) {
+        buffer.extend(self.buffer.iter().map(|&color| color as u8));
+    }
+
+    fn load_savestate<'a>(
+        &mut self,
+        buffer: &mut std::slice::Iter<'a, u8>,
+    ) -> Result<(), LoadSavestateError> {
+        for color in self.buffer.iter_mut() {
+            let value = read_savestate_byte(buffer)?;
+            if value > Color::Black as u8 {
+                return Err(LoadSavestateError::InvalidValue("Color", value));
+            }
+            *color = Color::from(value);
+        }
+
+        Ok(())
+    }
+}
+
 impl ScreenBuffer {
     pub fn rgb(&self) -> [u8; BUFFER_SIZE * 3] {
         let mut formatted_buffer = [0u8; BUFFER_SIZE * 3];
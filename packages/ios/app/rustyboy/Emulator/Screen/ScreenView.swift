@@ -10,14 +10,33 @@ import Foundation
 import MetalKit
 import SwiftUI
 
+/// Maps the rumble motor of the cartridge to haptic feedback
+class RumbleFeedback {
+    private let generator = UIImpactFeedbackGenerator(style: .medium)
+    private var wasRumbling = false
+
+    func update(isRumbling: Bool) {
+        if isRumbling {
+            if !wasRumbling {
+                generator.prepare()
+            }
+            generator.impactOccurred()
+        }
+        wasRumbling = isRumbling
+    }
+}
+
 struct ScreenView: UIViewRepresentable {
     typealias UIViewType = MTKView
     let gameboy: Gameboy
     private let device = MTLCreateSystemDefaultDevice()!
+    private let rumble = RumbleFeedback()
 
     func makeCoordinator() -> MTKViewDelegate {
         return Renderer(device: device, onDraw: {
-            self.gameboy.runToVblank()
+            let buffer = self.gameboy.runToVblank()
+            self.rumble.update(isRumbling: self.gameboy.isRumbling)
+            return buffer
         })!
     }
 
@@ -23,6 +23,28 @@ class RawSavestate {
     }
 }
 
+enum SavestateError: LocalizedError {
+    case invalid(String)
+
+    var errorDescription: String? {
+        switch self {
+        case .invalid(let message):
+            return "Couldn't load savestate: \(message)"
+        }
+    }
+}
+
+enum CheatError: LocalizedError {
+    case invalid(String)
+
+    var errorDescription: String? {
+        switch self {
+        case .invalid(let message):
+            return "Invalid cheat code: \(message)"
+        }
+    }
+}
+
 class Gameboy {
     var gameboyPointer: OpaquePointer
     var bufferPointer: UnsafeMutablePointer<UInt8>?
@@ -43,12 +65,20 @@ class Gameboy {
         return self.bufferPointer!
     }
 
+    var isRumbling: Bool {
+        gameboy_rumble(gameboyPointer)
+    }
+
     func sendInput(buttonType: ButtonType, eventType: ButtonEventType) {
         gameboy_send_input(self.gameboyPointer, buttonType.toCore(), eventType.toCore())
     }
 
-    func loadSavestate(buffer: [UInt8]) -> Bool {
-        gameboy_load_savestate(gameboyPointer, buffer, UInt(buffer.count))
+    func loadSavestate(buffer: [UInt8]) throws {
+        if let error = gameboy_load_savestate(gameboyPointer, buffer, UInt(buffer.count)) {
+            let message = String(cString: error)
+            string_free(error)
+            throw SavestateError.invalid(message)
+        }
     }
 
     func dumpSavestate() -> RawSavestate {
@@ -58,6 +88,26 @@ class Gameboy {
         return RawSavestate(start: pointer, count: size, screenBuffer: bufferPointer!)
     }
 
+    var cheatCount: Int {
+        Int(gameboy_cheat_count(gameboyPointer))
+    }
+
+    func addCheat(code: String) throws {
+        if let error = gameboy_add_cheat(gameboyPointer, code) {
+            let message = String(cString: error)
+            string_free(error)
+            throw CheatError.invalid(message)
+        }
+    }
+
+    func removeCheat(index: Int) -> Bool {
+        gameboy_remove_cheat(gameboyPointer, UInt(index))
+    }
+
+    func setCheatEnabled(index: Int, enabled: Bool) {
+        gameboy_set_cheat_enabled(gameboyPointer, UInt(index), enabled)
+    }
+
     func reset() {
         gameboy_reset(gameboyPointer)
     }
@@ -26,7 +26,7 @@ struct Savestates {
     static func load<D: HasGameboy>(savestate: Savestate) -> RIO<D, Void> {
         return RIO.invoke { env in
             let data = try Data(contentsOf: savestate.absolutePath(game: env.game))
-            env.gameboy.loadSavestate(buffer: [UInt8](data))
+            try env.gameboy.loadSavestate(buffer: [UInt8](data))
         }
     }
 
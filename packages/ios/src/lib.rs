@@ -1,4 +1,5 @@
-use std::os::raw::{c_uchar, c_ulong};
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_uchar, c_ulong};
 use std::slice;
 
 use rustyboy_core::cartridge::Cartridge;
@@ -73,12 +74,13 @@ pub unsafe extern "C" fn gameboy_dump_savestate(
     size
 }
 
+/// Loads a savestate. Returns null on success, or an error message to be freed with `string_free`.
 #[no_mangle]
 pub unsafe extern "C" fn gameboy_load_savestate(
     gameboy: *mut Gameboy,
     buffer: *const c_uchar,
     length: c_ulong,
-) -> bool {
+) -> *mut c_char {
     let mut gameboy = {
         assert!(!gameboy.is_null(), "Gameboy is null");
         Box::from_raw(gameboy)
@@ -90,7 +92,90 @@ pub unsafe extern "C" fn gameboy_load_savestate(
     let result = gameboy.gameboy.load_savestate(buffer.to_vec());
     Box::into_raw(gameboy);
 
-    result.is_ok()
+    match result {
+        Ok(()) => std::ptr::null_mut(),
+        Err(error) => CString::new(error.to_string()).unwrap().into_raw(),
+    }
+}
+
+/// Returns true if the rumble motor of the cartridge is on. Should be polled every frame.
+#[no_mangle]
+pub unsafe extern "C" fn gameboy_rumble(gameboy: *mut Gameboy) -> bool {
+    let gameboy = {
+        assert!(!gameboy.is_null(), "Gameboy is null");
+        Box::from_raw(gameboy)
+    };
+    let rumble = gameboy.gameboy.rumble();
+    Box::into_raw(gameboy);
+
+    rumble
+}
+
+/// Adds an enabled Game Genie, GameShark or Codebreaker code, at the index `gameboy_cheat_count() - 1`.
+/// Returns null on success, or an error message to be freed with `string_free`.
+#[no_mangle]
+pub unsafe extern "C" fn gameboy_add_cheat(
+    gameboy: *mut Gameboy,
+    code: *const c_char,
+) -> *mut c_char {
+    let mut gameboy = {
+        assert!(!gameboy.is_null(), "Gameboy is null");
+        Box::from_raw(gameboy)
+    };
+
+    assert!(!code.is_null(), "Cheat code is null");
+    let result = match CStr::from_ptr(code).to_str() {
+        Ok(code) => gameboy
+            .gameboy
+            .add_cheat(code)
+            .map_err(|error| error.to_string()),
+        Err(_) => Err(String::from("invalid characters")),
+    };
+    Box::into_raw(gameboy);
+
+    match result {
+        Ok(_) => std::ptr::null_mut(),
+        Err(error) => CString::new(error).unwrap().into_raw(),
+    }
+}
+
+/// Removes a cheat, the following ones move down an index. Returns false if there's no cheat at the index.
+#[no_mangle]
+pub unsafe extern "C" fn gameboy_remove_cheat(gameboy: *mut Gameboy, index: c_ulong) -> bool {
+    let mut gameboy = {
+        assert!(!gameboy.is_null(), "Gameboy is null");
+        Box::from_raw(gameboy)
+    };
+    let removed = gameboy.gameboy.remove_cheat(index as usize).is_some();
+    Box::into_raw(gameboy);
+
+    removed
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn gameboy_set_cheat_enabled(
+    gameboy: *mut Gameboy,
+    index: c_ulong,
+    enabled: bool,
+) {
+    let mut gameboy = {
+        assert!(!gameboy.is_null(), "Gameboy is null");
+        Box::from_raw(gameboy)
+    };
+    gameboy.gameboy.set_cheat_enabled(index as usize, enabled);
+    Box::into_raw(gameboy);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn gameboy_cheat_count(gameboy: *mut Gameboy) -> c_ulong {
+    let gameboy = {
+        assert!(!gameboy.is_null(), "Gameboy is null");
+        Box::from_raw(gameboy)
+    };
+    let count = gameboy.gameboy.cheats().len() as c_ulong;
+    Box::into_raw(gameboy);
+
+    count
 }
 
 #[no_mangle]
@@ -126,3 +211,11 @@ pub unsafe extern "C" fn vec_free(buffer: *mut c_uchar, length: c_ulong) {
     }
     Vec::from_raw_parts(buffer, length as usize, length as usize);
 }
+
+#[no_mangle]
+pub unsafe extern "C" fn string_free(string: *mut c_char) {
+    if string.is_null() {
+        return;
+    }
+    drop(CString::from_raw(string));
+}
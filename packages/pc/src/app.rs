@@ -1,17 +1,21 @@
 use clap::App;
 
+use rustyboy_core::cartridge::archive::RomFile;
+use rustyboy_core::cartridge::camera::{CameraSource, FrameSequence, StillImage};
+use rustyboy_core::cartridge::rom_database::{DumpStatus, MatchKind, RomDatabase};
 use rustyboy_core::cartridge::Cartridge;
 use rustyboy_core::config::Config;
 use rustyboy_core::debugger::Debugger;
 use rustyboy_core::gameboy::{DeviceType, Gameboy, GameboyEvent};
+use std::error::Error;
 use std::fs;
 use std::process::exit;
 
+use crate::autosave::Autosave;
 use crate::shell_debugger::ShellDebugger;
 use crate::window::background::BackgroundWindow;
 use crate::window::tile_data::TileDataWindow;
 use crate::window::{screen::MainWindow, UpdateResult, Window};
-use rustyboy_core::cartridge::cartridge_metadata::CartridgeMetadata;
 use std::path::{Path, PathBuf};
 use std::time::{Duration, Instant};
 
@@ -20,20 +24,55 @@ pub fn run() {
         .version(crate_version!())
         .about("Gameboy emulator written in Rust.")
         .args_from_usage(
-            "<rom_path> 'ROM path'
+            "<rom_path> 'ROM path, or zip or gzip file containing the ROM'
             -d, --debug 'Enable debugger'
             -i, --info 'Print cartridge metadata'
             -b, --background 'Display background contents'
             -t --tiles 'Display tile data'
-            -s --sprites 'Display sprite data'",
+            -s --sprites 'Display sprite data'
+            -c, --camera [camera_path] 'Image or directory of images seen by the Game Boy Camera'
+            -p, --patch [patch_path] 'IPS, UPS or BPS patch to apply, instead of the one next to the ROM'
+            -e, --entry [entry_name] 'File to load from a zip archive, instead of its first ROM'
+            --dat [dat_path] 'No-Intro DAT file used to identify the ROM with --info'
+            --cheat [code]... 'Game Genie, GameShark or Codebreaker code to enable, in addition to the ones of the cheats file'",
         )
         .get_matches();
 
-    let path = matches.value_of("rom_path").unwrap();
-    let cartridge = Cartridge::from_file(path).unwrap();
+    let rom_path = matches.value_of("rom_path").unwrap();
+    let rom_file =
+        RomFile::read(Path::new(rom_path), matches.value_of("entry")).unwrap_or_else(|error| {
+            println!("Couldn't load the ROM: {}", error);
+            exit(1)
+        });
+    // saves, states and patches are named after the ROM inside archives
+    let path = rom_file.path.clone();
+    let patch_path = matches
+        .value_of("patch")
+        .map(PathBuf::from)
+        .or_else(|| find_patch(&path));
+    let patches = match patch_path {
+        Some(patch_path) => {
+            println!("Applying patch {}", patch_path.display());
+            vec![fs::read(patch_path).unwrap_or_else(|error| {
+                println!("Couldn't read the patch: {}", error);
+                exit(1)
+            })]
+        }
+        None => Vec::new(),
+    };
+    let cartridge = Cartridge::from_rom_file(rom_file, &patches).unwrap_or_else(|error| {
+        println!("Couldn't load the ROM: {}", error);
+        exit(1)
+    });
 
     if matches.is_present("info") {
-        print_cartridge_info(cartridge.metadata());
+        let database = matches.value_of("dat").map(|dat_path| {
+            RomDatabase::from_file(Path::new(dat_path)).unwrap_or_else(|error| {
+                println!("Couldn't load the ROM database: {}", error);
+                exit(1)
+            })
+        });
+        print_cartridge_info(&cartridge, database.as_ref());
         exit(0);
     }
 
@@ -49,41 +88,171 @@ pub fn run() {
     let config = Config {
         device_type: DeviceType::GameBoy,
         debugger,
+        ..Config::default()
     };
 
     let options = RunOptions {
         show_background: matches.is_present("background"),
         show_tile_data: matches.is_present("tiles"),
         show_sprite_data: matches.is_present("sprites"),
-        path: PathBuf::from(path),
+        path,
     };
 
     let mut gameboy = Gameboy::new(cartridge, &config);
-    let savestate_path = Path::new(path).with_extension("state");
+    if let Some(camera_path) = matches.value_of("camera") {
+        match load_camera_source(Path::new(camera_path)) {
+            Ok(source) => gameboy.set_camera_source(source),
+            Err(error) => println!("Couldn't load camera images: {}", error),
+        }
+    }
+
+    let cheats_path = options.path.with_extension("cht");
+    let cheats_file = fs::read_to_string(cheats_path).unwrap_or_default();
+    let mut cheats = read_cheats_file(&cheats_file);
+    cheats.extend(matches.values_of("cheat").into_iter().flatten());
+    for code in cheats {
+        if let Err(error) = gameboy.add_cheat(code) {
+            println!("Couldn't add cheat {}: {}", code, error);
+        }
+    }
+
+    let savestate_path = options.path.with_extension("state");
     if let Ok(buffer) = fs::read(savestate_path) {
         gameboy
             .load_savestate(buffer)
-            .unwrap_or_else(|_| println!("Couldn't load savestate. Maybe it is corrupted?"))
+            .unwrap_or_else(|error| println!("Couldn't load savestate: {}", error))
     }
 
     start_emulation(gameboy, config, options);
 }
 
-fn print_cartridge_info(metadata: &CartridgeMetadata) {
+// patches with the name of the ROM are applied automatically
+fn find_patch(rom_path: &Path) -> Option<PathBuf> {
+    ["ips", "ups", "bps"]
+        .iter()
+        .map(|extension| rom_path.with_extension(extension))
+        .find(|path| path.is_file())
+}
+
+// cheats files have a code per line, followed by an optional description
+fn read_cheats_file(file: &str) -> Vec<&str> {
+    file.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.split_whitespace().next())
+        .collect()
+}
+
+// a directory gives a sequence of frames, one per picture taken
+fn load_camera_source(path: &Path) -> Result<Box<dyn CameraSource>, Box<dyn Error>> {
+    if path.is_dir() {
+        Ok(Box::new(FrameSequence::from_directory(path)?))
+    } else {
+        Ok(Box::new(StillImage::from_file(path)?))
+    }
+}
+
+fn print_cartridge_info(cartridge: &Cartridge, database: Option<&RomDatabase>) {
+    let metadata = cartridge.metadata();
     println!("Cartridge metadata");
     println!("{}", "-".repeat(20));
 
     println!("Title: {}", metadata.title);
-    println!("Region: {:?}", metadata.destination);
+    println!("Region: {}", metadata.destination.name());
     println!("Version: {}", metadata.version);
     println!("GameBoy Color support: {:?}", metadata.cgb_flag);
     println!("Super GameBoy enhancements: {:?}", metadata.sgb_enhanced);
     println!("Manufacturer code: {:?}", metadata.manufacturer_code);
+    println!("Publisher: {}", metadata.publisher().unwrap_or("Unknown"));
     println!("New licencee code: {:?}", metadata.new_licensee_code);
     println!("Old licencee code: {:?}", metadata.old_licensee_code);
-    println!("Cartridge capabilities: {:?}", metadata.capabilities);
-    println!("ROM size: {:?}", metadata.rom_size);
-    println!("RAM size: {:?}", metadata.ram_size);
+    println!("Cartridge type: {}", metadata.cartridge_type_name());
+    println!("ROM size: {}", metadata.rom_size_name());
+    println!("RAM size: {}", metadata.ram_size_name());
+
+    let validation = cartridge.header_validation();
+    println!();
+    println!("Header checks");
+    println!("{}", "-".repeat(20));
+    println!(
+        "Header checksum: {:#04X} ({})",
+        metadata.header_checksum,
+        check_result(
+            validation.header_checksum_valid,
+            format!("{:#04X}", validation.computed_header_checksum)
+        )
+    );
+    println!(
+        "Global checksum: {:#06X} ({})",
+        metadata.global_checksum,
+        check_result(
+            validation.global_checksum_valid,
+            format!("{:#06X}", validation.computed_global_checksum)
+        )
+    );
+    println!(
+        "Nintendo logo: {}",
+        if validation.logo_valid {
+            "valid"
+        } else {
+            "invalid"
+        }
+    );
+    println!(
+        "File size: {} ({})",
+        validation.actual_rom_size,
+        if validation.rom_size_valid {
+            "matches the header"
+        } else {
+            "doesn't match the header"
+        }
+    );
+
+    if let Some(database) = database {
+        println!();
+        print_database_match(cartridge, database);
+    }
+}
+
+fn print_database_match(cartridge: &Cartridge, database: &RomDatabase) {
+    println!("Database");
+    println!("{}", "-".repeat(20));
+
+    let found = match cartridge.identify(database) {
+        Some(found) => found,
+        None => {
+            println!("Unknown ROM, it isn't in the database");
+            return;
+        }
+    };
+    let entry = found.entry;
+    println!("Name: {}", entry.name);
+    println!("Title: {}", entry.title());
+    println!("Region: {}", entry.region().unwrap_or("Unknown"));
+    println!("Revision: {}", entry.revision().unwrap_or("None"));
+    println!("Status: {}", entry.status.name());
+
+    match found.kind {
+        MatchKind::Exact => {}
+        MatchKind::ChecksumMismatch => {
+            println!("Warning: the CRC32 matches but the SHA-1 doesn't, the ROM may be corrupted")
+        }
+        MatchKind::Overdump => println!(
+            "Warning: the ROM is overdumped, it has extra data after the {} bytes of the game",
+            entry.size
+        ),
+    }
+    if entry.status == DumpStatus::BadDump {
+        println!("Warning: the database lists this ROM as a bad dump");
+    }
+}
+
+fn check_result(valid: bool, computed: String) -> String {
+    if valid {
+        String::from("valid")
+    } else {
+        format!("invalid, computed {}", computed)
+    }
 }
 
 struct RunOptions {
@@ -97,6 +266,7 @@ fn start_emulation(mut gameboy: Gameboy, config: Config, options: RunOptions) {
     let mut windows = create_windows(&options);
     let mut debugger = config.debugger;
     let mut shell_debugger = ShellDebugger::default();
+    let mut autosave = Autosave::new(options.path.with_extension("sav"));
 
     let mut last_time = Instant::now();
     let update_rate = Duration::from_millis(1000 / 60);
@@ -108,32 +278,42 @@ fn start_emulation(mut gameboy: Gameboy, config: Config, options: RunOptions) {
         last_time = Instant::now();
 
         if let GameboyEvent::Debugger(debug_info) = gameboy.run_to_event(debugger.as_mut()) {
-            shell_debugger.run(debugger.as_mut().unwrap(), debug_info.as_ref())
+            shell_debugger.run(debugger.as_mut().unwrap(), debug_info.as_ref(), &gameboy)
         }
 
-        if let UpdateResult::Close = update_windows(&mut gameboy, &mut windows) {
-            if let Some(ram) = &gameboy.hardware().cartridge.ram {
-                fs::write(options.path.with_extension("sav"), ram)
+        autosave.update(&mut gameboy);
+
+        match update_windows(&mut gameboy, &mut windows) {
+            UpdateResult::Close => {
+                autosave
+                    .save(&gameboy)
                     .expect("Could not save cartridge RAM; game progress might have been lost");
-            }
 
-            let savestate = &gameboy.dump_savestate();
-            fs::write(options.path.with_extension("state"), savestate)
-                .expect("Could not create a savestate.");
+                let savestate = &gameboy.dump_savestate();
+                fs::write(options.path.with_extension("state"), savestate)
+                    .expect("Could not create a savestate.");
 
-            break;
+                break;
+            }
+            UpdateResult::Break => {
+                debugger.get_or_insert_with(Debugger::default).forced_break = true;
+            }
+            UpdateResult::Continue => {}
         }
     }
 }
 
 fn update_windows(gameboy: &mut Gameboy, windows: &mut Vec<Box<dyn Window>>) -> UpdateResult {
+    let mut result = UpdateResult::Continue;
     for window in windows.iter_mut() {
-        if let UpdateResult::Close = window.update(gameboy) {
-            return UpdateResult::Close;
+        match window.update(gameboy) {
+            UpdateResult::Close => return UpdateResult::Close,
+            UpdateResult::Break => result = UpdateResult::Break,
+            UpdateResult::Continue => {}
         }
     }
 
-    UpdateResult::Continue
+    result
 }
 
 fn create_windows(options: &RunOptions) -> Vec<Box<dyn Window>> {
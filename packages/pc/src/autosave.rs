@@ -0,0 +1,185 @@
+use rustyboy_core::gameboy::Gameboy;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Time without RAM writes to wait before saving, so a game saving its progress
+/// over many frames is written to disk only once
+const QUIET_DELAY: Duration = Duration::from_secs(1);
+/// Maximum time to wait before saving a game that keeps writing to its RAM
+const MAX_DELAY: Duration = Duration::from_secs(10);
+/// Number of previous save files kept next to the current one
+const BACKUP_COUNT: usize = 2;
+
+/// Periodically writes the battery save of the cartridge to disk when the game changes it.
+/// The save files of previous sessions are kept as backups.
+pub struct Autosave {
+    path: PathBuf,
+    debounce: Debounce,
+    backed_up: bool,
+}
+
+impl Autosave {
+    pub fn new(path: PathBuf) -> Autosave {
+        Autosave {
+            path,
+            debounce: Debounce::default(),
+            backed_up: false,
+        }
+    }
+
+    /// Checks for changes to the cartridge RAM, saving it once it settles down.
+    /// Should be called every frame.
+    pub fn update(&mut self, gameboy: &mut Gameboy) {
+        let now = Instant::now();
+        if gameboy.is_ram_dirty() {
+            gameboy.mark_ram_flushed();
+            self.debounce.change(now);
+        }
+
+        if self.debounce.is_due(now) {
+            if let Err(error) = self.save(gameboy) {
+                println!("Couldn't autosave cartridge RAM: {}", error);
+                // try again later instead of every frame
+                self.debounce.postpone(now);
+            }
+        }
+    }
+
+    /// Writes the battery save to disk right away
+    pub fn save(&mut self, gameboy: &Gameboy) -> io::Result<()> {
+        if let Some(save) = gameboy.hardware().cartridge.dump_battery_save() {
+            // only the first save of the session rotates the backups, otherwise
+            // a game writing to its RAM all the time would replace them in seconds
+            write_save(&self.path, &save, !self.backed_up)?;
+            self.backed_up = true;
+        }
+
+        self.debounce = Debounce::default();
+        Ok(())
+    }
+}
+
+/// Waits for the changes to stop for `QUIET_DELAY`, or for `MAX_DELAY` after the first one
+#[derive(Default)]
+struct Debounce {
+    pending_since: Option<Instant>,
+    last_change: Option<Instant>,
+}
+
+impl Debounce {
+    fn change(&mut self, now: Instant) {
+        self.last_change = Some(now);
+        self.pending_since.get_or_insert(now);
+    }
+
+    /// Waits for a whole `QUIET_DELAY` again
+    fn postpone(&mut self, now: Instant) {
+        self.pending_since = Some(now);
+        self.last_change = Some(now);
+    }
+
+    fn is_due(&self, now: Instant) -> bool {
+        match (self.pending_since, self.last_change) {
+            (Some(pending_since), Some(last_change)) => {
+                now - last_change >= QUIET_DELAY || now - pending_since >= MAX_DELAY
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Writes the file atomically: the content goes to a temporary file which then replaces
+/// the previous one, so a crash can't leave a partially written file behind.
+/// With `backup`, the previous files are kept as `<name>.1` to `<name>.BACKUP_COUNT`,
+/// newest first.
+fn write_save(path: &Path, content: &[u8], backup: bool) -> io::Result<()> {
+    let temporary_path = with_suffix(path, "tmp");
+    let mut file = File::create(&temporary_path)?;
+    file.write_all(content)?;
+    file.sync_all()?;
+
+    if backup && path.exists() {
+        for i in (1..BACKUP_COUNT).rev() {
+            let backup = with_suffix(path, &i.to_string());
+            if backup.exists() {
+                fs::rename(&backup, with_suffix(path, &(i + 1).to_string()))?;
+            }
+        }
+        fs::copy(path, with_suffix(path, "1"))?;
+    }
+
+    fs::rename(temporary_path, path)
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(".");
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn debounce_waits_for_changes_to_stop() {
+        let start = Instant::now();
+        let mut debounce = Debounce::default();
+        assert!(!debounce.is_due(start + MAX_DELAY));
+
+        debounce.change(start);
+        assert!(!debounce.is_due(start + QUIET_DELAY / 2));
+        debounce.change(start + QUIET_DELAY / 2);
+        assert!(!debounce.is_due(start + QUIET_DELAY));
+        assert!(debounce.is_due(start + QUIET_DELAY / 2 + QUIET_DELAY));
+    }
+
+    #[test]
+    fn debounce_saves_changes_that_never_stop() {
+        let start = Instant::now();
+        let mut debounce = Debounce::default();
+        let step = QUIET_DELAY / 2;
+        let mut now = start;
+        while now - start < MAX_DELAY {
+            debounce.change(now);
+            assert!(!debounce.is_due(now));
+            now += step;
+        }
+        assert!(debounce.is_due(now));
+
+        debounce.postpone(now);
+        assert!(!debounce.is_due(now + step));
+        assert!(debounce.is_due(now + QUIET_DELAY));
+    }
+
+    #[test]
+    fn backups_are_rotated_only_when_asked() {
+        let directory = std::env::temp_dir().join("rustyboy_autosave_backups");
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("game.sav");
+        let read = |suffix: &str| fs::read(with_suffix(&path, suffix)).ok();
+
+        write_save(&path, &[1], true).unwrap();
+        assert_eq!(read("1"), None);
+        write_save(&path, &[2], true).unwrap();
+        write_save(&path, &[3], true).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), [3]);
+        assert_eq!(read("1"), Some(vec![2]));
+        assert_eq!(read("2"), Some(vec![1]));
+
+        for value in 4..10 {
+            write_save(&path, &[value], false).unwrap();
+        }
+        assert_eq!(fs::read(&path).unwrap(), [9]);
+        assert_eq!(read("1"), Some(vec![2]));
+        assert_eq!(read("2"), Some(vec![1]));
+        assert_eq!(read("3"), None);
+        assert_eq!(read("tmp"), None);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
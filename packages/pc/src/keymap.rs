@@ -24,3 +24,32 @@ pub fn keymap(input: KeyboardInput) -> Option<Input> {
 
     Some(Input { input_type, button })
 }
+
+pub enum TiltAxis {
+    X,
+    Y,
+}
+
+/// Maps keys to the accelerometer of the cartridge, for games like Kirby Tilt 'n' Tumble.
+/// Returns the axis and its new value, which goes back to 0 when the key is released.
+pub fn tilt_keymap(input: KeyboardInput) -> Option<(TiltAxis, f32)> {
+    let key_code = input.virtual_keycode?;
+    let (axis, value) = match key_code {
+        VirtualKeyCode::I => (TiltAxis::Y, -1.0),
+        VirtualKeyCode::K => (TiltAxis::Y, 1.0),
+        VirtualKeyCode::J => (TiltAxis::X, -1.0),
+        VirtualKeyCode::L => (TiltAxis::X, 1.0),
+        _ => return None,
+    };
+
+    if input.state == ElementState::Pressed {
+        Some((axis, value))
+    } else {
+        Some((axis, 0.0))
+    }
+}
+
+/// F12 breaks into the debugger, for instance to continue a RAM search
+pub fn is_debugger_key(input: KeyboardInput) -> bool {
+    input.virtual_keycode == Some(VirtualKeyCode::F12) && input.state == ElementState::Pressed
+}
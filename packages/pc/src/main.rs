@@ -2,6 +2,7 @@
 extern crate clap;
 
 mod app;
+mod autosave;
 mod keymap;
 mod shell_debugger;
 mod util;
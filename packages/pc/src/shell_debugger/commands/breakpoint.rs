@@ -4,6 +4,7 @@ use rustyboy_core::debugger::breakpoint::{Breakpoint, BreakpointCondition};
 use rustyboy_core::debugger::commands::breakpoint::BreakpointAction;
 use rustyboy_core::debugger::debug_info::DebugInfo;
 use rustyboy_core::debugger::DebuggerAction;
+use rustyboy_core::gameboy::Gameboy;
 use rustyboy_core::util::parse_hex::parse_hex;
 
 const MATCHING_VALUES: &[&str] = &["breakpoint", "b"];
@@ -81,7 +82,13 @@ impl Command for BreakpointCommand {
         MATCHING_VALUES
     }
 
-    fn execute(&self, input: &[&str], debugger: &mut Debugger, _: &DebugInfo) -> CommandResult {
+    fn execute(
+        &self,
+        input: &[&str],
+        debugger: &mut Debugger,
+        _: &DebugInfo,
+        _: &Gameboy,
+    ) -> CommandResult {
         if let Some(action) = BreakpointCommandAction::parse(&input[1..]) {
             match action {
                 BreakpointCommandAction::BreakpointAction(action) => {
@@ -1,6 +1,7 @@
 use super::{Command, CommandResult, Debugger};
 use rustyboy_core::debugger::debug_info::DebugInfo;
 use rustyboy_core::debugger::DebuggerAction;
+use rustyboy_core::gameboy::Gameboy;
 
 const MATCHING_VALUES: &[&str] = &["continue", "c"];
 
@@ -17,7 +18,13 @@ impl Command for ContinueCommand {
         MATCHING_VALUES
     }
 
-    fn execute(&self, _: &[&str], debugger: &mut Debugger, _: &DebugInfo) -> CommandResult {
+    fn execute(
+        &self,
+        _: &[&str],
+        debugger: &mut Debugger,
+        _: &DebugInfo,
+        _: &Gameboy,
+    ) -> CommandResult {
         CommandResult::from(debugger.run_action(DebuggerAction::Continue))
     }
 }
@@ -1,10 +1,12 @@
 use super::Debugger;
 use rustyboy_core::debugger::debug_info::DebugInfo;
 use rustyboy_core::debugger::DebuggerActionResult;
+use rustyboy_core::gameboy::Gameboy;
 
 pub mod breakpoint;
 pub mod continue_cmd;
 pub mod quit;
+pub mod search;
 pub mod status;
 pub mod step_into;
 
@@ -29,6 +31,7 @@ pub trait Command {
         input: &[&str],
         debugger: &mut Debugger,
         debug_info: &DebugInfo,
+        gameboy: &Gameboy,
     ) -> CommandResult;
 
     fn help(&self) -> String {
@@ -1,5 +1,6 @@
 use super::{Command, CommandResult, Debugger};
 use rustyboy_core::debugger::debug_info::DebugInfo;
+use rustyboy_core::gameboy::Gameboy;
 
 const MATCHING_VALUES: &[&str] = &["quit", "q"];
 
@@ -16,7 +17,7 @@ impl Command for QuitCommand {
         MATCHING_VALUES
     }
 
-    fn execute(&self, _: &[&str], _: &mut Debugger, _: &DebugInfo) -> CommandResult {
+    fn execute(&self, _: &[&str], _: &mut Debugger, _: &DebugInfo, _: &Gameboy) -> CommandResult {
         std::process::exit(0);
     }
 }
@@ -0,0 +1,189 @@
+use super::{Command, CommandResult, Debugger};
+use rustyboy_core::cheats::search::{RamSearch, SearchFilter, ValueSize};
+use rustyboy_core::debugger::debug_info::DebugInfo;
+use rustyboy_core::gameboy::Gameboy;
+use std::cell::RefCell;
+
+const MATCHING_VALUES: &[&str] = &["search", "se"];
+const DEFAULT_LIST_COUNT: usize = 20;
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum SearchCommandAction {
+    Start(ValueSize, bool),
+    Filter(SearchFilter),
+    List(usize),
+    Cheat(usize, i32),
+}
+
+impl SearchCommandAction {
+    pub fn parse(values: &[&str]) -> Option<SearchCommandAction> {
+        let action = *values.get(0)?;
+        match action {
+            "start" | "s" => {
+                let size = match values.get(1).copied().unwrap_or("8") {
+                    "8" => ValueSize::Byte,
+                    "16" => ValueSize::Word,
+                    _ => return None,
+                };
+                let signed = match values.get(2).copied().unwrap_or("u") {
+                    "u" | "unsigned" => false,
+                    "s" | "signed" => true,
+                    _ => return None,
+                };
+                Some(SearchCommandAction::Start(size, signed))
+            }
+            "changed" | "c" => Some(SearchCommandAction::Filter(SearchFilter::Changed)),
+            "unchanged" | "u" => Some(SearchCommandAction::Filter(SearchFilter::Unchanged)),
+            "increased" | "i" => Some(SearchCommandAction::Filter(SearchFilter::Increased)),
+            "decreased" | "d" => Some(SearchCommandAction::Filter(SearchFilter::Decreased)),
+            "equal" | "=" => {
+                let value = parse_value(values.get(1)?)?;
+                Some(SearchCommandAction::Filter(SearchFilter::EqualTo(value)))
+            }
+            "list" | "l" => {
+                let count = match values.get(1) {
+                    Some(count) => count.parse().ok()?,
+                    None => DEFAULT_LIST_COUNT,
+                };
+                Some(SearchCommandAction::List(count))
+            }
+            "cheat" => {
+                let index = values.get(1)?.parse().ok()?;
+                let value = parse_value(values.get(2)?)?;
+                Some(SearchCommandAction::Cheat(index, value))
+            }
+            _ => None,
+        }
+    }
+}
+
+// values are decimal, or hexadecimal with a 0x prefix
+fn parse_value(value: &str) -> Option<i32> {
+    match value.strip_prefix("0x") {
+        Some(hex) => i32::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    }
+}
+
+pub struct SearchCommand {
+    search: RefCell<Option<RamSearch>>,
+}
+
+impl SearchCommand {
+    pub fn create_command() -> Box<dyn Command> {
+        Box::new(SearchCommand {
+            search: RefCell::new(None),
+        })
+    }
+}
+
+impl Command for SearchCommand {
+    fn matching_value(&self) -> &[&str] {
+        MATCHING_VALUES
+    }
+
+    fn execute(
+        &self,
+        input: &[&str],
+        _: &mut Debugger,
+        _: &DebugInfo,
+        gameboy: &Gameboy,
+    ) -> CommandResult {
+        let action = match SearchCommandAction::parse(&input[1..]) {
+            Some(action) => action,
+            None => {
+                println!(
+                    "Invalid input for search (start [8|16] [u|s] | changed | unchanged | increased | decreased | equal [value] | list [count] | cheat [index] [value])"
+                );
+                return CommandResult::Continue;
+            }
+        };
+
+        if let SearchCommandAction::Start(size, signed) = action {
+            let search = RamSearch::new(gameboy.memory_snapshot(), size, signed);
+            println!("{} candidates", search.len());
+            self.search.replace(Some(search));
+            return CommandResult::Continue;
+        }
+
+        match (action, self.search.borrow_mut().as_mut()) {
+            (SearchCommandAction::Start(..), _) => {}
+            (SearchCommandAction::Filter(filter), Some(search)) => {
+                search.filter(gameboy.memory_snapshot(), filter);
+                println!("{} candidates", search.len());
+            }
+            (SearchCommandAction::List(count), Some(search)) => {
+                for (index, result) in search.results().take(count).enumerate() {
+                    match result.ram_bank {
+                        Some(bank) => println!(
+                            "{}: 0x{:04X} (bank {}) = {}",
+                            index, result.address, bank, result.value
+                        ),
+                        None => println!("{}: 0x{:04X} = {}", index, result.address, result.value),
+                    }
+                }
+                if search.len() > count {
+                    println!("... {} more", search.len() - count);
+                }
+            }
+            (SearchCommandAction::Cheat(index, value), Some(search)) => {
+                match search.results().nth(index) {
+                    Some(result) if !result.size.fits(value) => {
+                        println!("{} doesn't fit in the size of the search", value)
+                    }
+                    Some(result) => match result.game_shark_codes(value) {
+                        Some(codes) => {
+                            for code in codes {
+                                println!("{}", code);
+                            }
+                        }
+                        None => println!("GameShark codes can't write to this RAM bank"),
+                    },
+                    None => println!("No candidate {}", index),
+                }
+            }
+            (_, None) => println!("No search started (search start [8|16] [u|s])"),
+        }
+
+        CommandResult::Continue
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::shell_debugger::commands::search::SearchCommandAction;
+    use rustyboy_core::cheats::search::{SearchFilter, ValueSize};
+
+    #[test]
+    fn parses_start_correctly() {
+        assert_eq!(
+            SearchCommandAction::parse(&["start"]),
+            Some(SearchCommandAction::Start(ValueSize::Byte, false))
+        );
+        assert_eq!(
+            SearchCommandAction::parse(&["s", "16", "s"]),
+            Some(SearchCommandAction::Start(ValueSize::Word, true))
+        );
+        assert_eq!(SearchCommandAction::parse(&["s", "32"]), None);
+    }
+
+    #[test]
+    fn parses_filters_correctly() {
+        assert_eq!(
+            SearchCommandAction::parse(&["d"]),
+            Some(SearchCommandAction::Filter(SearchFilter::Decreased))
+        );
+        assert_eq!(
+            SearchCommandAction::parse(&["=", "0x1F"]),
+            Some(SearchCommandAction::Filter(SearchFilter::EqualTo(0x1F)))
+        );
+        assert_eq!(
+            SearchCommandAction::parse(&["equal", "-3"]),
+            Some(SearchCommandAction::Filter(SearchFilter::EqualTo(-3)))
+        );
+        assert_eq!(
+            SearchCommandAction::parse(&["cheat", "2", "99"]),
+            Some(SearchCommandAction::Cheat(2, 99))
+        );
+    }
+}
@@ -2,6 +2,7 @@ use super::{Command, CommandResult, Debugger};
 use crate::shell_debugger::pretty_print::format_registers;
 use crate::util::parse_register;
 use rustyboy_core::debugger::debug_info::DebugInfo;
+use rustyboy_core::gameboy::Gameboy;
 use rustyboy_core::processor::registers::RegisterType;
 use rustyboy_core::util::parse_hex::parse_hex;
 
@@ -47,7 +48,13 @@ impl Command for StatusCommand {
         MATCHING_VALUES
     }
 
-    fn execute(&self, input: &[&str], _: &mut Debugger, debug_info: &DebugInfo) -> CommandResult {
+    fn execute(
+        &self,
+        input: &[&str],
+        _: &mut Debugger,
+        debug_info: &DebugInfo,
+        _: &Gameboy,
+    ) -> CommandResult {
         if let Some(status_type) = StatusType::parse(&input[1..]) {
             match status_type {
                 StatusType::Address(address) => {
@@ -1,10 +1,12 @@
 use rustyboy_core::debugger::{debug_info::DebugInfo, Debugger};
+use rustyboy_core::gameboy::Gameboy;
 
 use self::pretty_print::format_debug_info;
 use self::shell::Shell;
 use crate::shell_debugger::commands::breakpoint::BreakpointCommand;
 use crate::shell_debugger::commands::continue_cmd::ContinueCommand;
 use crate::shell_debugger::commands::quit::QuitCommand;
+use crate::shell_debugger::commands::search::SearchCommand;
 use crate::shell_debugger::commands::status::StatusCommand;
 use crate::shell_debugger::commands::step_into::StepIntoCommand;
 use crate::shell_debugger::commands::{Command, CommandResult};
@@ -27,6 +29,7 @@ impl Default for ShellDebugger {
                 BreakpointCommand::create_command(),
                 ContinueCommand::create_command(),
                 StatusCommand::create_command(),
+                SearchCommand::create_command(),
                 StepIntoCommand::create_command(),
                 QuitCommand::create_command(),
             ],
@@ -54,13 +57,14 @@ impl ShellDebugger {
         input: &str,
         debugger: &mut Debugger,
         debug_info: &DebugInfo,
+        gameboy: &Gameboy,
     ) -> Option<CommandResult> {
         let separated: Vec<&str> = input.split(' ').map(|x| x.trim()).collect();
         let command = matching_command(&self.commands, separated[0].to_string())?;
-        Some(command.execute(&separated, debugger, debug_info))
+        Some(command.execute(&separated, debugger, debug_info, gameboy))
     }
 
-    pub fn run(&mut self, debugger: &mut Debugger, debug_info: &DebugInfo) {
+    pub fn run(&mut self, debugger: &mut Debugger, debug_info: &DebugInfo, gameboy: &Gameboy) {
         if debugger.forced_break {
             debugger.forced_break = false;
         } else {
@@ -71,7 +75,7 @@ impl ShellDebugger {
 
         loop {
             let input = self.shell.read_input();
-            if let Some(result) = self.parse(&input, debugger, &debug_info) {
+            if let Some(result) = self.parse(&input, debugger, &debug_info, gameboy) {
                 match result {
                     CommandResult::Continue => {}
                     CommandResult::Quit => {
@@ -30,4 +30,5 @@ pub fn create_display(
 pub enum UpdateResult {
     Continue,
     Close,
+    Break,
 }
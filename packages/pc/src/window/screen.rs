@@ -7,12 +7,16 @@ use rustyboy_core::gameboy::Gameboy;
 use rustyboy_core::video::screen::SCREEN_SIZE;
 
 use super::{create_display, Window};
-use crate::keymap::keymap;
+use crate::keymap::{is_debugger_key, keymap, tilt_keymap, TiltAxis};
 use crate::window::UpdateResult;
 
+const TITLE: &str = "Rustyboy";
+
 pub struct MainWindow {
     display: Display,
     events_loop: EventsLoop,
+    tilt: (f32, f32),
+    rumble: bool,
 }
 
 impl MainWindow {
@@ -20,8 +24,24 @@ impl MainWindow {
         let events_loop = EventsLoop::new();
 
         MainWindow {
-            display: create_display("Rustyboy", SCREEN_SIZE, &events_loop),
+            display: create_display(TITLE, SCREEN_SIZE, &events_loop),
             events_loop,
+            tilt: (0.0, 0.0),
+            rumble: false,
+        }
+    }
+
+    // games turn the motor on and off quickly to change its strength,
+    // so it's only shown in the title instead of being logged
+    fn update_rumble(&mut self, rumble: bool) {
+        if rumble != self.rumble {
+            self.rumble = rumble;
+            let title = if rumble {
+                format!("{} (rumble)", TITLE)
+            } else {
+                TITLE.to_string()
+            };
+            self.display.gl_window().window().set_title(&title);
         }
     }
 }
@@ -39,8 +59,11 @@ impl Window for MainWindow {
             .fill(&target, MagnifySamplerFilter::Nearest);
 
         target.finish().unwrap();
+        self.update_rumble(gameboy.rumble());
 
         let mut close = false;
+        let mut debug = false;
+        let tilt = &mut self.tilt;
         self.events_loop.poll_events(|event| match event {
             Event::WindowEvent {
                 event: WindowEvent::CloseRequested,
@@ -52,6 +75,15 @@ impl Window for MainWindow {
                 event: WindowEvent::KeyboardInput { input, .. },
                 ..
             } => {
+                debug |= is_debugger_key(input);
+                if let Some((axis, value)) = tilt_keymap(input) {
+                    match axis {
+                        TiltAxis::X => tilt.0 = value,
+                        TiltAxis::Y => tilt.1 = value,
+                    }
+                    gameboy.set_tilt(tilt.0, tilt.1);
+                }
+
                 let input = keymap(input);
                 if let Some(input) = input {
                     gameboy.send_input(input);
@@ -62,6 +94,8 @@ impl Window for MainWindow {
 
         if close {
             UpdateResult::Close
+        } else if debug {
+            UpdateResult::Break
         } else {
             UpdateResult::Continue
         }
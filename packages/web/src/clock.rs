@@ -0,0 +1,11 @@
+use js_sys::Date;
+use rustyboy_core::util::clock::Clock;
+
+/// Clock using the time of the browser, since `SystemTime` isn't available in WebAssembly
+pub struct DateClock;
+
+impl Clock for DateClock {
+    fn now(&self) -> u64 {
+        (Date::now() / 1000.0) as u64
+    }
+}
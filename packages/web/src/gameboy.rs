@@ -68,4 +68,56 @@ impl GameboyJs {
     pub fn reset(&mut self) {
         self.gameboy.reset()
     }
+
+    /// Adds an enabled Game Genie, GameShark or Codebreaker code, returns its index
+    #[wasm_bindgen(js_name = addCheat)]
+    pub fn add_cheat(&mut self, code: &str) -> Result<usize, JsValue> {
+        self.gameboy
+            .add_cheat(code)
+            .map_err(|error| JsValue::from_str(&error.to_string()))
+    }
+
+    #[wasm_bindgen(js_name = removeCheat)]
+    pub fn remove_cheat(&mut self, index: usize) -> bool {
+        self.gameboy.remove_cheat(index).is_some()
+    }
+
+    #[wasm_bindgen(js_name = setCheatEnabled)]
+    pub fn set_cheat_enabled(&mut self, index: usize, enabled: bool) {
+        self.gameboy.set_cheat_enabled(index, enabled);
+    }
+
+    #[wasm_bindgen(js_name = cheatCount)]
+    pub fn cheat_count(&self) -> usize {
+        self.gameboy.cheats().len()
+    }
+
+    #[wasm_bindgen(js_name = cheatCode)]
+    pub fn cheat_code(&self, index: usize) -> Option<String> {
+        self.gameboy
+            .cheats()
+            .get(index)
+            .map(|cheat| cheat.code.clone())
+    }
+
+    #[wasm_bindgen(js_name = isCheatEnabled)]
+    pub fn is_cheat_enabled(&self, index: usize) -> bool {
+        match self.gameboy.cheats().get(index) {
+            Some(cheat) => cheat.enabled,
+            None => false,
+        }
+    }
+
+    #[wasm_bindgen(js_name = dumpSavestate)]
+    pub fn dump_savestate(&self) -> Vec<u8> {
+        self.gameboy.dump_savestate()
+    }
+
+    #[wasm_bindgen(js_name = loadSavestate)]
+    pub fn load_savestate(&mut self, buffer: Vec<u8>) -> Result<(), JsValue> {
+        self.gameboy
+            .load_savestate(buffer)
+            .map_err(|error| JsValue::from_str(&error.to_string()))?;
+        self.draw()
+    }
 }
@@ -1,10 +1,13 @@
 use wasm_bindgen::prelude::*;
 
+use crate::clock::DateClock;
 use crate::gameboy::GameboyJs;
 use rustyboy_core::cartridge::Cartridge;
 use rustyboy_core::config::Config;
 use rustyboy_core::gameboy::{DeviceType, Gameboy};
+use std::rc::Rc;
 
+pub mod clock;
 pub mod debugger;
 pub mod gameboy;
 pub mod input;
@@ -16,6 +19,8 @@ pub fn setup(buffer: Vec<u8>) -> GameboyJs {
     let config = Config {
         device_type: DeviceType::GameBoy,
         debugger: None,
+        clock: Rc::new(DateClock),
+        mbc_variant: None,
     };
 
     GameboyJs {